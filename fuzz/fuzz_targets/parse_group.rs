@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use yfass::user::Group;
+
+fuzz_target!(|s: &str| {
+    if let Ok(group) = s.parse::<Group>() {
+        assert_eq!(group.to_string().parse::<Group>().ok(), Some(group));
+    }
+});
@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use yfass::func::OwnedKey;
+
+fuzz_target!(|s: &str| {
+    if let Ok(key) = s.parse::<OwnedKey>() {
+        assert!(!key.name.is_empty() && !key.version.is_empty());
+        assert_eq!(key.to_string().parse::<OwnedKey>().ok(), Some(key));
+    }
+});
@@ -1,23 +1,33 @@
 //! Function abstractions.
 
 use std::{
+    collections::{BTreeSet, HashMap},
     fmt::Display,
     hash::Hash,
     net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    num::{NonZeroU32, NonZeroUsize},
     path::{Path, PathBuf},
     str::FromStr,
     sync::{
         Arc,
         atomic::{self, AtomicBool},
     },
+    time::Duration,
 };
 
+use futures_util::StreamExt as _;
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
+use time::UtcDateTime;
 use tokio::{io::AsyncRead, task::JoinSet};
 use tokio_tar::Archive as Tar;
 
-use crate::{NonExhaustiveMarker, dnem, sandbox::SandboxConfig, user};
+use crate::{
+    NonExhaustiveMarker, dnem,
+    sandbox::{BuildCommand, SandboxConfig, SandboxPatch},
+    storage::{FsStorage, Storage},
+    user,
+};
 
 /// Information of a function for FASS platform to host and perform.
 #[derive(Debug, Clone, Serialize)]
@@ -30,6 +40,72 @@ pub struct Function {
 
 type FunctionCell = Arc<RwLock<Function>>;
 
+impl Function {
+    /// Takes an owned snapshot of the fields required for spawning this function.
+    #[inline]
+    pub fn snapshot(&self) -> FunctionSnapshot {
+        FunctionSnapshot {
+            addr: self.config.addr,
+            sandbox: self.config.sandbox.clone(),
+            max_ws_connections: self.config.max_ws_connections,
+            upstream_no_keepalive: self.config.upstream_no_keepalive,
+            upstream_scheme: self.config.upstream_scheme,
+            error_pages: self.config.error_pages.clone(),
+            readiness: self.config.readiness.clone(),
+            health_check: self.config.health_check.clone(),
+            idle_timeout: self.config.idle_timeout,
+            proxy_timeout: self.config.proxy_timeout,
+            replicas: self.config.replicas,
+            autoscale: self.config.autoscale,
+            __ne: dnem(),
+        }
+    }
+}
+
+/// Owned snapshot of a [`Function`] for spawning it.
+///
+/// Functions are guarded by blocking locks, and holding their guards across await points may
+/// deadlock. Take a snapshot through [`Function::snapshot`] or [`FunctionManager::get_snapshot`]
+/// before awaiting instead.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FunctionSnapshot {
+    /// Address the function is listening on.
+    pub addr: SocketAddr,
+    /// Configuration of the sandbox.
+    pub sandbox: SandboxConfig,
+    /// Maximum count of concurrent websocket connections.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_ws_connections: Option<NonZeroUsize>,
+    /// Whether connections to the function are not reused.
+    pub upstream_no_keepalive: bool,
+    /// Scheme the function is serving.
+    pub upstream_scheme: UpstreamScheme,
+    /// Custom pages replacing error responses of given status codes.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub error_pages: HashMap<u16, ErrorPage>,
+    /// Probing of readiness after spawned.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub readiness: Option<Readiness>,
+    /// Periodic health checking while running.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub health_check: Option<HealthCheck>,
+    /// Duration staying idle before stopped.
+    #[serde(skip_serializing_if = "Option::is_none", with = "crate::sandbox::secs")]
+    pub idle_timeout: Option<Duration>,
+    /// Timeout of responses to proxied requests, overriding the platform-wide one.
+    #[serde(skip_serializing_if = "Option::is_none", with = "crate::sandbox::secs")]
+    pub proxy_timeout: Option<Duration>,
+    /// Count of replicas to spawn.
+    pub replicas: NonZeroUsize,
+    /// Scaling of replicas by concurrency while running.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub autoscale: Option<Autoscale>,
+
+    #[doc(hidden)]
+    #[serde(skip)]
+    pub __ne: NonExhaustiveMarker,
+}
+
 /// Runtime configuration of a [`Function`].
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -43,6 +119,399 @@ pub struct Config {
     /// Configuration of the sandbox.
     pub sandbox: SandboxConfig,
 
+    /// Maximum count of concurrent websocket connections proxied to this function.
+    ///
+    /// Upgrade requests exceeding the limit are rejected. Unlimited if not specified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_ws_connections: Option<NonZeroUsize>,
+
+    /// Whether to disable reusing connections to this function.
+    ///
+    /// Every proxied request is sent through a fresh connection and responded with
+    /// `Connection: close`. Useful for functions misbehaving with connection reuse.
+    #[serde(default)]
+    pub upstream_no_keepalive: bool,
+
+    /// Scheme the function is serving, for functions terminating TLS themselves.
+    ///
+    /// Ignored for network-isolated functions.
+    #[serde(default)]
+    pub upstream_scheme: UpstreamScheme,
+
+    /// Custom pages replacing bodies of responses with given status codes, including errors
+    /// generated by the proxy.
+    ///
+    /// WebSocket upgrades are not affected.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub error_pages: HashMap<u16, ErrorPage>,
+
+    /// Probing of readiness after the function is spawned.
+    ///
+    /// Deploying waits until the function is ready if specified, and fails if it never gets
+    /// ready in time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub readiness: Option<Readiness>,
+
+    /// Periodic health checking while the function is running.
+    ///
+    /// Public traffic avoids the function while it's unhealthy if specified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_check: Option<HealthCheck>,
+
+    /// Whether to start the function on demand when public traffic hits it while it's not
+    /// running.
+    #[serde(default)]
+    pub lazy_deploy: bool,
+
+    /// Duration in seconds for the function to be stopped after staying idle, without any
+    /// request in flight or open websocket connection.
+    ///
+    /// Never stopped for idling if not specified.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::sandbox::secs"
+    )]
+    pub idle_timeout: Option<Duration>,
+
+    /// Timeout in seconds of the function responding to a proxied request with headers once its
+    /// body is sent, with zero disabling it.
+    ///
+    /// WebSocket connections are not affected. Defaults to the platform-wide timeout if not
+    /// specified.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::sandbox::secs"
+    )]
+    pub proxy_timeout: Option<Duration>,
+
+    /// Count of replicas spawned for the function, which requests are distributed across in
+    /// round-robin.
+    ///
+    /// The first replica listens on [`Self::addr`] while the others listen on free ports picked
+    /// on the same IP address, with addresses passed through environment variable [`ENV_ADDR`]. Network-isolated replicas
+    /// each have their own private runtime directory and socket.
+    #[serde(default = "one", skip_serializing_if = "is_one")]
+    pub replicas: NonZeroUsize,
+
+    /// Scaling of replicas by concurrency while the function is running.
+    ///
+    /// The function starts with [`Self::replicas`] replicas clamped into the bounds if specified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub autoscale: Option<Autoscale>,
+
+    /// Command run once in the sandbox after contents are uploaded, with write access to the
+    /// contents.
+    ///
+    /// Uploading fails and is rolled back unless the command exits successfully.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_command: Option<BuildCommand>,
+
+    #[doc(hidden)]
+    #[serde(skip, default = "dnem")]
+    pub __ne: NonExhaustiveMarker,
+}
+
+/// Partial update of a [`Config`], where absent fields are left untouched.
+///
+/// Optional fields of the configuration are cleared with `null`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ConfigPatch {
+    /// Replaces [`Config::group`].
+    #[serde(default, deserialize_with = "present")]
+    pub group: Option<Option<user::Group>>,
+    /// Replaces [`Config::addr`].
+    pub addr: Option<SocketAddr>,
+    /// Merged into [`Config::sandbox`].
+    pub sandbox: Option<SandboxPatch>,
+    /// Replaces [`Config::max_ws_connections`].
+    #[serde(default, deserialize_with = "present")]
+    pub max_ws_connections: Option<Option<NonZeroUsize>>,
+    /// Replaces [`Config::upstream_no_keepalive`].
+    pub upstream_no_keepalive: Option<bool>,
+    /// Replaces [`Config::upstream_scheme`].
+    pub upstream_scheme: Option<UpstreamScheme>,
+    /// Merged into [`Config::error_pages`] status by status, with `null` values removing the
+    /// pages.
+    #[serde(default)]
+    pub error_pages: HashMap<u16, Option<ErrorPage>>,
+    /// Replaces [`Config::readiness`].
+    #[serde(default, deserialize_with = "present")]
+    pub readiness: Option<Option<Readiness>>,
+    /// Replaces [`Config::health_check`].
+    #[serde(default, deserialize_with = "present")]
+    pub health_check: Option<Option<HealthCheck>>,
+    /// Replaces [`Config::lazy_deploy`].
+    pub lazy_deploy: Option<bool>,
+    /// Replaces [`Config::idle_timeout`].
+    #[serde(
+        default,
+        deserialize_with = "crate::sandbox::secs::deserialize_present"
+    )]
+    pub idle_timeout: Option<Option<Duration>>,
+    /// Replaces [`Config::proxy_timeout`].
+    #[serde(
+        default,
+        deserialize_with = "crate::sandbox::secs::deserialize_present"
+    )]
+    pub proxy_timeout: Option<Option<Duration>>,
+    /// Replaces [`Config::replicas`].
+    pub replicas: Option<NonZeroUsize>,
+    /// Replaces [`Config::autoscale`].
+    #[serde(default, deserialize_with = "present")]
+    pub autoscale: Option<Option<Autoscale>>,
+    /// Replaces [`Config::build_command`].
+    #[serde(default, deserialize_with = "present")]
+    pub build_command: Option<Option<BuildCommand>>,
+
+    #[doc(hidden)]
+    #[serde(skip, default = "dnem")]
+    pub __ne: NonExhaustiveMarker,
+}
+
+impl ConfigPatch {
+    /// Merges this patch into given configuration.
+    pub fn apply(self, config: &mut Config) {
+        if let Some(group) = self.group {
+            config.group = group;
+        }
+        if let Some(addr) = self.addr {
+            config.addr = addr;
+        }
+        if let Some(sandbox) = self.sandbox {
+            sandbox.apply(&mut config.sandbox);
+        }
+        if let Some(max_ws_connections) = self.max_ws_connections {
+            config.max_ws_connections = max_ws_connections;
+        }
+        if let Some(upstream_no_keepalive) = self.upstream_no_keepalive {
+            config.upstream_no_keepalive = upstream_no_keepalive;
+        }
+        if let Some(upstream_scheme) = self.upstream_scheme {
+            config.upstream_scheme = upstream_scheme;
+        }
+        for (status, page) in self.error_pages {
+            match page {
+                Some(page) => drop(config.error_pages.insert(status, page)),
+                None => drop(config.error_pages.remove(&status)),
+            }
+        }
+        if let Some(readiness) = self.readiness {
+            config.readiness = readiness;
+        }
+        if let Some(health_check) = self.health_check {
+            config.health_check = health_check;
+        }
+        if let Some(lazy_deploy) = self.lazy_deploy {
+            config.lazy_deploy = lazy_deploy;
+        }
+        if let Some(idle_timeout) = self.idle_timeout {
+            config.idle_timeout = idle_timeout;
+        }
+        if let Some(proxy_timeout) = self.proxy_timeout {
+            config.proxy_timeout = proxy_timeout;
+        }
+        if let Some(replicas) = self.replicas {
+            config.replicas = replicas;
+        }
+        if let Some(autoscale) = self.autoscale {
+            config.autoscale = autoscale;
+        }
+        if let Some(build_command) = self.build_command {
+            config.build_command = build_command;
+        }
+    }
+}
+
+/// Deserializes a field which is present, telling `null` apart from absent fields defaulted to
+/// `None`.
+pub(crate) fn present<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
+
+/// Readiness probing of a function.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Readiness {
+    /// Path to send `GET` requests to, expecting successful responses.
+    ///
+    /// Only whether the function accepts connections is probed if not specified, which can't
+    /// catch functions accepting connections but never responding.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_path: Option<String>,
+
+    /// Duration in seconds for the function to get ready after spawned, before it's considered
+    /// failed.
+    ///
+    /// Defaults to the platform-wide timeout of connecting to functions if not specified.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::sandbox::secs"
+    )]
+    pub timeout: Option<Duration>,
+
+    /// Interval in seconds between probes, which could be fractional.
+    ///
+    /// Defaults to [`DEFAULT_READINESS_INTERVAL`].
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::sandbox::secs"
+    )]
+    pub interval: Option<Duration>,
+
+    #[doc(hidden)]
+    #[serde(skip, default = "dnem")]
+    pub __ne: NonExhaustiveMarker,
+}
+
+impl Readiness {
+    /// Returns the [interval](Self::interval) between probes, or the default one if not
+    /// specified.
+    #[inline]
+    pub fn interval(&self) -> Duration {
+        self.interval.unwrap_or(DEFAULT_READINESS_INTERVAL)
+    }
+}
+
+/// Default interval between readiness probes of a function.
+pub const DEFAULT_READINESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Periodic health checking of a running function.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthCheck {
+    /// Path to send `GET` requests to, expecting successful responses.
+    pub http_path: String,
+
+    /// Interval in seconds between checks, which also bounds how long a check may take.
+    ///
+    /// Defaults to [`DEFAULT_HEALTH_CHECK_INTERVAL`].
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::sandbox::secs"
+    )]
+    pub interval: Option<Duration>,
+
+    /// Count of consecutive failed checks for the function to be considered unhealthy, until a
+    /// check succeeds again.
+    #[serde(default = "default_unhealthy_threshold")]
+    pub unhealthy_threshold: NonZeroU32,
+
+    #[doc(hidden)]
+    #[serde(skip, default = "dnem")]
+    pub __ne: NonExhaustiveMarker,
+}
+
+impl HealthCheck {
+    /// Returns the [interval](Self::interval) between checks, or the default one if not
+    /// specified.
+    #[inline]
+    pub fn interval(&self) -> Duration {
+        self.interval.unwrap_or(DEFAULT_HEALTH_CHECK_INTERVAL)
+    }
+}
+
+/// Default interval between health checks of a function.
+pub const DEFAULT_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+#[inline]
+const fn default_unhealthy_threshold() -> NonZeroU32 {
+    NonZeroU32::new(3).unwrap()
+}
+
+/// Scaling of replicas of a running function by its concurrency.
+///
+/// Periodically, the count of replicas needed for keeping concurrent requests and websocket
+/// connections per replica at [`Self::target_concurrency`] is computed by rounding up, and
+/// clamped into [`Self::min`] and [`Self::max`]. Replicas are added or removed one at a time
+/// towards that count, at most once per [`Self::cooldown`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Autoscale {
+    /// Minimum count of replicas.
+    #[serde(default = "one")]
+    pub min: NonZeroUsize,
+    /// Maximum count of replicas, which must not be less than [`Self::min`].
+    pub max: NonZeroUsize,
+    /// Concurrency per replica to keep the function near.
+    pub target_concurrency: NonZeroUsize,
+    /// Minimum duration in seconds between two scalings, also applied after the function is
+    /// spawned.
+    ///
+    /// Defaults to [`DEFAULT_AUTOSCALE_COOLDOWN`].
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::sandbox::secs"
+    )]
+    pub cooldown: Option<Duration>,
+}
+
+impl Autoscale {
+    /// Returns the [cooldown](Self::cooldown) between scalings, or the default one if not
+    /// specified.
+    #[inline]
+    pub fn cooldown(&self) -> Duration {
+        self.cooldown.unwrap_or(DEFAULT_AUTOSCALE_COOLDOWN)
+    }
+
+    /// Returns the count of replicas wanted for given concurrency across all replicas, within the
+    /// bounds.
+    pub fn desired_replicas(&self, concurrency: usize) -> usize {
+        self.clamp_replicas(concurrency.div_ceil(self.target_concurrency.get()))
+    }
+
+    /// Clamps given count of replicas into the bounds.
+    #[inline]
+    pub fn clamp_replicas(&self, replicas: usize) -> usize {
+        // inverted bounds are rejected by configuration checks, but never panic on them
+        replicas.clamp(self.min.get(), self.max.get().max(self.min.get()))
+    }
+}
+
+/// Default minimum duration between two scalings of a function.
+pub const DEFAULT_AUTOSCALE_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Environment variable passing the address each replica of a function should listen on.
+pub const ENV_ADDR: &str = "YFASS_ADDR";
+
+impl FunctionSnapshot {
+    /// Returns the count of replicas to spawn initially, which is [`Self::replicas`] clamped into
+    /// the bounds of [`Self::autoscale`] if any.
+    pub fn initial_replicas(&self) -> usize {
+        let replicas = self.replicas.get();
+        match self.autoscale {
+            Some(autoscale) => autoscale.clamp_replicas(replicas),
+            None => replicas,
+        }
+    }
+}
+
+/// Scheme of a function serving HTTP and WebSocket connections.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum UpstreamScheme {
+    /// Plain HTTP and WebSocket.
+    #[default]
+    Http,
+    /// HTTP and WebSocket over TLS.
+    Https,
+}
+
+/// Custom page responded in place of an error.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorPage {
+    /// Content type of the page.
+    pub content_type: String,
+    /// Content of the page.
+    pub body: String,
+
     #[doc(hidden)]
     #[serde(skip, default = "dnem")]
     pub __ne: NonExhaustiveMarker,
@@ -55,9 +524,25 @@ pub struct Metadata {
     pub name: String,
     /// Version identifier of the function.
     pub version: String,
-    /// Alias of the function's version for quick access in subdomains.
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub version_alias: Option<String>,
+    /// Aliases of the function's version for quick access in subdomains.
+    ///
+    /// The single alias stored by older versions as `version_alias` is accepted as well.
+    #[serde(
+        default,
+        alias = "version_alias",
+        deserialize_with = "aliases",
+        skip_serializing_if = "BTreeSet::is_empty"
+    )]
+    pub version_aliases: BTreeSet<String>,
+    /// Instant the function was first uploaded.
+    ///
+    /// Functions stored by older versions are assumed to be created when their metadata was last
+    /// written.
+    #[serde(default = "unix_epoch", with = "crate::rfc3339")]
+    pub created_at: UtcDateTime,
+    /// Instant the configuration or alias of the function was last modified.
+    #[serde(default = "unix_epoch", with = "crate::rfc3339")]
+    pub updated_at: UtcDateTime,
 
     #[doc(hidden)]
     #[serde(skip, default = "dnem")]
@@ -70,22 +555,82 @@ impl Default for Config {
             group: None,
             addr: SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0)),
             sandbox: SandboxConfig::default(),
+            max_ws_connections: None,
+            upstream_no_keepalive: false,
+            upstream_scheme: UpstreamScheme::Http,
+            error_pages: HashMap::new(),
+            readiness: None,
+            health_check: None,
+            lazy_deploy: false,
+            idle_timeout: None,
+            proxy_timeout: None,
+            replicas: NonZeroUsize::MIN,
+            autoscale: None,
+            build_command: None,
             __ne: dnem(),
         }
     }
 }
 
+/// Deserializes aliases from either a set, a single alias or `null`.
+fn aliases<'de, D>(deserializer: D) -> Result<BTreeSet<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Aliases {
+        Set(BTreeSet<String>),
+        Single(Option<String>),
+    }
+
+    Ok(match Aliases::deserialize(deserializer)? {
+        Aliases::Set(set) => set,
+        Aliases::Single(alias) => alias.into_iter().collect(),
+    })
+}
+
+#[inline]
+const fn one() -> NonZeroUsize {
+    NonZeroUsize::MIN
+}
+
+#[inline]
+#[allow(clippy::trivially_copy_pass_by_ref)] // signature required by serde
+fn is_one(n: &NonZeroUsize) -> bool {
+    *n == NonZeroUsize::MIN
+}
+
+#[inline]
+const fn unix_epoch() -> UtcDateTime {
+    UtcDateTime::UNIX_EPOCH
+}
+
 impl Default for Metadata {
     fn default() -> Self {
         Self {
             name: String::new(),
             version: String::new(),
-            version_alias: None,
+            version_aliases: BTreeSet::new(),
+            created_at: UtcDateTime::UNIX_EPOCH,
+            updated_at: UtcDateTime::UNIX_EPOCH,
             __ne: dnem(),
         }
     }
 }
 
+/// Modification of aliases of a [`Function`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum AliasModification {
+    /// Adds an alias.
+    Add(String),
+    /// Removes an alias.
+    Remove(String),
+    /// Replaces all aliases with the given one, or removes all of them if `None`.
+    Set(Option<String>),
+}
+
 /// Owned version of [`Key`].
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct OwnedKey {
@@ -125,6 +670,12 @@ impl FromStr for OwnedKey {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let (n, v) = s.split_once('@').ok_or(ParseKeyError::MissingSeparator)?;
+        if n.is_empty() {
+            return Err(ParseKeyError::InvalidName);
+        }
+        if v.is_empty() {
+            return Err(ParseKeyError::InvalidVersion);
+        }
         Ok(Self {
             name: n.to_owned(),
             version: v.to_owned(),
@@ -175,7 +726,7 @@ pub struct Key<'a> {
     pub version: &'a str,
 }
 
-impl Key<'_> {
+impl<'a> Key<'a> {
     /// Converts this borrowed key into its owned variant.
     #[inline]
     pub fn into_owned(self) -> OwnedKey {
@@ -190,6 +741,13 @@ impl Key<'_> {
     pub fn to_host_prefix(&self) -> String {
         format!("{}.{}", self.version, self.name)
     }
+
+    /// Parses a prefix for host names back into a key, see [`Self::to_host_prefix`].
+    #[inline]
+    pub fn from_host_prefix(host_prefix: &'a str) -> Option<Self> {
+        let (version, name) = host_prefix.split_once('.')?;
+        Some(Self { name, version })
+    }
 }
 
 impl Display for Key<'_> {
@@ -216,40 +774,197 @@ impl Display for Key<'_> {
 ///
 /// - `config.json` for [`Config`].
 /// - `metadata.json` for [`Metadata`].
+///
+/// The JSON files are persisted through the [`Storage`] with the same keys, which is the
+/// filesystem under the root directory by default. Contents are always on the filesystem.
 #[derive(Debug)]
 pub struct FunctionManager {
     functions: scc::HashMap<OwnedKey, FunctionCell>,
 
     root_dir: Arc<Path>,
     dirty: AtomicBool,
+    storage: Option<Arc<dyn Storage>>,
+    default_config: Config,
+    config_limits: ConfigLimits,
+    /// Directories in the host system absolute environment variable files are allowed under.
+    env_file_dirs: Box<[PathBuf]>,
+}
+
+/// Caps on counts of entries of function configurations, preventing a single configuration from
+/// bloating memory and saves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfigLimits {
+    /// Maximum count of [environment variables](SandboxConfig::envs).
+    pub max_envs: usize,
+    /// Maximum count of [read-only entries](SandboxConfig::ro_entries).
+    pub max_ro_entries: usize,
+    /// Maximum count of filtered syscalls.
+    pub max_syscall_filter: usize,
+
+    #[doc(hidden)]
+    pub __ne: NonExhaustiveMarker,
+}
+
+impl ConfigLimits {
+    /// Limits that never reject any configuration.
+    pub const UNLIMITED: Self = Self {
+        max_envs: usize::MAX,
+        max_ro_entries: usize::MAX,
+        max_syscall_filter: usize::MAX,
+        __ne: dnem(),
+    };
+
+    /// Checks whether given configuration is within the limits.
+    ///
+    /// # Errors
+    ///
+    /// - Returns `TooManyEntries` naming the first field exceeding its limit.
+    /// - Returns `InvalidAutoscale` if the maximum count of replicas of autoscaling is less than
+    ///   the minimum one.
+    pub fn check(&self, config: &Config) -> Result<(), ManagerError> {
+        if config
+            .autoscale
+            .is_some_and(|autoscale| autoscale.max < autoscale.min)
+        {
+            return Err(ManagerError::InvalidAutoscale);
+        }
+
+        let sandbox = &config.sandbox;
+        #[cfg(target_os = "linux")]
+        let syscall_filter = sandbox.platform_ext.syscall_filter.len();
+        #[cfg(not(target_os = "linux"))]
+        let syscall_filter = 0;
+
+        for (field, len, max) in [
+            ("envs", sandbox.envs.len(), self.max_envs),
+            ("ro_entries", sandbox.ro_entries.len(), self.max_ro_entries),
+            ("syscall_filter", syscall_filter, self.max_syscall_filter),
+        ] {
+            if len > max {
+                return Err(ManagerError::TooManyEntries { field, max });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for ConfigLimits {
+    #[inline]
+    fn default() -> Self {
+        Self::UNLIMITED
+    }
 }
 
 const FILE_METADATA: &str = "metadata.json";
 const FILE_CONFIG: &str = "config.json";
 const DIR_CONTENTS: &str = "contents";
+const DIR_CONTENTS_STAGING: &str = "contents.staging";
+const DIR_CONTENTS_RETIRED: &str = "contents.retired";
+const DIR_RUNTIME: &str = "run";
+const DIR_LOGS: &str = "logs";
+const FILE_EMBEDDED_CONFIG: &str = "yfass.json";
 
 impl FunctionManager {
     fn mark_dirty(&self) {
-        self.dirty.store(true, atomic::Ordering::Relaxed);
+        if self.storage.is_some() {
+            self.dirty.store(true, atomic::Ordering::Relaxed);
+        }
     }
 
-    /// Checks whether the user manager is dirty and needs to be written to the filesystem.
+    /// Checks whether the function manager is dirty and needs to be written to the storage.
     #[inline]
     pub fn is_dirty(&self) -> bool {
         self.dirty.load(atomic::Ordering::Relaxed)
     }
 
-    /// Creates an empty, uninitialized function manager.
+    /// Creates an empty, uninitialized function manager persisting to the filesystem.
     ///
-    /// For loading functions from the filesystem, use [`Self::read_from_fs`].
+    /// For loading functions from the storage, use [`Self::read_from_storage`].
     pub fn new<P>(root_dir: P) -> Self
     where
         P: Into<PathBuf>,
     {
+        let root_dir: Arc<Path> = root_dir.into().into_boxed_path().into();
         Self {
             functions: scc::HashMap::new(),
-            root_dir: root_dir.into().into_boxed_path().into(),
+            storage: Some(Arc::new(FsStorage::new(&*root_dir))),
+            root_dir,
             dirty: AtomicBool::new(false),
+            default_config: Config::default(),
+            config_limits: ConfigLimits::UNLIMITED,
+            env_file_dirs: Box::default(),
+        }
+    }
+
+    /// Sets the limits configurations of functions are checked against when applied.
+    pub fn with_config_limits(self, limits: ConfigLimits) -> Self {
+        Self {
+            config_limits: limits,
+            ..self
+        }
+    }
+
+    /// Sets the directories in the host system [environment variable
+    /// files](SandboxConfig::env_files) with absolute paths are allowed under.
+    ///
+    /// Absolute paths are rejected if none is set, while relative ones are always allowed as long
+    /// as they stay inside the contents directory.
+    pub fn with_env_file_dirs<I>(self, dirs: I) -> Self
+    where
+        I: IntoIterator<Item = PathBuf>,
+    {
+        Self {
+            env_file_dirs: dirs.into_iter().collect(),
+            ..self
+        }
+    }
+
+    /// Checks given configuration against the limits and the allowed directories of environment
+    /// variable files.
+    fn check_config(&self, config: &Config) -> Result<(), ManagerError> {
+        self.config_limits.check(config)?;
+        for path in &config.sandbox.env_files {
+            let allowed = !path
+                .components()
+                .any(|c| c == std::path::Component::ParentDir)
+                && (path.is_relative()
+                    || self.env_file_dirs.iter().any(|dir| path.starts_with(dir)));
+            if !allowed {
+                return Err(ManagerError::EnvFileNotAllowed(path.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the storage information of functions are persisted to, while contents are still
+    /// unpacked under the root directory.
+    pub fn with_storage(self, storage: Arc<dyn Storage>) -> Self {
+        Self {
+            storage: Some(storage),
+            ..self
+        }
+    }
+
+    /// Sets the configuration new functions start with, except for the group which is always
+    /// initialized by the uploader.
+    pub fn with_default_config(self, config: Config) -> Self {
+        Self {
+            default_config: config,
+            ..self
+        }
+    }
+
+    /// Creates an empty function manager without persistence of function information.
+    ///
+    /// Contents of functions are still unpacked under the given directory, while reading from and
+    /// writing to the storage are no-ops.
+    pub fn new_in_memory<P>(contents_root_dir: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self {
+            storage: None,
+            ..Self::new(contents_root_dir)
         }
     }
 
@@ -259,42 +974,54 @@ impl FunctionManager {
         self.functions.is_empty()
     }
 
-    /// Loads all functions from the filesystem.
+    /// Returns the count of functions, with each version counted separately.
+    ///
+    /// Aliases are not counted, as they resolve to the same functions as their versions.
+    pub fn len(&self) -> usize {
+        let mut len = 0;
+        self.functions.iter_sync(|key, func| {
+            if key.version == func.read().meta.version {
+                len += 1;
+            }
+            true
+        });
+        len
+    }
+
+    /// Loads all functions from the storage.
     ///
-    /// This function is blocking and _should only be called at initialization._
+    /// This function _should only be called at initialization._
     ///
     /// # Errors
     ///
     /// - `Initialized` if the function manager is not empty.
-    /// - Other errors if any error occurs while interacting with filesystem.
-    #[allow(clippy::missing_panics_doc)] // should not panic
-    pub fn read_from_fs(&self) -> Result<(), ManagerError> {
-        let span = tracing::info_span!("loading information of functions from the filesystem");
-        let _e = span.enter();
+    /// - Other errors if any error occurs while interacting with the storage.
+    pub async fn read_from_storage(&self) -> Result<(), ManagerError> {
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
 
-        let result = self.priv_read_from_fs();
+        let span = tracing::info_span!("loading information of functions from the storage");
+        let _e = span.enter();
 
-        // emit not-found errors
-        if result.as_ref().is_err_and(|err| {
-            if let ManagerError::Io(io) = err {
-                io.kind() == std::io::ErrorKind::NotFound
-            } else {
-                false
-            }
-        }) {
-            Ok(())
-        } else {
-            result
-        }
+        self.priv_read_from_storage(&**storage).await
     }
 
-    /// Writes all information of functions to the filesystem.
-    #[allow(clippy::missing_errors_doc)] // general I/O errors from std::io
-    pub async fn write_all_to_fs(&self) -> Result<(), ManagerError> {
-        let span = tracing::info_span!("writing information of functions to the filesystem");
+    /// Writes all information of functions to the storage.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error if failed to write any function, after trying to write all of
+    /// them. The manager is left dirty in that case.
+    pub async fn write_all_to_storage(&self) -> Result<(), ManagerError> {
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
+
+        let span = tracing::info_span!("writing information of functions to the storage");
         let _e = span.enter();
 
-        self.priv_write_all_to_fs().await?;
+        self.priv_write_all_to_storage(storage).await?;
 
         self.dirty.store(false, atomic::Ordering::Relaxed);
         Ok(())
@@ -302,52 +1029,262 @@ impl FunctionManager {
 
     /// Adds a function to the platform with given minimal information and stream of tarball.
     ///
+    /// If the tarball contains a `yfass.json` file at its root, it's applied as the configuration
+    /// of the function, with the group overridden by the given one.
+    ///
+    /// Sizes of entries of the tarball are passed to `charge` before they're unpacked, which
+    /// aborts unpacking by returning `false`.
+    ///
     /// # Errors
     ///
     /// - Returns an error if the function with given key already exists.
     /// - Returns an error if the tarball is corrupted.
-    pub async fn add_func<R>(
+    /// - `UnpackLimitExceeded` if `charge` refuses an entry.
+    /// - Returns an error if the embedded configuration is malformed, exceeds the limits or has
+    ///   environment variable files outside allowed directories.
+    ///
+    /// The function is removed again on errors after it's added.
+    pub async fn add_func<R, F>(
         &self,
         key: Key<'_>,
         init_group: Option<user::Group>,
         tarball: &mut Tar<R>,
+        charge: F,
     ) -> Result<(), ManagerError>
     where
         R: AsyncRead + Unpin,
+        F: FnMut(u64) -> bool,
     {
-        self.priv_init_info(key, init_group)?;
-        self.priv_write_contents(key, tarball).await?;
+        self.priv_init_info(key, init_group.clone())?;
+        let result = async {
+            self.priv_write_contents(key, tarball, charge).await?;
+            match self.priv_read_embedded_config(key).await? {
+                Some(mut config) => {
+                    config.group = init_group;
+                    self.priv_modify_config(key, config)
+                }
+                None => Ok(()),
+            }
+        }
+        .await;
+        if let Err(err) = result {
+            // nothing of a failed upload is kept, so it could be retried under the same key
+            drop(
+                self.priv_remove_func(key)
+                    .await
+                    .inspect_err(|e| tracing::error!("failed to roll back function {key}: {e}")),
+            );
+            return Err(err);
+        }
         self.mark_dirty();
         Ok(())
     }
 
-    /// Modifies alias of a function.
+    /// Unpacks given stream of tarball into the staging directory of a function, returning the
+    /// path to it. The staged contents replace current ones by [`Self::swap_staged_contents`],
+    /// leaving its information untouched.
+    ///
+    /// Contents are staged separately so a corrupted tarball or a failed build never affects the
+    /// function, and leftovers of interrupted replacements are cleaned up by the next one. Sizes
+    /// of entries are charged the same as [`Self::add_func`].
     ///
     /// # Errors
     ///
-    /// Returns an error if the function with given key is not found.
-    #[inline]
-    pub fn modify_alias(&self, key: Key<'_>, alias: Option<String>) -> Result<(), ManagerError> {
-        self.priv_modify_alias(key, alias)?;
-        self.mark_dirty();
-        Ok(())
+    /// - `NotFound` if the function with given key is not found.
+    /// - Returns an error if the tarball is corrupted.
+    /// - `UnpackLimitExceeded` if `charge` refuses an entry.
+    pub async fn stage_contents<R, F>(
+        &self,
+        key: Key<'_>,
+        tarball: &mut Tar<R>,
+        charge: F,
+    ) -> Result<PathBuf, ManagerError>
+    where
+        R: AsyncRead + Unpin,
+        F: FnMut(u64) -> bool,
+    {
+        self.priv_stage_contents(key, tarball, charge).await
     }
 
-    /// Modifies configuration of a function.
+    /// Replaces contents of a function with the ones staged by [`Self::stage_contents`].
     ///
     /// # Errors
     ///
-    /// Returns an error if the function with given key is not found.
-    #[inline]
-    pub fn modify_config(&self, key: Key<'_>, config: Config) -> Result<(), ManagerError> {
-        self.priv_modify_config(key, config)?;
+    /// - `NotFound` if the function with given key or its staged contents are not found.
+    pub async fn swap_staged_contents(&self, key: Key<'_>) -> Result<(), ManagerError> {
+        self.priv_swap_staged_contents(key).await?;
         self.mark_dirty();
         Ok(())
     }
 
-    /// Removes a function from this manager.
-    ///
-    /// # Errors
+    /// Removes contents staged by [`Self::stage_contents`] without applying them.
+    pub async fn discard_staged_contents(&self, key: Key<'_>) {
+        if let Some(dir) = self.priv_dir(key) {
+            drop(
+                tokio::fs::remove_dir_all(dir.join(DIR_CONTENTS_STAGING))
+                    .await
+                    .inspect_err(|e| tracing::error!("failed to remove staged contents: {e}")),
+            );
+        }
+    }
+
+    /// Checks whether given function could be imported without touching this manager, see
+    /// [`Self::import_func`].
+    ///
+    /// # Errors
+    ///
+    /// - `Duplicated` if the function already exists and `replace` is not set, or any of its
+    ///   aliases is an existing version.
+    /// - Returns an error if the configuration exceeds the limits or has environment variable
+    ///   files outside allowed directories.
+    pub fn check_import(&self, func: &Function, replace: bool) -> Result<(), ManagerError> {
+        self.check_config(&func.config)?;
+        let key = Key {
+            name: &func.meta.name,
+            version: &func.meta.version,
+        };
+        if !replace && self.functions.contains_sync(&key) {
+            return Err(ManagerError::Duplicated);
+        }
+        // versions are never shadowed by aliases
+        for alias in &func.meta.version_aliases {
+            let alias_key = Key {
+                name: &func.meta.name,
+                version: alias,
+            };
+            if self
+                .functions
+                .read_sync(&alias_key, |_, f| f.read().meta.version == *alias)
+                .unwrap_or_default()
+            {
+                return Err(ManagerError::Duplicated);
+            }
+        }
+        Ok(())
+    }
+
+    /// Imports a function with its information and contents from a directory, which is moved
+    /// into this manager.
+    ///
+    /// The contents directory should be on the same filesystem as the root directory, like the
+    /// ones under [`Self::import_staging_path`].
+    ///
+    /// # Errors
+    ///
+    /// - `Duplicated` if the function already exists and `replace` is not set, or any of its
+    ///   aliases is an existing version.
+    /// - Returns an error if the configuration exceeds the limits or has environment variable
+    ///   files outside allowed directories.
+    /// - Returns an error if the contents could not be moved.
+    pub async fn import_func(
+        &self,
+        func: Function,
+        contents: &Path,
+        replace: bool,
+    ) -> Result<(), ManagerError> {
+        self.check_import(&func, replace)?;
+        let key = OwnedKey {
+            name: func.meta.name.clone(),
+            version: func.meta.version.clone(),
+        };
+        if self.functions.contains_sync(&key) {
+            if !replace {
+                return Err(ManagerError::Duplicated);
+            }
+            self.priv_remove_func(key.as_ref()).await?;
+        }
+
+        let path = self.contents_path(key.as_ref());
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        match tokio::fs::rename(contents, &path).await {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                tokio::fs::create_dir_all(&path).await?;
+            }
+            result => result?,
+        }
+
+        let alias_keys: Vec<_> = func
+            .meta
+            .version_aliases
+            .iter()
+            .map(|alias| OwnedKey {
+                name: key.name.clone(),
+                version: alias.clone(),
+            })
+            .collect();
+        let cell = Arc::new(RwLock::new(func));
+        if let scc::hash_map::Entry::Vacant(entry) = self.functions.entry_sync(key) {
+            drop(entry.insert_entry(cell.clone()));
+        } else {
+            return Err(ManagerError::Duplicated);
+        }
+        for alias_key in alias_keys {
+            self.priv_link_alias(&cell, alias_key)?;
+        }
+
+        self.mark_dirty();
+        Ok(())
+    }
+
+    /// Returns a hidden directory under the root directory for staging imported contents, which
+    /// is never loaded as a function.
+    pub fn import_staging_path(&self, name: &str) -> PathBuf {
+        self.root_dir.join(format!(".import-{name}"))
+    }
+
+    /// Modifies aliases of a function.
+    ///
+    /// An added alias held by another version of the function is taken over from it.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if the function with given key is not found.
+    /// - `NotAliased` if the alias to be removed is not held by the function.
+    /// - `Duplicated` if the alias to be added is a version of the function.
+    #[inline]
+    pub fn modify_alias(
+        &self,
+        key: Key<'_>,
+        modification: AliasModification,
+    ) -> Result<(), ManagerError> {
+        self.priv_modify_alias(key, modification)?;
+        self.mark_dirty();
+        Ok(())
+    }
+
+    /// Modifies configuration of a function.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if the function with given key is not found.
+    /// - Returns an error if the configuration exceeds the limits or has environment variable files
+    ///   outside allowed directories.
+    #[inline]
+    pub fn modify_config(&self, key: Key<'_>, config: Config) -> Result<(), ManagerError> {
+        self.priv_modify_config(key, config)?;
+        self.mark_dirty();
+        Ok(())
+    }
+
+    /// Merges a partial update into configuration of a function atomically, so concurrent
+    /// updates of other fields are never lost.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if the function with given key is not found.
+    /// - Returns an error if the merged configuration exceeds the limits or has environment
+    ///   variable files outside allowed directories, leaving it untouched.
+    pub fn patch_config(&self, key: Key<'_>, patch: ConfigPatch) -> Result<(), ManagerError> {
+        self.priv_patch_config(key, patch)?;
+        self.mark_dirty();
+        Ok(())
+    }
+
+    /// Removes a function from this manager.
+    ///
+    /// # Errors
     ///
     /// Returns an error if the function with given key is not found.
     #[inline]
@@ -357,154 +1294,387 @@ impl FunctionManager {
         Ok(())
     }
 
+    /// Renames a function to another key, moving its directory along with it.
+    ///
+    /// Aliases of the function are kept, under the new name. `is_running` tells whether the
+    /// function of given canonical key is running, which is checked with the function locked for
+    /// writing through the move so it's never renamed while running, thus must never access this
+    /// manager.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if the function with given key or its directory is not found.
+    /// - `Duplicated` if the new key (or the alias under the new name) is already taken.
+    /// - `Running` if `is_running` tells so.
+    #[inline]
+    pub async fn rename<F>(
+        &self,
+        from: Key<'_>,
+        to: Key<'_>,
+        is_running: F,
+    ) -> Result<(), ManagerError>
+    where
+        F: FnOnce(Key<'_>) -> bool,
+    {
+        self.priv_rename(from, to, is_running).await?;
+        self.mark_dirty();
+        Ok(())
+    }
+
     /// Returns the function information of given key if present.
     #[inline]
     pub fn get(&self, key: Key<'_>) -> Option<FunctionCell> {
         self.functions.read_sync(&key, |_, v| v.clone())
     }
 
+    /// Runs given closure with the function of given key if present, without cloning its cell.
+    ///
+    /// The closure runs with the function locked for reading, thus should be short and never
+    /// access this manager.
+    #[inline]
+    pub fn with_function<F, R>(&self, key: Key<'_>, f: F) -> Option<R>
+    where
+        F: FnOnce(&Function) -> R,
+    {
+        self.functions.read_sync(&key, |_, func| f(&func.read()))
+    }
+
+    /// Resolves given key or alias into the key of the underlying version, if present.
+    pub fn canonical_key(&self, key: Key<'_>) -> Option<OwnedKey> {
+        self.functions.read_sync(&key, |_, func| {
+            let rg = func.read();
+            OwnedKey {
+                name: rg.meta.name.clone(),
+                version: rg.meta.version.clone(),
+            }
+        })
+    }
+
+    /// Returns information of functions required by given group, or all functions if `None`.
+    pub fn functions_by_group(&self, group: Option<&user::Group>) -> Vec<Function> {
+        let mut functions = Vec::new();
+        self.functions.iter_sync(|key, func| {
+            let rg = func.read();
+            // skip alias entries, as aliases resolve to the same function as their versions
+            if key.version == rg.meta.version
+                && group.is_none_or(|g| rg.config.group.as_ref() == Some(g))
+            {
+                functions.push(rg.clone());
+            }
+            true
+        });
+        functions
+    }
+
+    /// Returns a page of keys of functions whose names start with given prefix, sorted by name
+    /// then version, along with the total count of matching functions.
+    ///
+    /// Aliases are not included, as they resolve to the same functions as their versions.
+    pub fn list_page(
+        &self,
+        prefix: Option<&str>,
+        offset: usize,
+        limit: usize,
+    ) -> (Vec<OwnedKey>, usize) {
+        let mut keys = Vec::new();
+        self.functions.iter_sync(|key, func| {
+            if prefix.is_none_or(|prefix| key.name.starts_with(prefix))
+                && key.version == func.read().meta.version
+            {
+                keys.push(key.clone());
+            }
+            true
+        });
+        let total = keys.len();
+        keys.sort_unstable_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+        let page = keys.into_iter().skip(offset).take(limit).collect();
+        (page, total)
+    }
+
+    /// Returns versions of functions with given name, sorted.
+    ///
+    /// Aliases are not included, as they resolve to the same functions as their versions.
+    pub fn versions(&self, name: &str) -> Vec<String> {
+        let mut versions = Vec::new();
+        self.functions.iter_sync(|key, func| {
+            if key.name == name && key.version == func.read().meta.version {
+                versions.push(key.version.clone());
+            }
+            true
+        });
+        versions.sort_unstable();
+        versions
+    }
+
+    /// Returns the total size in bytes of contents of functions owned by given user, which are
+    /// the ones requiring the singular group of the user.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any contents directory fails to be walked through.
+    pub async fn used_bytes(&self, user: &str) -> Result<u64, ManagerError> {
+        let owner = user::Group::Singular(user.to_owned());
+        let mut paths = Vec::new();
+        self.functions.iter_sync(|key, func| {
+            let rg = func.read();
+            if key.version == rg.meta.version && rg.config.group.as_ref() == Some(&owner) {
+                paths.push(self.contents_path(key.as_ref()));
+            }
+            true
+        });
+
+        let mut used = 0;
+        for path in paths {
+            used += dir_size(&path).await?;
+        }
+        Ok(used)
+    }
+
+    /// Returns the size in bytes of contents of the function of given key or alias.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if the function with given key is not found.
+    /// - Returns an error if the contents could not be walked.
+    pub async fn contents_bytes(&self, key: Key<'_>) -> Result<u64, ManagerError> {
+        let dir = self.priv_dir(key).ok_or(ManagerError::NotFound)?;
+        Ok(dir_size(&dir.join(DIR_CONTENTS)).await?)
+    }
+
+    /// Returns an await-safe snapshot of the function of given key if present.
+    #[inline]
+    pub fn get_snapshot(&self, key: Key<'_>) -> Option<FunctionSnapshot> {
+        self.functions.read_sync(&key, |_, v| v.read().snapshot())
+    }
+
+    /// Reads the raw `config.json` of a function verbatim, or serializes the in-memory
+    /// configuration if it's not written yet.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if the function with given key is not found.
+    /// - Other errors if any error occurs while interacting with the storage.
+    pub async fn read_raw_config(&self, key: Key<'_>) -> Result<Vec<u8>, ManagerError> {
+        let func = self.get(key).ok_or(ManagerError::NotFound)?;
+        let (storage_key, serialized) = {
+            let rg = func.read();
+            let canonical = Key {
+                name: &rg.meta.name,
+                version: &rg.meta.version,
+            };
+            (
+                format!("{canonical}/{FILE_CONFIG}"),
+                serde_json::to_vec_pretty(&rg.config),
+            )
+        };
+
+        let raw = match &self.storage {
+            Some(storage) => storage.read(&storage_key).await?,
+            None => None,
+        };
+        raw.map_or_else(|| serialized.map_err(Into::into), Ok)
+    }
+
     /// Returns the path to the `contents` directory of a function.
     pub fn contents_path(&self, key: Key<'_>) -> PathBuf {
         self.root_dir.join(key.to_string()).join(DIR_CONTENTS)
     }
+
+    /// Returns the path to the private runtime directory of a function.
+    pub fn runtime_path(&self, key: Key<'_>) -> PathBuf {
+        self.root_dir.join(key.to_string()).join(DIR_RUNTIME)
+    }
+
+    /// Returns the path to the private runtime directory of given replica of a function, which is
+    /// the one of the function for the first replica.
+    pub fn replica_runtime_path(&self, key: Key<'_>, replica: usize) -> PathBuf {
+        let dir = self.root_dir.join(key.to_string());
+        if replica == 0 {
+            dir.join(DIR_RUNTIME)
+        } else {
+            dir.join(format!("{DIR_RUNTIME}.{replica}"))
+        }
+    }
+
+    /// Returns the path to the directory of output log files of a function.
+    pub fn logs_path(&self, key: Key<'_>) -> PathBuf {
+        self.root_dir.join(key.to_string()).join(DIR_LOGS)
+    }
 }
 
 // Implementation
 impl FunctionManager {
-    fn priv_read_from_fs(&self) -> Result<(), ManagerError> {
+    async fn priv_read_from_storage(&self, storage: &dyn Storage) -> Result<(), ManagerError> {
         if !self.is_empty() {
             return Err(ManagerError::Initialized);
         }
 
-        for entry in std::fs::read_dir(&self.root_dir)?
-            .inspect(|r| {
-                if let Err(e) = r {
-                    tracing::error!("failed to read directory entry: {e}")
-                }
-            })
-            .filter_map(Result::ok)
-        {
-            let path = entry.path();
-            if path.is_dir() {
-                let Ok(func) = || -> Result<Function, ManagerError> {
-                    let metadata: Metadata = serde_json::from_reader(std::io::BufReader::new(
-                        std::fs::File::open(path.join(FILE_METADATA))?,
-                    ))?;
-
-                    let config: Config = serde_json::from_reader(std::io::BufReader::new(
-                        std::fs::File::open(path.join(FILE_CONFIG))?,
-                    ))?;
-
-                    Ok(Function {
-                        meta: metadata,
-                        config,
-                    })
-                }()
-                .inspect_err(|e| tracing::error!("failed to load function information: {e}")) else {
-                    continue;
+        for dir in storage.list_dirs("").await? {
+            let result: Result<Option<Function>, ManagerError> = async {
+                let (Some(metadata), Some(config)) = (
+                    storage.read(&format!("{dir}/{FILE_METADATA}")).await?,
+                    storage.read(&format!("{dir}/{FILE_CONFIG}")).await?,
+                ) else {
+                    // not a function
+                    return Ok(None);
                 };
-
-                let func = Arc::new(RwLock::new(func));
-                let fr = func.try_read().unwrap(); // this won't fail
-
-                if let Some(ref alias) = fr.meta.version_alias {
-                    let _r = self
-                        .functions
-                        .insert_sync(
-                            OwnedKey {
-                                name: fr.meta.name.clone(),
-                                version: alias.clone(),
-                            },
-                            func.clone(),
-                        )
-                        .inspect_err(|(k, _)| {
-                            tracing::error!("duplicated function entry: (alias) {k}",)
-                        });
+                let mut meta: Metadata = serde_json::from_slice(&metadata)?;
+                // written by older versions without timestamps
+                if meta.created_at == UtcDateTime::UNIX_EPOCH {
+                    if let Some(modified) =
+                        storage.modified(&format!("{dir}/{FILE_METADATA}")).await?
+                    {
+                        meta.created_at = modified.into();
+                    }
+                    if meta.updated_at == UtcDateTime::UNIX_EPOCH {
+                        meta.updated_at = meta.created_at;
+                    }
                 }
+                Ok(Some(Function {
+                    meta,
+                    config: serde_json::from_slice(&config)?,
+                }))
+            }
+            .await;
+            let Ok(Some(func)) =
+                result.inspect_err(|e| tracing::error!("failed to load function information: {e}"))
+            else {
+                continue;
+            };
 
-                let key = OwnedKey {
-                    name: fr.meta.name.clone(),
-                    version: fr.meta.version.clone(),
-                };
-
-                drop(fr);
+            let func = Arc::new(RwLock::new(func));
+            let fr = func.try_read().unwrap(); // this won't fail
 
+            for alias in &fr.meta.version_aliases {
                 let _r = self
                     .functions
-                    .insert_sync(key, func)
-                    .inspect_err(|(k, _)| tracing::error!("duplicated function entry: {k}"));
+                    .insert_sync(
+                        OwnedKey {
+                            name: fr.meta.name.clone(),
+                            version: alias.clone(),
+                        },
+                        func.clone(),
+                    )
+                    .inspect_err(|(k, _)| {
+                        tracing::error!("duplicated function entry: (alias) {k}",)
+                    });
             }
+
+            let key = OwnedKey {
+                name: fr.meta.name.clone(),
+                version: fr.meta.version.clone(),
+            };
+
+            drop(fr);
+
+            let _r = self
+                .functions
+                .insert_sync(key, func)
+                .inspect_err(|(k, _)| tracing::error!("duplicated function entry: {k}"));
         }
 
         Ok(())
     }
 
-    async fn priv_write_all_to_fs(&self) -> Result<(), ManagerError> {
+    async fn priv_write_all_to_storage(
+        &self,
+        storage: &Arc<dyn Storage>,
+    ) -> Result<(), ManagerError> {
         let mut js = JoinSet::new();
 
         self.functions.iter_sync(|key, func| {
             let func = func.clone();
             let key = key.clone();
-            let path = self.root_dir.join(key.to_string());
+            let storage = storage.clone();
 
             let func = func.read();
             let meta = serde_json::to_vec_pretty(&func.meta);
             let config = serde_json::to_vec_pretty(&func.config);
 
             js.spawn(async move {
-                let _r: Result<(), ManagerError> = async {
-                    tokio::fs::create_dir_all(&path).await?;
-                    tokio::fs::write(path.join(FILE_METADATA), meta?).await?;
-                    tokio::fs::write(path.join(FILE_CONFIG), config?).await?;
+                async {
+                    storage
+                        .write(&format!("{key}/{FILE_METADATA}"), meta?)
+                        .await?;
+                    storage
+                        .write(&format!("{key}/{FILE_CONFIG}"), config?)
+                        .await?;
 
                     Ok(())
                 }
                 .await
-                .inspect_err(|e| {
-                    tracing::error!("failed to write function `{key}` to filesystem: {e}");
-                });
+                .inspect_err(|e: &ManagerError| {
+                    tracing::error!("failed to write function `{key}` to storage: {e}");
+                })
             });
             true
         });
 
-        drop(js.join_all().await);
-        Ok(())
+        // the rest are still written on failures of some functions
+        js.join_all().await.into_iter().collect()
     }
 
     fn priv_modify_config(&self, key: Key<'_>, config: Config) -> Result<(), ManagerError> {
+        self.check_config(&config)?;
         let func = self
             .functions
             .read_sync(&key, |_, func| func.clone())
             .ok_or(ManagerError::NotFound)?;
 
-        func.write().config = config;
+        let mut wg = func.write();
+        wg.config = config;
+        wg.meta.updated_at = UtcDateTime::now();
 
         Ok(())
     }
 
-    fn priv_modify_alias(&self, key: Key<'_>, alias: Option<String>) -> Result<(), ManagerError> {
+    fn priv_patch_config(&self, key: Key<'_>, patch: ConfigPatch) -> Result<(), ManagerError> {
         let func = self
             .functions
             .read_sync(&key, |_, func| func.clone())
             .ok_or(ManagerError::NotFound)?;
 
         let mut wg = func.write();
-        if wg.meta.version_alias == alias {
-            return Ok(());
-        }
-        let an = alias.is_some();
-        let ao = std::mem::replace(&mut wg.meta.version_alias, alias);
-        drop(wg);
+        let mut config = wg.config.clone();
+        patch.apply(&mut config);
+        self.check_config(&config)?;
+        wg.config = config;
+        wg.meta.updated_at = UtcDateTime::now();
 
-        if let Some(old) = ao {
-            self.priv_remove_alias(key, &old)?;
-        }
+        Ok(())
+    }
 
-        if an {
-            self.priv_add_alias(&func)?;
-        }
+    fn priv_modify_alias(
+        &self,
+        key: Key<'_>,
+        modification: AliasModification,
+    ) -> Result<(), ManagerError> {
+        let func = self
+            .functions
+            .read_sync(&key, |_, func| func.clone())
+            .ok_or(ManagerError::NotFound)?;
 
-        Ok(())
+        match modification {
+            AliasModification::Add(alias) => self.priv_add_alias(&func, alias),
+            AliasModification::Remove(alias) => self.priv_remove_alias(&func, &alias),
+            AliasModification::Set(alias) => {
+                let stale: Vec<String> = func
+                    .read()
+                    .meta
+                    .version_aliases
+                    .iter()
+                    .filter(|old| alias.as_ref() != Some(*old))
+                    .cloned()
+                    .collect();
+                for old in stale {
+                    self.priv_remove_alias(&func, &old)?;
+                }
+                if let Some(alias) = alias {
+                    self.priv_add_alias(&func, alias)?;
+                }
+                Ok(())
+            }
+        }
     }
 
     async fn priv_remove_func(&self, key: Key<'_>) -> Result<(), ManagerError> {
@@ -512,72 +1682,315 @@ impl FunctionManager {
             .functions
             .remove_sync(&key)
             .ok_or(ManagerError::NotFound)?;
-        if let Some(ref alias) = func.read().meta.version_alias {
-            self.priv_remove_alias(key, alias)?;
+        let (name, aliases) = {
+            let rg = func.read();
+            (rg.meta.name.clone(), rg.meta.version_aliases.clone())
+        };
+        for alias in &aliases {
+            self.priv_unlink_alias(
+                &func,
+                Key {
+                    name: &name,
+                    version: alias,
+                },
+            );
         }
 
+        if let Some(storage) = &self.storage {
+            storage.remove(&format!("{key}/{FILE_METADATA}")).await?;
+            storage.remove(&format!("{key}/{FILE_CONFIG}")).await?;
+        }
         tokio::fs::remove_dir_all(self.root_dir.join(key.to_string())).await?;
         Ok(())
     }
 
-    fn priv_remove_alias(&self, key: Key<'_>, old_alias: &str) -> Result<(), ManagerError> {
-        // assume that the function with key is not aliased
-
-        self.functions.remove_sync(&Key {
-            name: key.name,
-            version: old_alias,
-        });
-        Ok(())
-    }
-
-    fn priv_add_alias(&self, new_aliased: &FunctionCell) -> Result<(), ManagerError> {
-        // assume that new_aliased is correctly aliased itself
+    async fn priv_rename(
+        &self,
+        from: Key<'_>,
+        to: Key<'_>,
+        is_running: impl FnOnce(Key<'_>) -> bool,
+    ) -> Result<(), ManagerError> {
+        let func = self
+            .functions
+            .read_sync(&from, |_, func| func.clone())
+            .ok_or(ManagerError::NotFound)?;
+        let (old_key, aliases) = {
+            let rg = func.read();
+            (
+                OwnedKey {
+                    name: rg.meta.name.clone(),
+                    version: rg.meta.version.clone(),
+                },
+                rg.meta.version_aliases.clone(),
+            )
+        };
+        let new_key = to.into_owned();
 
-        let nfr = new_aliased.read();
-        let alias_key = Key {
-            name: &nfr.meta.name,
-            version: nfr
-                .meta
-                .version_alias
-                .as_deref()
-                .ok_or(ManagerError::NotAliased)?,
+        // reserve new entries first, before locking the function as entries are locked before
+        // functions elsewhere
+        if let scc::hash_map::Entry::Vacant(entry) = self.functions.entry_sync(new_key.clone()) {
+            drop(entry.insert_entry(func.clone()));
+        } else {
+            return Err(ManagerError::Duplicated);
+        }
+        // alias entries stay valid if the name is unchanged
+        let mut new_alias_keys = Vec::new();
+        let unreserve = |new_alias_keys: &[OwnedKey]| {
+            self.functions.remove_sync(&new_key);
+            for alias_key in new_alias_keys {
+                self.functions.remove_sync(alias_key);
+            }
         };
+        if new_key.name != old_key.name {
+            for alias in &aliases {
+                let alias_key = OwnedKey {
+                    name: new_key.name.clone(),
+                    version: alias.clone(),
+                };
+                if self
+                    .functions
+                    .insert_sync(alias_key.clone(), func.clone())
+                    .is_err()
+                {
+                    unreserve(&new_alias_keys);
+                    return Err(ManagerError::Duplicated);
+                }
+                new_alias_keys.push(alias_key);
+            }
+        }
 
-        // update alias entry
-        if let Some(mut entry_alias) = self.functions.get_sync(&alias_key) {
-            *entry_alias = new_aliased.clone();
-            let name = alias_key.name.to_owned();
+        let old_dir = self.root_dir.join(old_key.to_string());
+        let new_dir = self.root_dir.join(new_key.to_string());
+        let moved = {
+            let mut wg = func.write();
+            if wg.meta.name != old_key.name || wg.meta.version != old_key.version {
+                // renamed by someone else meanwhile
+                Err(ManagerError::NotFound)
+            } else if is_running(old_key.as_ref()) {
+                Err(ManagerError::Running)
+            } else {
+                // moved synchronously to keep the function locked, which is a single syscall
+                std::fs::rename(&old_dir, &new_dir)
+                    .map(|()| {
+                        wg.meta.name.clone_from(&new_key.name);
+                        wg.meta.version.clone_from(&new_key.version);
+                        wg.meta.updated_at = UtcDateTime::now();
+                        (
+                            serde_json::to_vec_pretty(&wg.meta),
+                            serde_json::to_vec_pretty(&wg.config),
+                        )
+                    })
+                    .map_err(ManagerError::from)
+            }
+        };
+        let (meta, config) = match moved {
+            Ok(serialized) => serialized,
+            Err(err) => {
+                unreserve(&new_alias_keys);
+                return Err(err);
+            }
+        };
+        self.functions.remove_sync(&old_key);
+        if !new_alias_keys.is_empty() {
+            for alias in &aliases {
+                self.priv_unlink_alias(
+                    &func,
+                    Key {
+                        name: &old_key.name,
+                        version: alias,
+                    },
+                );
+            }
+        }
 
-            // forbid potential deadlocks
-            drop(nfr);
+        // metadata moved along with the directory still holds the old key, which must never be
+        // loaded back
+        if let Some(storage) = &self.storage {
+            storage
+                .write(&format!("{new_key}/{FILE_METADATA}"), meta?)
+                .await?;
+            storage
+                .write(&format!("{new_key}/{FILE_CONFIG}"), config?)
+                .await?;
+            storage
+                .remove(&format!("{old_key}/{FILE_METADATA}"))
+                .await?;
+            storage.remove(&format!("{old_key}/{FILE_CONFIG}")).await?;
+        }
+        Ok(())
+    }
 
-            let old_key = OwnedKey {
+    fn priv_add_alias(&self, func: &FunctionCell, alias: String) -> Result<(), ManagerError> {
+        let name = {
+            let rg = func.read();
+            if rg.meta.version_aliases.contains(&alias) {
+                return Ok(());
+            }
+            rg.meta.name.clone()
+        };
+        self.priv_link_alias(
+            func,
+            OwnedKey {
                 name,
-                version: entry_alias.read().meta.version.clone(),
-            };
+                version: alias,
+            },
+        )
+    }
 
-            drop(entry_alias);
+    fn priv_remove_alias(&self, func: &FunctionCell, alias: &str) -> Result<(), ManagerError> {
+        let name = {
+            let mut wg = func.write();
+            if !wg.meta.version_aliases.remove(alias) {
+                return Err(ManagerError::NotAliased);
+            }
+            wg.meta.updated_at = UtcDateTime::now();
+            wg.meta.name.clone()
+        };
+        self.priv_unlink_alias(
+            func,
+            Key {
+                name: &name,
+                version: alias,
+            },
+        );
+        Ok(())
+    }
 
-            // remove old entry's alias
-            if let Some(old) = self.functions.read_sync(&old_key, |_, f| f.clone()) {
-                old.write().meta.version_alias = None;
+    /// Points the entry of given alias to given function and records the alias in the function,
+    /// taking the alias over from the function previously holding it.
+    fn priv_link_alias(
+        &self,
+        func: &FunctionCell,
+        alias_key: OwnedKey,
+    ) -> Result<(), ManagerError> {
+        // recorded while the entry is still held, so the alias is never linked without being
+        // recorded, and the function is never renamed meanwhile
+        let record = || {
+            let mut wg = func.write();
+            if wg.meta.name != alias_key.name {
+                return Err(ManagerError::NotFound);
             }
-        }
+            if wg.meta.version_aliases.insert(alias_key.version.clone()) {
+                wg.meta.updated_at = UtcDateTime::now();
+            }
+            Ok(())
+        };
+        let previous = match self.functions.entry_sync(alias_key.clone()) {
+            scc::hash_map::Entry::Vacant(entry) => {
+                record()?;
+                drop(entry.insert_entry(func.clone()));
+                None
+            }
+            scc::hash_map::Entry::Occupied(mut entry) => {
+                // versions are never shadowed by aliases
+                if entry.get().read().meta.version == alias_key.version {
+                    return Err(ManagerError::Duplicated);
+                }
+                record()?;
+                Some(std::mem::replace(entry.get_mut(), func.clone()))
+            }
+        };
 
+        // forbid potential deadlocks by locking after the entry is released
+        if let Some(previous) = previous.filter(|previous| !Arc::ptr_eq(previous, func)) {
+            let mut wg = previous.write();
+            wg.meta.version_aliases.remove(&alias_key.version);
+            wg.meta.updated_at = UtcDateTime::now();
+        }
         Ok(())
     }
 
+    /// Removes the entry of given alias if it still points to given function.
+    fn priv_unlink_alias(&self, func: &FunctionCell, alias_key: Key<'_>) {
+        self.functions
+            .remove_if_sync(&alias_key, |entry| Arc::ptr_eq(entry, func));
+    }
+
     async fn priv_write_contents<R>(
         &self,
         key: Key<'_>,
         tarball: &mut Tar<R>,
+        charge: impl FnMut(u64) -> bool,
     ) -> Result<(), ManagerError>
     where
         R: AsyncRead + Unpin,
     {
         let path = self.contents_path(key);
         tokio::fs::create_dir_all(&path).await?;
-        tarball.unpack(path).await?;
+        unpack_charged(tarball, &path, charge).await
+    }
+
+    async fn priv_read_embedded_config(
+        &self,
+        key: Key<'_>,
+    ) -> Result<Option<Config>, ManagerError> {
+        match tokio::fs::read(self.contents_path(key).join(FILE_EMBEDDED_CONFIG)).await {
+            Ok(raw) => Ok(Some(serde_json::from_slice(&raw)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Returns the directory of the function of given key or alias.
+    fn priv_dir(&self, key: Key<'_>) -> Option<PathBuf> {
+        self.functions.read_sync(&key, |_, func| {
+            let rg = func.read();
+            self.root_dir.join(
+                Key {
+                    name: &rg.meta.name,
+                    version: &rg.meta.version,
+                }
+                .to_string(),
+            )
+        })
+    }
+
+    async fn priv_stage_contents<R>(
+        &self,
+        key: Key<'_>,
+        tarball: &mut Tar<R>,
+        charge: impl FnMut(u64) -> bool,
+    ) -> Result<PathBuf, ManagerError>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let dir = self.priv_dir(key).ok_or(ManagerError::NotFound)?;
+        let staging = dir.join(DIR_CONTENTS_STAGING);
+        let retired = dir.join(DIR_CONTENTS_RETIRED);
+
+        // leftovers from interrupted replacements
+        for path in [&staging, &retired] {
+            if tokio::fs::try_exists(path).await? {
+                tokio::fs::remove_dir_all(path).await?;
+            }
+        }
+
+        tokio::fs::create_dir_all(&staging).await?;
+        if let Err(err) = unpack_charged(tarball, &staging, charge).await {
+            drop(tokio::fs::remove_dir_all(&staging).await);
+            return Err(err);
+        }
+        Ok(staging)
+    }
+
+    async fn priv_swap_staged_contents(&self, key: Key<'_>) -> Result<(), ManagerError> {
+        let dir = self.priv_dir(key).ok_or(ManagerError::NotFound)?;
+        let contents = dir.join(DIR_CONTENTS);
+        let staging = dir.join(DIR_CONTENTS_STAGING);
+        let retired = dir.join(DIR_CONTENTS_RETIRED);
+        if !tokio::fs::try_exists(&staging).await? {
+            return Err(ManagerError::NotFound);
+        }
+
+        if tokio::fs::try_exists(&contents).await? {
+            tokio::fs::rename(&contents, &retired).await?;
+        }
+        tokio::fs::rename(&staging, &contents).await?;
+        drop(
+            tokio::fs::remove_dir_all(&retired)
+                .await
+                .inspect_err(|e| tracing::error!("failed to remove retired contents: {e}")),
+        );
         Ok(())
     }
 
@@ -586,16 +1999,19 @@ impl FunctionManager {
         key: Key<'_>,
         init_group: Option<user::Group>,
     ) -> Result<(), ManagerError> {
+        let now = UtcDateTime::now();
         let func = Function {
             meta: Metadata {
                 name: key.name.to_owned(),
                 version: key.version.to_owned(),
+                created_at: now,
+                updated_at: now,
                 ..Default::default()
             },
 
             config: Config {
                 group: init_group,
-                ..Default::default()
+                ..self.default_config.clone()
             },
         };
 
@@ -613,12 +2029,64 @@ impl FunctionManager {
     }
 }
 
+/// Unpacks given tarball into given directory, passing the size of each entry to `charge` before
+/// it's written and aborting once refused.
+///
+/// Sizes are the ones entries take once unpacked, so limits hold no matter how the tarball is
+/// compressed in transit.
+async fn unpack_charged<R>(
+    tarball: &mut Tar<R>,
+    dst: &Path,
+    mut charge: impl FnMut(u64) -> bool,
+) -> Result<(), ManagerError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut entries = tarball.entries()?;
+    while let Some(entry) = entries.next().await {
+        let mut entry = entry?;
+        if !charge(entry.header().size()?) {
+            return Err(ManagerError::UnpackLimitExceeded);
+        }
+        entry.unpack_in(dst).await?;
+    }
+    Ok(())
+}
+
+/// Returns the total size in bytes of regular files under given directory, without following
+/// symbolic links. Missing directories are considered empty.
+///
+/// # Errors
+///
+/// Returns an error if the directory could not be walked.
+pub async fn dir_size(path: &Path) -> std::io::Result<u64> {
+    let mut size = 0;
+    let mut dirs = vec![path.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = match tokio::fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            // never follows symbolic links
+            let metadata = entry.metadata().await?;
+            if metadata.is_dir() {
+                dirs.push(entry.path());
+            } else if metadata.is_file() {
+                size += metadata.len();
+            }
+        }
+    }
+    Ok(size)
+}
+
 /// Errors that may occur when working with a [`FunctionManager`].
 #[derive(Debug, thiserror::Error)]
 #[allow(missing_docs)]
 #[non_exhaustive]
 pub enum ManagerError {
-    #[error("the given function is not aliased")]
+    #[error("the given function does not hold the alias")]
     NotAliased,
     #[error("I/O error occurred: {0}")]
     Io(#[from] std::io::Error),
@@ -630,6 +2098,19 @@ pub enum ManagerError {
     Duplicated,
     #[error("the function holding the given key (or alias) does not exist")]
     NotFound,
+    #[error("too many entries in `{field}` of the configuration, at most {max} are allowed")]
+    TooManyEntries { field: &'static str, max: usize },
+    #[error(
+        "environment variable file {} is outside the contents and allowed directories",
+        .0.display()
+    )]
+    EnvFileNotAllowed(PathBuf),
+    #[error("maximum count of replicas of autoscaling is less than the minimum one")]
+    InvalidAutoscale,
+    #[error("unpacked contents exceed the limit")]
+    UnpackLimitExceeded,
+    #[error("the function is running")]
+    Running,
 }
 
 /// Errors that may occur when parsing a function key from string.
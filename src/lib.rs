@@ -1,12 +1,18 @@
 //! Abstraction and implementation for FASS platform web services.
 
 pub mod func;
+pub mod lifecycle;
+pub mod logs;
+pub mod migrate;
+pub mod rfc3339;
 pub mod sandbox;
+pub mod storage;
+pub mod upload;
 pub mod user;
 
 pub mod os;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[doc(hidden)]
 #[repr(transparent)]
 pub struct NonExhaustiveMarker(()); // intended for struct constructors to be used normally
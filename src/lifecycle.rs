@@ -0,0 +1,160 @@
+//! Observation of lifecycle events of functions, and hooks into traffic relayed to them.
+
+use axum::{
+    body::Body,
+    http::{self, Uri},
+};
+use hyper_util::client::legacy::{Client, connect::HttpConnector};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::func::Key;
+
+/// Observer of lifecycle events of functions, for embedders reacting to them programmatically.
+///
+/// All methods do nothing by default. They are invoked synchronously where the events occur, thus
+/// should return quickly and spawn tasks for expensive work instead.
+pub trait LifecycleObserver: std::fmt::Debug + Send + Sync {
+    /// Called after a function is uploaded.
+    #[inline]
+    fn on_upload(&self, _key: Key<'_>) {}
+
+    /// Called after a function is deployed and public traffic is routed to it.
+    ///
+    /// The key is of the version, even if deployed through an alias.
+    #[inline]
+    fn on_deploy(&self, _key: Key<'_>) {}
+
+    /// Called after a function is stopped by the platform.
+    #[inline]
+    fn on_stop(&self, _key: Key<'_>) {}
+
+    /// Called after a function exits on its own.
+    #[inline]
+    fn on_crash(&self, _key: Key<'_>) {}
+}
+
+/// Observer posting lifecycle events of functions to a webhook.
+///
+/// Each event is posted as a JSON object like `{"event":"deploy","name":"test","version":"a0"}` in
+/// the background, where `event` is one of `upload`, `deploy`, `stop` and `crash`. Failed
+/// deliveries are logged without retrying.
+#[derive(Debug)]
+pub struct WebhookObserver {
+    uri: Uri,
+    client: Client<HttpConnector, Body>,
+}
+
+impl WebhookObserver {
+    /// Creates a new observer posting events to given URI through given client.
+    #[inline]
+    pub fn new(uri: Uri, client: Client<HttpConnector, Body>) -> Self {
+        Self { uri, client }
+    }
+
+    fn post(&self, event: &'static str, key: Key<'_>) {
+        let body = serde_json::json!({
+            "event": event,
+            "name": key.name,
+            "version": key.version,
+        });
+        let request = http::Request::post(self.uri.clone())
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .expect("webhook request should be valid");
+        let response = self.client.request(request);
+        let key = key.into_owned();
+        tokio::spawn(async move {
+            match response.await {
+                Ok(res) if !res.status().is_success() => tracing::warn!(
+                    "lifecycle webhook responded {} to {event} event of {key}",
+                    res.status()
+                ),
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::warn!(
+                        "failed to post {event} event of {key} to lifecycle webhook: {e}"
+                    )
+                }
+            }
+        });
+    }
+}
+
+impl LifecycleObserver for WebhookObserver {
+    #[inline]
+    fn on_upload(&self, key: Key<'_>) {
+        self.post("upload", key);
+    }
+
+    #[inline]
+    fn on_deploy(&self, key: Key<'_>) {
+        self.post("deploy", key);
+    }
+
+    #[inline]
+    fn on_stop(&self, key: Key<'_>) {
+        self.post("stop", key);
+    }
+
+    #[inline]
+    fn on_crash(&self, key: Key<'_>) {
+        self.post("crash", key);
+    }
+}
+
+/// Direction of a websocket message relayed by the proxy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WsDirection {
+    /// From the client to the function.
+    ClientToFunction,
+    /// From the function to the client.
+    FunctionToClient,
+}
+
+/// Hook into websocket messages relayed between clients and functions, for embedders auditing or
+/// filtering them.
+///
+/// Connections are relayed without any overhead if no hook is configured.
+pub trait WsHook: std::fmt::Debug + Send + Sync {
+    /// Called with each message relayed in given direction, returning the message to be relayed
+    /// in place of it, or `None` for dropping it.
+    ///
+    /// The key is of the version, even if connected through an alias. Close codes are sanitized
+    /// after this hook, thus reserved ones are never relayed.
+    fn on_message(&self, key: Key<'_>, direction: WsDirection, message: Message)
+    -> Option<Message>;
+}
+
+/// Hook auditing websocket messages relayed between clients and functions by logging their kinds
+/// and sizes, without modifying them.
+///
+/// Payloads are never logged.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AuditWsHook;
+
+impl WsHook for AuditWsHook {
+    fn on_message(
+        &self,
+        key: Key<'_>,
+        direction: WsDirection,
+        message: Message,
+    ) -> Option<Message> {
+        let kind = match &message {
+            Message::Text(_) => "text",
+            Message::Binary(_) => "binary",
+            Message::Ping(_) => "ping",
+            Message::Pong(_) => "pong",
+            Message::Close(_) => "close",
+            Message::Frame(_) => "frame",
+        };
+        tracing::info!(
+            function = %key,
+            ?direction,
+            kind,
+            bytes = message.len(),
+            "websocket message relayed"
+        );
+        Some(message)
+    }
+}
@@ -0,0 +1,299 @@
+//! Capturing of outputs from functions.
+
+use std::{
+    collections::VecDeque,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use time::UtcDateTime;
+use tokio::io::{
+    AsyncBufReadExt as _, AsyncRead, AsyncReadExt as _, AsyncSeekExt as _, AsyncWriteExt as _,
+    BufReader,
+};
+
+/// Output stream a [`LogLine`] is captured from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[allow(clippy::exhaustive_enums)] // there are only two output streams
+pub enum LogStream {
+    /// The standard output.
+    Stdout,
+    /// The standard error.
+    Stderr,
+}
+
+/// A line of output captured from a function.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogLine {
+    /// Unix timestamp in milliseconds the line was captured at.
+    pub ts: i64,
+    /// Stream this line was captured from.
+    pub stream: LogStream,
+    /// Content of this line, without the trailing line break.
+    pub line: String,
+}
+
+/// Maximum length in bytes of a captured line, beyond which the line is split.
+pub const MAX_LINE_BYTES: u64 = 64 * 1024;
+
+/// Ring buffer of lines captured from a function, dropping the oldest lines once full.
+#[derive(Debug)]
+pub struct LogBuffer {
+    lines: Mutex<VecDeque<LogLine>>,
+    capacity: usize,
+}
+
+impl LogBuffer {
+    /// Creates a new buffer holding at most `capacity` lines.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            lines: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    /// Pushes a line into this buffer, timestamped with the current instant.
+    pub fn push(&self, stream: LogStream, line: String) {
+        self.push_line(LogLine {
+            ts: now_millis(),
+            stream,
+            line,
+        });
+    }
+
+    fn push_line(&self, line: LogLine) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut lines = self.lines.lock();
+        if lines.len() >= self.capacity {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    /// Returns a copy of currently buffered lines from the oldest to the newest.
+    pub fn snapshot(&self) -> Vec<LogLine> {
+        self.lines.lock().iter().cloned().collect()
+    }
+
+    /// Captures lines from given reader into this buffer until it reaches EOF, writing them
+    /// into given log files as well if any.
+    ///
+    /// Invalid UTF-8 sequences are replaced with `U+FFFD`, and lines longer than
+    /// [`MAX_LINE_BYTES`] are split. Writing into log files stops on failures, without affecting
+    /// this buffer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if failed to read from the reader.
+    pub async fn capture<R>(
+        &self,
+        reader: R,
+        stream: LogStream,
+        mut files: Option<&LogFiles>,
+    ) -> std::io::Result<()>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let mut reader = BufReader::new(reader);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            if (&mut reader)
+                .take(MAX_LINE_BYTES)
+                .read_until(b'\n', &mut buf)
+                .await?
+                == 0
+            {
+                return Ok(());
+            }
+            let line = buf.strip_suffix(b"\n").unwrap_or(&buf);
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            let line = LogLine {
+                ts: now_millis(),
+                stream,
+                line: String::from_utf8_lossy(line).into_owned(),
+            };
+            if let Some(f) = files
+                && let Err(err) = f.write(&line).await
+            {
+                tracing::warn!("failed to write outputs into log files, stopped writing: {err}");
+                files = None;
+            }
+            self.push_line(line);
+        }
+    }
+}
+
+/// Writer of captured lines into files under a directory, as JSON Lines of [`LogLine`].
+///
+/// Files are named after the Unix timestamp in milliseconds they're created at, like
+/// `1760600000000.log`, and rotated once exceeding the size limit. The oldest files are removed on
+/// rotating once exceeding the count limit.
+#[derive(Debug)]
+pub struct LogFiles {
+    dir: PathBuf,
+    max_bytes: u64,
+    max_files: Option<NonZeroUsize>,
+    /// The file being written and its size.
+    current: tokio::sync::Mutex<Option<(tokio::fs::File, u64)>>,
+}
+
+impl LogFiles {
+    /// Creates a new writer of files under given directory, each no larger than `max_bytes`
+    /// unless a single line exceeds it, keeping at most `max_files` files if specified.
+    ///
+    /// Files are created lazily on the first line.
+    pub fn new(dir: PathBuf, max_bytes: u64, max_files: Option<NonZeroUsize>) -> Self {
+        Self {
+            dir,
+            max_bytes,
+            max_files,
+            current: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Appends a line to the current file, rotating to a new file first if it doesn't fit.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if failed to create or write to the file.
+    pub async fn write(&self, line: &LogLine) -> std::io::Result<()> {
+        let mut raw = serde_json::to_vec(line).map_err(std::io::Error::other)?;
+        raw.push(b'\n');
+        let len = raw.len() as u64;
+
+        let mut current = self.current.lock().await;
+        let (mut file, size) = match current.take() {
+            Some((file, size)) if size == 0 || size + len <= self.max_bytes => (file, size),
+            _ => {
+                let file = self.create().await?;
+                self.remove_oldest().await;
+                (file, 0)
+            }
+        };
+        file.write_all(&raw).await?;
+        *current = Some((file, size + len));
+        Ok(())
+    }
+
+    async fn create(&self) -> std::io::Result<tokio::fs::File> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let mut ts = now_millis();
+        loop {
+            match tokio::fs::OpenOptions::new()
+                .append(true)
+                .create_new(true)
+                .open(self.dir.join(format!("{ts}.log")))
+                .await
+            {
+                // rotated more than once in a millisecond
+                Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => ts += 1,
+                result => return result,
+            }
+        }
+    }
+
+    /// Removes the oldest files beyond the count limit, including ones left by previous runs.
+    async fn remove_oldest(&self) {
+        let Some(max_files) = self.max_files else {
+            return;
+        };
+        let mut files = match list_files(&self.dir).await {
+            Ok(files) => files,
+            Err(err) => {
+                tracing::warn!("failed to list log files for removal: {err}");
+                return;
+            }
+        };
+        files.sort_unstable_by_key(|(ts, _)| *ts);
+        let excess = files.len().saturating_sub(max_files.get());
+        for (_, path) in files.drain(..excess) {
+            if let Err(err) = tokio::fs::remove_file(&path).await {
+                tracing::warn!("failed to remove log file {}: {err}", path.display());
+            }
+        }
+    }
+}
+
+/// Lists log files written by [`LogFiles`] under given directory along with their timestamps, in
+/// arbitrary order.
+///
+/// Returns an empty list if the directory doesn't exist.
+async fn list_files(dir: &Path) -> std::io::Result<Vec<(i64, PathBuf)>> {
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let mut files = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name();
+        if let Some(ts) = name
+            .to_str()
+            .and_then(|name| name.strip_suffix(".log"))
+            .and_then(|ts| ts.parse::<i64>().ok())
+        {
+            files.push((ts, entry.path()));
+        }
+    }
+    Ok(files)
+}
+
+/// Reads the last `count` lines of the most recent log file written by [`LogFiles`] under given
+/// directory, from the oldest to the newest.
+///
+/// Returns an empty list if there's no log file. Malformed lines are skipped. The file is read
+/// backwards in chunks until enough lines are found, so large files are never read as a whole.
+///
+/// # Errors
+///
+/// Returns an error if failed to read the directory or the file.
+pub async fn tail_files(dir: &Path, count: usize) -> std::io::Result<Vec<LogLine>> {
+    let Some((_, path)) = list_files(dir).await?.into_iter().max_by_key(|(ts, _)| *ts) else {
+        return Ok(Vec::new());
+    };
+
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut pos = file.metadata().await?.len();
+    let mut lines = Vec::new();
+    // leading bytes of the chunks read so far, which may continue in the previous chunk
+    let mut partial = Vec::new();
+    while lines.len() < count && pos > 0 {
+        let len = pos.min(MAX_LINE_BYTES);
+        pos -= len;
+        let mut chunk = vec![0; len as usize];
+        file.seek(std::io::SeekFrom::Start(pos)).await?;
+        file.read_exact(&mut chunk).await?;
+        chunk.append(&mut partial);
+
+        let complete = if pos == 0 {
+            &chunk[..]
+        } else if let Some(i) = chunk.iter().position(|b| *b == b'\n') {
+            partial = chunk[..i].to_vec();
+            &chunk[i + 1..]
+        } else {
+            partial = chunk;
+            continue;
+        };
+        let remaining = count - lines.len();
+        lines.extend(
+            complete
+                .split(|b| *b == b'\n')
+                .rev()
+                .filter_map(|line| serde_json::from_slice::<LogLine>(line).ok())
+                .take(remaining),
+        );
+    }
+    lines.reverse();
+    Ok(lines)
+}
+
+#[inline]
+fn now_millis() -> i64 {
+    (UtcDateTime::now().unix_timestamp_nanos() / 1_000_000) as i64
+}
@@ -2,51 +2,140 @@
 
 use std::{
     borrow::Cow,
+    collections::HashSet,
     net::{IpAddr, SocketAddr},
-    path::PathBuf,
-    sync::Arc,
+    num::{NonZeroU64, NonZeroUsize},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
 };
 
 use axum::{
-    Router, ServiceExt as _,
+    Router,
     body::Body,
+    extract::{MatchedPath, Request},
     http::{self, StatusCode},
     middleware,
     response::IntoResponse,
 };
+use base64::Engine as _;
 use bitflags::bitflags;
 use clap::Parser as _;
 use hyper_util::client;
 use parking_lot::Mutex;
-use rand::{SeedableRng as _, rngs::StdRng};
+use rand::{Rng as _, SeedableRng as _, rngs::StdRng};
 use serde::Serialize;
+use tokio::task::JoinSet;
 use tokio_tungstenite::tungstenite;
 use tower_layer::Layer as _;
 use tracing_subscriber::EnvFilter;
 use yfass::{
     func::{self, FunctionManager, OwnedKey},
-    os,
-    sandbox::{self, Sandbox},
+    lifecycle::{AuditWsHook, LifecycleObserver, WebhookObserver, WsHook},
+    logs::{LogBuffer, LogFiles, LogStream},
+    migrate, os,
+    sandbox::{self, LogSink, Sandbox},
+    upload,
     user::{self, Permission, UserManager},
 };
 
 mod proxy;
 mod service;
+#[cfg(feature = "upstream-tls")]
+mod tls;
+#[cfg(feature = "tls")]
+mod tls_listener;
 
 #[derive(Debug)]
 struct LocalCx {
     funcs: FunctionManager,
-    proxies: scc::HashIndex<String, http::uri::Authority>,
+    uploads: upload::UploadSessions,
+    /// Permits of unpacking uploaded archives, bounding simultaneous disk writes.
+    upload_permits: tokio::sync::Semaphore,
+    /// Timeout of unpacking an uploaded archive, bounding stalled uploads holding permits.
+    upload_timeout: tokio::time::Duration,
+    /// Maximum size of an uploaded archive.
+    max_upload_bytes: Option<u64>,
+    /// Disk usage of users with uploads in progress, see [`QuotaReservation`].
+    quota_ledgers: scc::HashMap<String, QuotaLedger>,
+    /// Timeout of running the build command of a function.
+    build_timeout: tokio::time::Duration,
+    proxies: scc::HashIndex<String, proxy::Route>,
     users: UserManager,
 
     sandbox: os::SandboxImpl,
-    handles: scc::HashMap<OwnedKey, os::SandboxHandleImpl>,
+    handles: scc::HashMap<OwnedKey, Instance>,
+    /// Functions whose last deploy failed their readiness probe, or which exited on their own.
+    failed: scc::HashSet<OwnedKey>,
+    /// Lazy deployments in progress by host prefixes.
+    lazy_deploys: scc::HashMap<String, Arc<LazyDeploy>>,
+    /// Count of restarts of functions performed by the platform.
+    restarts: AtomicU64,
+    save_state: Mutex<SaveState>,
+    /// Count of consecutive failed saves failing the readiness probe.
+    readiness_max_save_failures: Option<NonZeroU64>,
+    /// Duration in seconds of saves failing the readiness probe.
+    readiness_max_failing_secs: Option<NonZeroU64>,
+    /// Held while saving data, as saving on demand may overlap with the periodic one.
+    saving: tokio::sync::Mutex<()>,
+    /// Whether data is loaded and the listener is bound, cleared once shutting down.
+    ready: AtomicBool,
 
     client: client::legacy::Client<client::legacy::connect::HttpConnector, Body>,
+    client_no_keepalive: client::legacy::Client<client::legacy::connect::HttpConnector, Body>,
+    #[cfg(feature = "upstream-tls")]
+    client_tls: client::legacy::Client<
+        hyper_rustls::HttpsConnector<client::legacy::connect::HttpConnector>,
+        Body,
+    >,
+    #[cfg(feature = "upstream-tls")]
+    upstream_tls: Arc<rustls::ClientConfig>,
+    upstream_connect_timeout: tokio::time::Duration,
+    /// Timeout of functions responding to proxied requests with headers.
+    proxy_timeout: Option<tokio::time::Duration>,
+    drain_timeout: tokio::time::Duration,
+    max_shutdown_grace: tokio::time::Duration,
+    slow_response_threshold: Option<tokio::time::Duration>,
+    /// Timeout of Server-Sent Events streams staying idle.
+    sse_idle_timeout: Option<tokio::time::Duration>,
+    /// Maximum size of bodies of requests proxied to functions.
+    max_proxy_body_bytes: Option<u64>,
+    /// Maximum size of a single frame of relayed WebSocket connections.
+    max_ws_frame_bytes: usize,
+    /// Maximum size of a message of relayed WebSocket connections.
+    max_ws_message_bytes: usize,
+    /// Interval of pinging both sides of relayed WebSocket connections.
+    ws_ping_interval: Option<tokio::time::Duration>,
+    ws_pong_timeout: tokio::time::Duration,
     host_with_dot_prefixed: String,
     host_port_with_dot_prefixed: String,
+    /// Whether connections from clients are terminated with TLS by the platform.
+    serve_tls: bool,
+    routing: Routing,
 
     rng: Mutex<StdRng>,
+    log_buffer_lines: usize,
+    log_file_max_bytes: u64,
+    log_file_max_count: Option<NonZeroUsize>,
+    default_token_duration: time::Duration,
+    upstream_retries: u32,
+    buffer_retryable_bodies: usize,
+    strip_response_headers: Box<[http::HeaderName]>,
+    add_response_headers: Box<[ResponseHeader]>,
+    auth_schemes: Box<[AuthScheme]>,
+    quiet_proxy_404: bool,
+    reserved_labels: Box<[String]>,
+    max_function_name_length: usize,
+
+    api_allowlist: Box<[ApiAllowRule]>,
+
+    /// Observer of lifecycle events of functions.
+    observer: Option<Box<dyn LifecycleObserver>>,
+    /// Hook into relayed websocket messages.
+    ws_hook: Option<Arc<dyn WsHook>>,
 }
 
 fn main() {
@@ -66,15 +155,59 @@ fn main() {
         );
     }
 
-    let rt = tokio::runtime::Builder::new_multi_thread()
+    let args = Args::parse();
+    // read before any other thread is spawned
+    let root_token = read_root_token(&args);
+
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    if let Some(worker_threads) = args.worker_threads {
+        builder.worker_threads(worker_threads.get());
+    }
+    let rt = builder
         .enable_all()
         .build()
         .expect("failed to create tokio runtime");
-    rt.block_on(main_async())
+    rt.block_on(main_async(args, root_token));
+    // do not wait for blocking tasks that may be stuck after a timed-out shutdown
+    rt.shutdown_background();
 }
 
-async fn main_async() {
-    let args = Args::parse();
+/// Environment variable of the token of the root account.
+const ENV_ROOT_TOKEN: &str = "YFASS_ROOT_TOKEN";
+
+/// Reads the configured token of the root account, removing it from the environment so that
+/// functions never inherit it.
+fn read_root_token(args: &Args) -> Option<String> {
+    let from_env = std::env::var(ENV_ROOT_TOKEN).ok();
+    // SAFETY: called before spawning any other thread
+    unsafe { std::env::remove_var(ENV_ROOT_TOKEN) };
+    let raw = match (&args.root_token_file, from_env) {
+        (Some(path), from_env) => {
+            if let Some(token) = from_env {
+                wipe_token(token);
+            }
+            std::fs::read_to_string(path).expect("failed to read root token file")
+        }
+        (None, from_env) => from_env?,
+    };
+    let token = raw.trim().to_owned();
+    wipe_token(raw);
+    assert!(!token.is_empty(), "configured root token is empty");
+    Some(token)
+}
+
+/// Overwrites given plaintext token before freeing it, so it never lingers in freed memory.
+fn wipe_token(mut token: String) {
+    // SAFETY: zeros are valid UTF-8
+    for byte in unsafe { token.as_bytes_mut() } {
+        // SAFETY: the pointer comes from a valid mutable reference, and volatile writes are never
+        // elided as dead stores
+        unsafe { std::ptr::write_volatile(byte, 0) };
+    }
+    std::sync::atomic::compiler_fence(Ordering::SeqCst);
+}
+
+async fn main_async(args: Args, root_token: Option<String>) {
     let addr = SocketAddr::new(
         args.addr
             .unwrap_or(IpAddr::V4(std::net::Ipv4Addr::LOCALHOST)),
@@ -85,30 +218,199 @@ async fn main_async() {
 
     let mut rng = StdRng::from_os_rng();
 
-    let client = client::legacy::Builder::new(hyper_util::rt::TokioExecutor::new())
+    let upstream_connect_timeout = tokio::time::Duration::from_secs(args.upstream_connect_timeout);
+    let mut connector = client::legacy::connect::HttpConnector::new();
+    connector.set_connect_timeout(Some(upstream_connect_timeout));
+    let mut client_builder = client::legacy::Builder::new(hyper_util::rt::TokioExecutor::new());
+    client_builder
         .http1_ignore_invalid_headers_in_responses(true)
         .http1_preserve_header_case(true)
-        .set_host(false)
-        .build(client::legacy::connect::HttpConnector::new());
+        .set_host(false);
+    let client = client_builder.build(connector.clone());
+    let observer = args.lifecycle_webhook.clone().map(|uri| {
+        Box::new(WebhookObserver::new(uri, client.clone())) as Box<dyn LifecycleObserver>
+    });
+    #[cfg(feature = "upstream-tls")]
+    let upstream_tls = {
+        let verification = if args.upstream_insecure {
+            tracing::warn!(
+                "certificates of HTTPS functions are NOT verified, connections to them could be intercepted. never use `--upstream-insecure` in production"
+            );
+            tls::Verification::Insecure
+        } else if let Some(path) = &args.upstream_ca {
+            tls::Verification::CustomCa(path)
+        } else {
+            tls::Verification::Native
+        };
+        tls::client_config(verification).expect("failed to build TLS client configuration")
+    };
+    #[cfg(feature = "tls")]
+    let tls_server = args
+        .tls_cert
+        .as_deref()
+        .zip(args.tls_key.as_deref())
+        .map(|(cert, key)| {
+            tls_listener::server_config(cert, key)
+                .expect("failed to build TLS server configuration")
+        });
+    #[cfg(feature = "upstream-tls")]
+    let client_tls = {
+        let mut connector = connector.clone();
+        // schemes are checked by the connector
+        connector.enforce_http(false);
+        client_builder.build(
+            hyper_rustls::HttpsConnectorBuilder::new()
+                .with_tls_config((*upstream_tls).clone())
+                .https_only()
+                .enable_http1()
+                .wrap_connector(connector),
+        )
+    };
+    // for functions misbehaving with connection reuse
+    let client_no_keepalive = client_builder.pool_max_idle_per_host(0).build(connector);
+
+    // contents of functions are unpacked into a temporary directory in in-memory mode
+    let in_memory_dir = args
+        .in_memory
+        .then(|| std::env::temp_dir().join(format!("yfass-{}", user::gen_token(&mut rng))));
+
+    let default_config = args
+        .default_config
+        .as_ref()
+        .map_or_else(func::Config::default, |path| {
+            let raw = std::fs::read(path).expect("failed to read default function config");
+            serde_json::from_slice(&raw).expect("failed to parse default function config")
+        });
+
+    let users = if args.in_memory {
+        UserManager::new_in_memory(&mut rng, root_token.as_deref())
+    } else {
+        UserManager::new(&mut rng, &root_dir, root_token.as_deref())
+    };
+    // only its digest is kept by the manager
+    if let Some(token) = root_token {
+        wipe_token(token);
+    }
 
     let cx = Arc::new(LocalCx {
-        funcs: FunctionManager::new(&root_dir),
-        users: UserManager::new(&mut rng, &root_dir),
+        funcs: in_memory_dir
+            .as_ref()
+            .map_or_else(
+                || FunctionManager::new(&root_dir),
+                FunctionManager::new_in_memory,
+            )
+            .with_default_config(default_config)
+            .with_config_limits(func::ConfigLimits {
+                max_envs: args.max_config_envs,
+                max_ro_entries: args.max_config_ro_entries,
+                max_syscall_filter: args.max_config_syscall_filter,
+                ..func::ConfigLimits::UNLIMITED
+            })
+            .with_env_file_dirs(args.env_file_dir.iter().cloned()),
+        uploads: upload::UploadSessions::new(
+            in_memory_dir
+                .as_ref()
+                .unwrap_or(&root_dir)
+                .join(DIR_UPLOADS),
+            tokio::time::Duration::from_secs(args.upload_session_ttl),
+            args.max_upload_sessions_per_user,
+        ),
+        upload_permits: tokio::sync::Semaphore::new(
+            args.max_concurrent_uploads
+                .map_or(tokio::sync::Semaphore::MAX_PERMITS, NonZeroUsize::get),
+        ),
+        upload_timeout: tokio::time::Duration::from_secs(args.upload_timeout.get()),
+        max_upload_bytes: args.max_upload_bytes,
+        quota_ledgers: scc::HashMap::new(),
+        build_timeout: tokio::time::Duration::from_secs(args.build_timeout.get()),
+        users,
         proxies: scc::HashIndex::new(),
         handles: scc::HashMap::new(),
+        lazy_deploys: scc::HashMap::new(),
+        failed: scc::HashSet::new(),
+        restarts: AtomicU64::new(0),
+        save_state: Mutex::new(SaveState::default()),
+        readiness_max_save_failures: NonZeroU64::new(args.readiness_max_save_failures),
+        readiness_max_failing_secs: NonZeroU64::new(args.readiness_max_failing_secs),
+        saving: tokio::sync::Mutex::new(()),
+        ready: AtomicBool::new(false),
         sandbox: os::SandboxImpl::default(),
         rng: Mutex::new(rng),
+        log_buffer_lines: args.log_buffer_lines,
+        log_file_max_bytes: args.log_file_max_bytes,
+        log_file_max_count: NonZeroUsize::new(args.log_file_max_count),
+        default_token_duration: time::Duration::days(args.default_token_duration),
+        upstream_retries: args.upstream_retries,
+        buffer_retryable_bodies: args.buffer_retryable_bodies,
+        strip_response_headers: args.strip_response_headers.into_boxed_slice(),
+        add_response_headers: args.add_response_headers.into_boxed_slice(),
+        auth_schemes: args.auth_scheme.into_boxed_slice(),
+        quiet_proxy_404: args.quiet_proxy_404,
+        max_function_name_length: args.max_function_name_length.get(),
+        // labels of the platform host itself are reserved as well
+        reserved_labels: args
+            .reserved_label
+            .iter()
+            .map(String::as_str)
+            .chain(host.split('.'))
+            .map(str::to_ascii_lowercase)
+            .collect(),
         client,
+        client_no_keepalive,
+        #[cfg(feature = "upstream-tls")]
+        client_tls,
+        #[cfg(feature = "upstream-tls")]
+        upstream_tls,
+        upstream_connect_timeout,
+        proxy_timeout: (args.proxy_timeout > 0)
+            .then(|| tokio::time::Duration::from_secs(args.proxy_timeout)),
+        drain_timeout: tokio::time::Duration::from_secs(args.drain_timeout),
+        max_shutdown_grace: tokio::time::Duration::from_secs(args.max_shutdown_grace),
+        slow_response_threshold: args
+            .slow_response_threshold
+            .map(tokio::time::Duration::from_millis),
+        sse_idle_timeout: args
+            .sse_idle_timeout
+            .map(|secs| tokio::time::Duration::from_secs(secs.get())),
+        max_proxy_body_bytes: args.max_proxy_body_bytes,
+        max_ws_frame_bytes: args.max_ws_frame_bytes,
+        max_ws_message_bytes: args.max_ws_message_bytes,
+        ws_ping_interval: (args.ws_ping_interval > 0)
+            .then(|| tokio::time::Duration::from_secs(args.ws_ping_interval)),
+        ws_pong_timeout: tokio::time::Duration::from_secs(args.ws_pong_timeout),
         host_with_dot_prefixed: format!(".{}", host),
         host_port_with_dot_prefixed: format!(".{}:{}", host, args.port),
+        #[cfg(feature = "tls")]
+        serve_tls: tls_server.is_some(),
+        #[cfg(not(feature = "tls"))]
+        serve_tls: false,
+        routing: args.routing,
+        api_allowlist: args.api_allow.into_boxed_slice(),
+        observer,
+        ws_hook: args
+            .ws_audit
+            .then(|| Arc::new(AuditWsHook) as Arc<dyn WsHook>),
     });
 
     cx.funcs
-        .read_from_fs()
-        .expect("failed to read functions from fs");
+        .read_from_storage()
+        .await
+        .expect("failed to read functions from storage");
     cx.users
-        .read_from_fs()
-        .expect("failed to read users from fs");
+        .read_from_storage()
+        .await
+        .expect("failed to read users from storage");
+    cx.uploads
+        .clear_fs()
+        .expect("failed to remove upload sessions of previous runs");
+
+    // failures are not fatal as the warm set is only an optimization
+    for key in &args.preload {
+        match cx.start_fn(key.as_ref()).await {
+            Ok(()) => tracing::info!("preloaded function {key}"),
+            Err(err) => tracing::error!("failed to preload function {key}: {err}"),
+        }
+    }
 
     let router = Router::new()
         // func services
@@ -116,14 +418,57 @@ async fn main_async() {
             service::func::PATH_UPLOAD,
             axum::routing::post(service::func::upload),
         )
+        .route(
+            service::func::PATH_UPLOAD_DEPLOY,
+            axum::routing::post(service::func::upload_deploy),
+        )
+        .route(
+            service::func::PATH_UPLOAD_SESSION,
+            axum::routing::post(service::func::create_upload_session)
+                .get(service::func::upload_session_status)
+                .patch(service::func::append_upload_session)
+                .delete(service::func::remove_upload_session),
+        )
+        .route(
+            service::func::PATH_UPLOAD_SESSION_COMMIT,
+            axum::routing::post(service::func::commit_upload_session),
+        )
+        .route(
+            service::func::PATH_REPLACE_CONTENTS,
+            axum::routing::put(service::func::replace_contents),
+        )
         .route(
             service::func::PATH_GET,
             axum::routing::get(service::func::get),
         )
+        .route(
+            service::func::PATH_MY_FUNCTIONS,
+            axum::routing::get(service::func::my_functions),
+        )
+        .route(
+            service::func::PATH_LIST,
+            axum::routing::get(service::func::list),
+        )
+        .route(
+            service::func::PATH_VERSIONS,
+            axum::routing::get(service::func::versions),
+        )
+        .route(
+            service::func::PATH_RAW_CONFIG,
+            axum::routing::get(service::func::raw_config),
+        )
         .route(
             service::func::PATH_OVERRIDE_CONFIG,
             axum::routing::put(service::func::override_config),
         )
+        .route(
+            service::func::PATH_PATCH_CONFIG,
+            axum::routing::patch(service::func::patch_config),
+        )
+        .route(
+            service::func::PATH_RENAME,
+            axum::routing::patch(service::func::rename),
+        )
         .route(
             service::func::PATH_ALIAS,
             axum::routing::patch(service::func::alias),
@@ -136,6 +481,18 @@ async fn main_async() {
             service::func::PATH_DEPLOY,
             axum::routing::post(service::func::deploy),
         )
+        .route(
+            service::func::PATH_PREWARM,
+            axum::routing::post(service::func::prewarm),
+        )
+        .route(
+            service::func::PATH_PROMOTE_INSTANCE,
+            axum::routing::post(service::func::promote_instance),
+        )
+        .route(
+            service::func::PATH_ROUTE_WEIGHT,
+            axum::routing::post(service::func::route_weight),
+        )
         .route(
             service::func::PATH_KILL,
             axum::routing::post(service::func::kill),
@@ -144,6 +501,35 @@ async fn main_async() {
             service::func::PATH_STATUS,
             axum::routing::get(service::func::status),
         )
+        .route(
+            service::func::PATH_LOGS,
+            axum::routing::get(service::func::logs),
+        )
+        .route(
+            service::func::PATH_FILE,
+            axum::routing::get(service::func::file),
+        )
+        .route(
+            service::func::PATH_DOWNLOAD,
+            axum::routing::get(service::func::download),
+        )
+        .route(
+            service::func::PATH_HEALTH,
+            axum::routing::get(service::func::health),
+        )
+        // administration services
+        .route(
+            service::admin::PATH_EXPORT,
+            axum::routing::get(service::admin::export),
+        )
+        .route(
+            service::admin::PATH_IMPORT,
+            axum::routing::post(service::admin::import),
+        )
+        .route(
+            service::admin::PATH_FLUSH,
+            axum::routing::post(service::admin::flush),
+        )
         // user services
         .route(
             service::user::PATH_ADD,
@@ -153,6 +539,10 @@ async fn main_async() {
             service::user::PATH_GET,
             axum::routing::get(service::user::get),
         )
+        .route(
+            service::user::PATH_LIST,
+            axum::routing::get(service::user::list),
+        )
         .route(
             service::user::PATH_REMOVE,
             axum::routing::delete(service::user::remove),
@@ -161,103 +551,1346 @@ async fn main_async() {
             service::user::PATH_REQUEST_TOKEN,
             axum::routing::post(service::user::request_token),
         )
+        .route(
+            service::user::PATH_CHANGE_TOKEN_DURATION,
+            axum::routing::post(service::user::change_token_duration),
+        )
+        .route(
+            service::user::PATH_REFRESH_TOKEN,
+            axum::routing::post(service::user::refresh_token),
+        )
+        .route(
+            service::user::PATH_LIST_TOKENS,
+            axum::routing::get(service::user::list_tokens),
+        )
         .route(
             service::user::PATH_MODIFY,
             axum::routing::put(service::user::modify),
         )
+        .route(
+            service::user::PATH_GROUPS,
+            axum::routing::get(service::user::groups),
+        )
+        .route(
+            service::user::PATH_GROUP_MEMBERS,
+            axum::routing::get(service::user::group_members),
+        )
+        .route(
+            service::user::PATH_MEMBERS,
+            axum::routing::get(service::user::members),
+        )
+        .route(
+            service::user::PATH_GROUP_ADD,
+            axum::routing::post(service::user::add_group),
+        )
+        .route(
+            service::user::PATH_GROUP_REMOVE,
+            axum::routing::delete(service::user::remove_group),
+        )
+        .route(
+            service::user::PATH_GROUP_LIST,
+            axum::routing::get(service::user::list_groups),
+        )
+        // respond misses in the same format as other errors
+        .fallback(async || Error::NotFound)
         // layers being executed from bottom to top in axum's ordering
+        .route_layer(middleware::from_fn_with_state(
+            cx.clone(),
+            filter_api_allowlist,
+        ))
         .route_layer(tower_http::trace::TraceLayer::new_for_http())
         // somehow one found <()> looks like F35 engine from outside
         .with_state::<()>(cx.clone());
 
+    if !args.in_memory {
+        tokio::spawn({
+            let cloned_cx = cx.clone();
+            let interval = tokio::time::Duration::from_secs(args.save_interval_secs.get());
+            async move {
+                let cx = cloned_cx;
+                loop {
+                    tokio::time::sleep(interval).await;
+                    // failures are logged and tracked in the save state
+                    drop(save_data(&cx).await);
+                }
+            }
+        });
+    }
+
     tokio::spawn({
         let cloned_cx = cx.clone();
+        let interval = args.token_prune_interval_secs.get();
         async move {
-            const WRITE_DURATION: tokio::time::Duration = tokio::time::Duration::from_mins(12);
             let cx = cloned_cx;
             loop {
-                tokio::time::sleep(WRITE_DURATION).await;
-                save_data(&cx).await;
+                // jittered by up to a sixth of the interval to avoid scanning at the same instant
+                // as data saving
+                let jitter = cx.rng.lock().random_range(0..=interval / 6);
+                tokio::time::sleep(tokio::time::Duration::from_secs(interval + jitter)).await;
+                cx.users.prune_expired_tokens();
             }
         }
     });
 
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(
-        listener,
-        middleware::from_fn_with_state(cx.clone(), proxy::forward_http_req)
-            .layer(router)
-            .into_make_service(),
-    )
-    .with_graceful_shutdown(async move {
-        let ctrl_c = async {
-            tokio::signal::ctrl_c()
-                .await
-                .expect("failed to install Ctrl+C handler");
-        };
+    tokio::spawn({
+        let cloned_cx = cx.clone();
+        async move {
+            const CHECK_EXITED_DURATION: tokio::time::Duration =
+                tokio::time::Duration::from_secs(5);
+            let cx = cloned_cx;
+            loop {
+                tokio::time::sleep(CHECK_EXITED_DURATION).await;
+                cx.reap_exited_fns().await;
+            }
+        }
+    });
 
-        #[cfg(unix)]
-        let terminate = async {
-            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
-                .expect("failed to install SIGTERM handler")
-                .recv()
-                .await;
-        };
+    tokio::spawn({
+        let cloned_cx = cx.clone();
+        async move {
+            const CHECK_IDLE_DURATION: tokio::time::Duration = tokio::time::Duration::from_secs(5);
+            let cx = cloned_cx;
+            loop {
+                tokio::time::sleep(CHECK_IDLE_DURATION).await;
+                cx.stop_idle_fns().await;
+            }
+        }
+    });
 
-        #[cfg(not(unix))]
-        let terminate = std::future::pending::<()>();
+    tokio::spawn({
+        let cloned_cx = cx.clone();
+        async move {
+            const AUTOSCALE_TICK: tokio::time::Duration = tokio::time::Duration::from_secs(1);
+            let cx = cloned_cx;
+            loop {
+                tokio::time::sleep(AUTOSCALE_TICK).await;
+                cx.autoscale_fns().await;
+            }
+        }
+    });
 
-        tokio::select! {
-            _ = ctrl_c => {},
-            _ = terminate => {},
+    tokio::spawn({
+        let cloned_cx = cx.clone();
+        async move {
+            const HEALTH_CHECK_TICK: tokio::time::Duration = tokio::time::Duration::from_secs(1);
+            let cx = cloned_cx;
+            loop {
+                tokio::time::sleep(HEALTH_CHECK_TICK).await;
+                cx.check_health();
+            }
         }
+    });
 
-        save_data(&cx).await
-    })
-    .await
-    .unwrap();
+    tokio::spawn({
+        let cloned_cx = cx.clone();
+        let interval = tokio::time::Duration::from_secs(args.reconcile_interval.get());
+        async move {
+            let cx = cloned_cx;
+            loop {
+                tokio::time::sleep(interval).await;
+                cx.reconcile_routes();
+            }
+        }
+    });
+
+    tokio::spawn({
+        let cloned_cx = cx.clone();
+        async move {
+            const PRUNE_UPLOADS_DURATION: tokio::time::Duration =
+                tokio::time::Duration::from_mins(5);
+            let cx = cloned_cx;
+            loop {
+                tokio::time::sleep(PRUNE_UPLOADS_DURATION).await;
+                cx.uploads.prune().await;
+            }
+        }
+    });
+
+    // probes are answered ahead of the proxy, so they're never affected by the management API
+    let app = Router::new().fallback_service(
+        middleware::from_fn_with_state(cx.clone(), service::probe::route_probes).layer(
+            middleware::from_fn_with_state(cx.clone(), proxy::forward_http_req).layer(router),
+        ),
+    );
+
+    let shutdown_timeout = tokio::time::Duration::from_secs(args.shutdown_timeout);
+    let signal = {
+        let cx = cx.clone();
+        async move {
+            let ctrl_c = async {
+                tokio::signal::ctrl_c()
+                    .await
+                    .expect("failed to install Ctrl+C handler");
+            };
+
+            #[cfg(unix)]
+            let terminate = async {
+                tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+                    .expect("failed to install SIGTERM handler")
+                    .recv()
+                    .await;
+            };
+
+            #[cfg(not(unix))]
+            let terminate = std::future::pending::<()>();
+
+            tokio::select! {
+                _ = ctrl_c => {},
+                _ = terminate => {},
+            }
+
+            shutdown(&cx, shutdown_timeout).await
+        }
+    };
+
+    if let Some(path) = &args.unix_socket {
+        let listener = bind_unix(path)
+            .await
+            .expect("failed to bind to unix socket");
+        serve(&cx, listener, app, signal).await;
+        drop(
+            tokio::fs::remove_file(path)
+                .await
+                .inspect_err(|err| tracing::error!("failed to remove unix socket: {err}")),
+        );
+    } else {
+        let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+        #[cfg(feature = "tls")]
+        if let Some(config) = tls_server {
+            let listener = tls_listener::TlsListener::new(listener, config)
+                .expect("failed to listen for TLS connections");
+            serve_tls(&cx, listener, app, signal).await;
+        } else {
+            serve(&cx, listener, app, signal).await;
+        }
+        #[cfg(not(feature = "tls"))]
+        serve(&cx, listener, app, signal).await;
+    }
+
+    if let Some(dir) = in_memory_dir {
+        drop(
+            tokio::fs::remove_dir_all(&dir)
+                .await
+                .inspect_err(|err| tracing::error!("failed to remove temporary directory: {err}")),
+        );
+    }
     tracing::info!("server stopped");
 }
 
+/// A running instance of a function, made of one or more replicas.
+#[derive(Debug)]
+struct Instance {
+    replicas: Vec<Replica>,
+    /// Snapshot of the function taken at spawn time.
+    snapshot: func::FunctionSnapshot,
+    /// Upstreams of the replicas in the same order, for routing public traffic to this instance.
+    pool: proxy::Pool,
+    /// Whether the replicas listen on free ports and use alternate runtime directories, as
+    /// spawned alongside the previous instance by [`LocalCx::redeploy_fn`].
+    surge: bool,
+    /// Log files shared by the replicas, for functions with `log_sink` set to `file`.
+    log_files: Option<Arc<LogFiles>>,
+    /// Instant the instance was spawned or last scaled, see [`func::Autoscale::cooldown`].
+    last_scaled: tokio::time::Instant,
+}
+
+/// A spawned replica of an [`Instance`].
+#[derive(Debug)]
+struct Replica {
+    handle: os::SandboxHandleImpl,
+    /// Captured outputs of this replica.
+    logs: Arc<LogBuffer>,
+}
+
+/// Lazy deployments of a host prefix, shared by requests hitting it meanwhile.
+#[derive(Debug, Default)]
+struct LazyDeploy {
+    /// Count of finished attempts, telling requests waiting for an attempt apart from ones
+    /// arriving after it.
+    attempts: AtomicU64,
+    /// Held while attempting, telling whether the last attempt failed.
+    failed: tokio::sync::Mutex<bool>,
+}
+
+/// Disk usage of a user shared by uploads of the user in progress, so that concurrent uploads are
+/// charged against the same quota.
+#[derive(Debug)]
+struct QuotaLedger {
+    /// Size of contents of functions of the user measured by the first of the uploads, with
+    /// contents replaced by finished ones deducted.
+    base: u64,
+    /// Bytes charged by the uploads, kept for finished ones until all of them end.
+    charged: u64,
+    /// Count of uploads in progress.
+    uploads: usize,
+}
+
+/// Reservation of the disk quota of a user for an upload in progress, charged with sizes of
+/// unpacked entries and the growth of contents by the build.
+///
+/// Bytes charged are released once dropped unless [committed](Self::commit), as the contents are
+/// rolled back then.
+struct QuotaReservation {
+    cx: Arc<LocalCx>,
+    user: String,
+    /// Quota of the user.
+    total: u64,
+    /// Size of the contents replaced by the upload, which are freed once committed.
+    replaced: u64,
+    /// Bytes charged by this upload.
+    charged: AtomicU64,
+    committed: bool,
+}
+
+impl QuotaReservation {
+    /// Charges given count of bytes, returning whether they fit in the quota.
+    fn charge(&self, bytes: u64) -> bool {
+        let fits = self
+            .cx
+            .quota_ledgers
+            .update_sync(&self.user, |_, ledger| {
+                let fits = ledger
+                    .base
+                    .saturating_add(ledger.charged)
+                    .saturating_add(bytes)
+                    <= self.total.saturating_add(self.replaced);
+                if fits {
+                    ledger.charged += bytes;
+                }
+                fits
+            })
+            .unwrap_or_default();
+        if fits {
+            self.charged.fetch_add(bytes, Ordering::Relaxed);
+        }
+        fits
+    }
+
+    /// Keeps the charged bytes as the contents are in place, freeing the replaced ones.
+    fn commit(&mut self) {
+        if !std::mem::replace(&mut self.committed, true) {
+            self.cx.quota_ledgers.update_sync(&self.user, |_, ledger| {
+                ledger.base = ledger.base.saturating_sub(self.replaced);
+            });
+        }
+    }
+}
+
+impl Drop for QuotaReservation {
+    fn drop(&mut self) {
+        let released = if self.committed {
+            0
+        } else {
+            *self.charged.get_mut()
+        };
+        self.cx.quota_ledgers.remove_if_sync(&self.user, |ledger| {
+            ledger.charged = ledger.charged.saturating_sub(released);
+            ledger.uploads -= 1;
+            ledger.uploads == 0
+        });
+    }
+}
+
 impl LocalCx {
+    /// Spawns and routes public traffic to a function.
+    ///
+    /// Waits until the function is ready if it has a readiness probe, killing it if it never gets
+    /// ready in time.
     async fn start_fn(&self, key: func::Key<'_>) -> Result<(), Error> {
-        let func = self.funcs.get(key).ok_or(Error::NotFound)?;
-
-        let config;
-        let auth_uri;
+        self.start_fn_with(key, false).await
+    }
 
+    /// Same as [`Self::start_fn`], but also waits until the function accepts connections without
+    /// a readiness probe if `wait_reachable` is set.
+    async fn start_fn_with(&self, key: func::Key<'_>, wait_reachable: bool) -> Result<(), Error> {
+        let (instance_key, pool) = self.spawn_fn(key).await?;
+        if (wait_reachable || pool.has_readiness_probe())
+            && let Err(err) = self.wait_ready(&pool).await
         {
-            let rg = func.read();
-            // need to clone it or non-async read lock will cause deadlock across await points
-            config = rg.config.sandbox.clone();
-            auth_uri = http::uri::Authority::from_maybe_shared(rg.config.addr.to_string())?;
+            drop(self.stop_fn(instance_key.as_ref()).await);
+            drop(self.failed.insert_sync(instance_key));
+            return Err(err);
         }
+        // the alias may be removed or retargeted while spawning, which would leave the instance
+        // unreachable through the requested key
+        if let Err(err) = self.check_resolves_to(key, &instance_key) {
+            drop(self.stop_fn(instance_key.as_ref()).await);
+            return Err(err);
+        }
+        self.route_fn(key)?;
+        self.failed.remove_sync(&instance_key);
+        self.notify(|o| o.on_deploy(instance_key.as_ref()));
+        Ok(())
+    }
 
-        let handle = Sandbox::spawn(&self.sandbox, &config, &self.funcs.contents_path(key)).await?;
+    /// Starts the function of given host prefix on demand if it has lazy deployment enabled,
+    /// for public traffic hitting it while it's not routed.
+    ///
+    /// Requests arriving while a deployment of the same host prefix is in progress share its
+    /// outcome, failing without retrying if it fails. Requests arriving afterwards attempt again.
+    async fn lazy_deploy_fn(&self, host_prefix: &str) -> Result<(), Error> {
+        let key = func::Key::from_host_prefix(host_prefix).ok_or(Error::FunctionNotRunning)?;
+        if !self
+            .funcs
+            .with_function(key, |func| func.config.lazy_deploy)
+            .unwrap_or_default()
+        {
+            return Err(Error::FunctionNotRunning);
+        }
 
-        if let Err((_, handle)) = self.handles.insert_sync(key.into_owned(), handle) {
-            sandbox::Handle::kill(handle).await;
-            Err(Error::InstanceAlreadyRunning)
+        let deploy = self
+            .lazy_deploys
+            .entry_sync(host_prefix.to_owned())
+            .or_default()
+            .get()
+            .clone();
+        let seen = deploy.attempts.load(Ordering::Acquire);
+        let mut failed = deploy.failed.lock().await;
+        let result = if deploy.attempts.load(Ordering::Acquire) != seen {
+            // an attempt finished while waiting for it
+            if *failed {
+                Err(Error::FunctionNotRunning)
+            } else {
+                Ok(())
+            }
+        } else if self.proxies.peek_with(host_prefix, |_, _| ()).is_some() {
+            // deployed by others meanwhile
+            Ok(())
         } else {
-            drop(self.proxies.insert_sync(key.to_host_prefix(), auth_uri));
+            tracing::info!("proxy: lazily deploying function {key}");
+            let result = self.start_fn_with(key, true).await;
+            *failed = result.is_err();
+            deploy.attempts.fetch_add(1, Ordering::Release);
+            result
+        };
+        drop(failed);
+        // the state is only held by the map and this request if no one else is waiting
+        self.lazy_deploys
+            .remove_if_sync(host_prefix, |deploy| Arc::strong_count(deploy) == 2);
+        result
+    }
+
+    /// Checks whether given key or alias still resolves to the instance spawned for it.
+    fn check_resolves_to(&self, key: func::Key<'_>, instance_key: &OwnedKey) -> Result<(), Error> {
+        match self.funcs.canonical_key(key) {
+            Some(resolved) if resolved == *instance_key => Ok(()),
+            Some(resolved) => Err(Error::AliasRetargeted {
+                requested: key.into_owned(),
+                resolved,
+            }),
+            None => Err(Error::FunctionNotFound(key.into_owned())),
+        }
+    }
+
+    /// Stops then starts a function again, counting the restart.
+    async fn restart_fn(&self, key: func::Key<'_>) -> Result<(), Error> {
+        self.stop_fn(key).await?;
+        self.restarts.fetch_add(1, Ordering::Relaxed);
+        self.start_fn(key).await
+    }
+
+    /// Replaces a running function with a new instance picking up its latest configuration,
+    /// without downtime.
+    ///
+    /// The new instance is spawned alongside the running one, and public traffic is switched to
+    /// it once it's ready. The previous instance is then drained and stopped. The running
+    /// instance is left untouched if the new one fails to get ready.
+    async fn redeploy_fn(&self, key: func::Key<'_>) -> Result<(), Error> {
+        let instance_key = self.instance_key(key);
+        let surge = self
+            .handles
+            .read_sync(&instance_key, |_, instance| !instance.surge)
+            .ok_or(Error::FunctionNotRunning)?;
+        let snapshot = self
+            .funcs
+            .get_snapshot(instance_key.as_ref())
+            .ok_or_else(|| Error::FunctionNotFound(key.into_owned()))?;
+
+        let instance = self
+            .spawn_instance(instance_key.as_ref(), snapshot, surge)
+            .await?;
+        if let Err(err) = self.wait_ready(&instance.pool).await {
+            kill_replicas(instance.replicas).await;
+            return Err(err);
+        }
+
+        let pool = instance.pool.clone();
+        let previous = match self.handles.entry_sync(instance_key.clone()) {
+            scc::hash_map::Entry::Occupied(mut entry) => {
+                std::mem::replace(entry.get_mut(), instance)
+            }
+            // stopped meanwhile
+            scc::hash_map::Entry::Vacant(entry) => {
+                drop(entry);
+                kill_replicas(instance.replicas).await;
+                return Err(Error::FunctionNotRunning);
+            }
+        };
+        self.reroute_fn(instance_key.as_ref(), &pool);
+        self.restarts.fetch_add(1, Ordering::Relaxed);
+        self.failed.remove_sync(&instance_key);
+        self.notify(|o| o.on_deploy(instance_key.as_ref()));
+
+        self.drain_pool(instance_key.as_ref(), &previous.pool).await;
+        terminate_replicas(previous.replicas, self.shutdown_grace(&previous.snapshot)).await;
+        Ok(())
+    }
+
+    /// Stops routing public traffic to a running function, then stops it once the requests it's
+    /// handling finish, or the drain timeout elapses.
+    async fn drain_fn(&self, key: func::Key<'_>) -> Result<(), Error> {
+        let (instance_key, pool) = self.pool(key).ok_or(Error::FunctionNotRunning)?;
+        self.unroute_fn(instance_key.as_ref());
+        self.drain_pool(instance_key.as_ref(), &pool).await;
+        self.stop_fn(instance_key.as_ref()).await
+    }
+
+    /// Waits until given pool of a function no longer routed finishes the requests it's handling,
+    /// or the drain timeout elapses.
+    async fn drain_pool(&self, key: func::Key<'_>, pool: &proxy::Pool) {
+        const POLL_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_millis(100);
+        let drain = async {
+            while pool.concurrency() > 0 {
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        };
+        if tokio::time::timeout(self.drain_timeout, drain)
+            .await
+            .is_err()
+        {
+            tracing::warn!(
+                "function {key} still handling {} requests after draining, stopping anyway",
+                pool.concurrency()
+            );
+        }
+    }
+
+    /// Adds a function, see [`FunctionManager::add_func`].
+    ///
+    /// Waits for a permit if too many archives are being unpacked. The function is removed if
+    /// unpacking fails or doesn't finish within the upload timeout, or its build command fails
+    /// afterwards.
+    ///
+    /// Unpacked entries and the growth of contents by the build are charged into `quota` if any,
+    /// which is committed once the function is added.
+    async fn add_func<R>(
+        &self,
+        key: func::Key<'_>,
+        init_group: Option<user::Group>,
+        tarball: &mut tokio_tar::Archive<R>,
+        mut quota: Option<QuotaReservation>,
+    ) -> Result<(), Error>
+    where
+        R: tokio::io::AsyncRead + Unpin,
+    {
+        let permit = self.upload_permit().await;
+        let result = tokio::time::timeout(
+            self.upload_timeout,
+            self.funcs.add_func(key, init_group, tarball, |bytes| {
+                quota.as_ref().is_none_or(|quota| quota.charge(bytes))
+            }),
+        )
+        .await;
+        drop(permit);
+        let err = match result {
+            Ok(Ok(())) => None,
+            // the function is someone else's
+            Ok(Err(err @ func::ManagerError::Duplicated)) => return Err(err.into()),
+            Ok(Err(err)) => Some(quota_error(quota.as_ref(), err)),
+            Err(_) => Some(Error::UploadTimeout),
+        };
+        if let Some(err) = err {
+            // information is inserted before unpacking, so the function is ours to roll back
+            // along with partially unpacked contents
+            if let Err(err) = self.funcs.remove_func(key).await {
+                tracing::error!("failed to roll back failed upload of {key}: {err}");
+            }
+            return Err(err);
+        }
+        let contents_path = self.funcs.contents_path(key);
+        let built = match self.build_fn(key, &contents_path).await {
+            Ok(()) => match &quota {
+                Some(quota) => self.charge_build(&contents_path, quota).await,
+                None => Ok(()),
+            },
+            Err(err) => Err(err),
+        };
+        if let Err(err) = built {
+            if let Err(err) = self.funcs.remove_func(key).await {
+                tracing::error!("failed to roll back upload of {key} failed building: {err}");
+            }
+            return Err(err);
+        }
+        if let Some(quota) = &mut quota {
+            quota.commit();
+        }
+        self.notify(|o| o.on_upload(key));
+        Ok(())
+    }
+
+    /// Reserves the disk quota of given user for an upload, or `None` if the user is unlimited.
+    ///
+    /// `replaced` is the size of the contents replaced by the upload, which is left available.
+    async fn reserve_quota(
+        self: &Arc<Self>,
+        user: &str,
+        replaced: u64,
+    ) -> Result<Option<QuotaReservation>, Error> {
+        let Some(total) = self.users.quota_bytes(user) else {
+            return Ok(None);
+        };
+        // uploads in progress are charged on top of the usage measured by the first of them,
+        // which covers contents being unpacked by the others
+        let joined = self
+            .quota_ledgers
+            .update_sync(user, |_, ledger| ledger.uploads += 1)
+            .is_some();
+        if !joined {
+            let base = self.funcs.used_bytes(user).await?;
+            match self.quota_ledgers.entry_sync(user.to_owned()) {
+                scc::hash_map::Entry::Occupied(mut entry) => entry.get_mut().uploads += 1,
+                scc::hash_map::Entry::Vacant(entry) => drop(entry.insert_entry(QuotaLedger {
+                    base,
+                    charged: 0,
+                    uploads: 1,
+                })),
+            }
+        }
+        Ok(Some(QuotaReservation {
+            cx: self.clone(),
+            user: user.to_owned(),
+            total,
+            replaced,
+            charged: AtomicU64::new(0),
+            committed: false,
+        }))
+    }
+
+    /// Charges the growth of given contents by the build into given reservation.
+    async fn charge_build(
+        &self,
+        contents_path: &Path,
+        quota: &QuotaReservation,
+    ) -> Result<(), Error> {
+        let size = func::dir_size(contents_path).await?;
+        let unpacked = quota.charged.load(Ordering::Relaxed);
+        if quota.charge(size.saturating_sub(unpacked)) {
             Ok(())
+        } else {
+            Err(Error::QuotaExceeded(quota.total))
+        }
+    }
+
+    /// Runs the build command of a function against given contents if there's one, failing
+    /// unless the command exits successfully within the build timeout.
+    ///
+    /// Outputs are written into log files if the function logs into files, while only the last
+    /// lines are kept in memory for reporting failures.
+    async fn build_fn(&self, key: func::Key<'_>, contents_path: &Path) -> Result<(), Error> {
+        /// Maximum count of the last lines of outputs kept for a failed build.
+        const MAX_REPORTED_LINES: usize = 32;
+        /// Maximum length of outputs of a failed build reported back.
+        const MAX_REPORTED_OUTPUT: usize = 1024;
+
+        let Some((config, build)) = self
+            .funcs
+            .with_function(key, |func| {
+                func.config
+                    .build_command
+                    .clone()
+                    .map(|build| (func.config.sandbox.clone(), build))
+            })
+            .ok_or(Error::NotFound)?
+        else {
+            return Ok(());
+        };
+
+        let runtime_path = self.funcs.runtime_path(key);
+        tokio::fs::create_dir_all(&runtime_path).await?;
+        let logs = Arc::new(LogBuffer::new(MAX_REPORTED_LINES));
+        let files = (config.log_sink == Some(LogSink::File)).then(|| {
+            Arc::new(LogFiles::new(
+                self.funcs.logs_path(key),
+                self.log_file_max_bytes,
+                self.log_file_max_count,
+            ))
+        });
+        tracing::info!("building function {key} with command {}", build.command);
+        let status = tokio::time::timeout(self.build_timeout, async {
+            // aborted along with the build on timeouts
+            let mut captures = JoinSet::new();
+            let status = Sandbox::build(
+                &self.sandbox,
+                &config,
+                &build,
+                contents_path,
+                &runtime_path,
+                |output| {
+                    for (reader, stream) in [
+                        (output.stdout, LogStream::Stdout),
+                        (output.stderr, LogStream::Stderr),
+                    ] {
+                        let Some(reader) = reader else { continue };
+                        let logs = logs.clone();
+                        let files = files.clone();
+                        captures.spawn(async move {
+                            drop(
+                                logs.capture(reader, stream, files.as_deref())
+                                    .await
+                                    .inspect_err(|e| {
+                                        tracing::warn!("failed to capture build outputs: {e}")
+                                    }),
+                            )
+                        });
+                    }
+                },
+            )
+            .await?;
+            // ends once the pipes are closed
+            while captures.join_next().await.is_some() {}
+            Ok(status)
+        })
+        .await
+        .map_err(|_| Error::BuildFailed("timed out".to_owned()))?
+        .map_err(spawn_error)?;
+        if status.success() {
+            return Ok(());
+        }
+
+        let output = logs
+            .snapshot()
+            .into_iter()
+            .map(|line| line.line)
+            .collect::<Vec<_>>()
+            .join("\n");
+        let tail = String::from_utf8_lossy(
+            &output.as_bytes()[output.len().saturating_sub(MAX_REPORTED_OUTPUT)..],
+        );
+        tracing::warn!("build of function {key} exited with {status}");
+        Err(Error::BuildFailed(format!(
+            "exited with {status}: {}",
+            tail.trim()
+        )))
+    }
+
+    /// Waits for a permit of unpacking an uploaded archive, held until dropped.
+    async fn upload_permit(&self) -> tokio::sync::SemaphorePermit<'_> {
+        // the semaphore is never closed
+        self.upload_permits
+            .acquire()
+            .await
+            .expect("upload semaphore closed")
+    }
+
+    /// Invokes the lifecycle observer if there's one.
+    #[inline]
+    fn notify<F>(&self, f: F)
+    where
+        F: FnOnce(&dyn LifecycleObserver),
+    {
+        if let Some(observer) = &self.observer {
+            f(observer.as_ref())
+        }
+    }
+
+    /// Resolves the key an instance of given function is stored with, which is the key of its
+    /// version rather than any alias, so that an alias and its version share the same instance.
+    fn instance_key(&self, key: func::Key<'_>) -> OwnedKey {
+        self.funcs
+            .canonical_key(key)
+            .unwrap_or_else(|| key.into_owned())
+    }
+
+    /// Spawns all replicas of a function without routing public traffic to it, returning the key
+    /// of its instance and the pool of upstreams of the replicas.
+    async fn spawn_fn(&self, key: func::Key<'_>) -> Result<(OwnedKey, proxy::Pool), Error> {
+        let requested = key;
+        let key = self.instance_key(requested);
+        // fail fast before spawning, as the alias and its version share the same instance
+        if self.handles.contains_sync(&key) {
+            return Err(already_running(requested, key));
+        }
+        let key = key.as_ref();
+
+        let snapshot = self
+            .funcs
+            .get_snapshot(key)
+            .ok_or_else(|| Error::FunctionNotFound(requested.into_owned()))?;
+
+        let instance = self.spawn_instance(key, snapshot, false).await?;
+        let pool = instance.pool.clone();
+        if let Err((_, instance)) = self.handles.insert_sync(key.into_owned(), instance) {
+            kill_replicas(instance.replicas).await;
+            Err(already_running(requested, key.into_owned()))
+        } else {
+            Ok((key.into_owned(), pool))
+        }
+    }
+
+    /// Spawns the initial replicas of a function from given snapshot as an instance, without
+    /// storing it. Replicas spawned before any failure are killed.
+    ///
+    /// See [`Instance::surge`] for `surge`.
+    async fn spawn_instance(
+        &self,
+        key: func::Key<'_>,
+        snapshot: func::FunctionSnapshot,
+        surge: bool,
+    ) -> Result<Instance, Error> {
+        let files = (snapshot.sandbox.log_sink == Some(LogSink::File)).then(|| {
+            Arc::new(LogFiles::new(
+                self.funcs.logs_path(key),
+                self.log_file_max_bytes,
+                self.log_file_max_count,
+            ))
+        });
+
+        let count = snapshot.initial_replicas();
+        let mut replicas = Vec::with_capacity(count);
+        let mut upstreams = Vec::with_capacity(count);
+        for replica in 0..count {
+            match self
+                .spawn_replica(key, &snapshot, replica, surge, files.clone())
+                .await
+            {
+                Ok((spawned, upstream)) => {
+                    replicas.push(spawned);
+                    upstreams.push(upstream);
+                }
+                Err(err) => {
+                    kill_replicas(replicas).await;
+                    return Err(err);
+                }
+            }
         }
+        Ok(Instance {
+            replicas,
+            snapshot,
+            pool: proxy::Pool::new(upstreams),
+            surge,
+            log_files: files,
+            last_scaled: tokio::time::Instant::now(),
+        })
+    }
+
+    /// Spawns a replica of a function, returning it along with its upstream.
+    ///
+    /// The replica listens on its own address passed through [`func::ENV_ADDR`], with outputs
+    /// written into given log files if any. See [`Instance::surge`] for `surge`.
+    async fn spawn_replica(
+        &self,
+        key: func::Key<'_>,
+        snapshot: &func::FunctionSnapshot,
+        replica: usize,
+        surge: bool,
+        files: Option<Arc<LogFiles>>,
+    ) -> Result<(Replica, proxy::Upstream), Error> {
+        // the configured address is still taken by the instance being replaced, and ports next
+        // to it may be taken by anything else
+        let addr = if surge || replica > 0 {
+            std::net::TcpListener::bind((snapshot.addr.ip(), 0))?.local_addr()?
+        } else {
+            snapshot.addr
+        };
+        let auth_uri = http::uri::Authority::from_maybe_shared(addr.to_string())?;
+
+        let mut runtime_path = self.funcs.replica_runtime_path(key, replica);
+        if surge {
+            runtime_path.as_mut_os_string().push(".surge");
+        }
+        let socket = if snapshot.sandbox.network_isolation {
+            tokio::fs::create_dir_all(&runtime_path).await?;
+            let socket = runtime_path.join(sandbox::SOCKET_NAME);
+            // left by the previous instance
+            if tokio::fs::try_exists(&socket).await? {
+                tokio::fs::remove_file(&socket).await?;
+            }
+            Some(socket)
+        } else {
+            None
+        };
+        let upstream = proxy::Upstream::new(auth_uri, snapshot, socket);
+
+        let mut config = snapshot.sandbox.clone();
+        config
+            .envs
+            .insert(func::ENV_ADDR.to_owned(), Some(addr.to_string()));
+        tracing::info!(
+            "spawning replica {replica} of function {key} with command {}",
+            config.command
+        );
+        let mut handle = Sandbox::spawn(
+            &self.sandbox,
+            &config,
+            &self.funcs.contents_path(key),
+            &runtime_path,
+        )
+        .await
+        .map_err(spawn_error)?;
+
+        let logs = Arc::new(LogBuffer::new(self.log_buffer_lines));
+        if let Some(output) = sandbox::Handle::take_output(&mut handle) {
+            for (reader, stream) in [
+                (output.stdout, LogStream::Stdout),
+                (output.stderr, LogStream::Stderr),
+            ] {
+                let Some(reader) = reader else { continue };
+                let logs = logs.clone();
+                let files = files.clone();
+                // ends once the pipe is closed by the exiting process
+                tokio::spawn(async move {
+                    drop(
+                        logs.capture(reader, stream, files.as_deref())
+                            .await
+                            .inspect_err(|e| tracing::warn!("failed to capture outputs: {e}")),
+                    )
+                });
+            }
+        }
+        Ok((Replica { handle, logs }, upstream))
+    }
+
+    /// Returns the pool of upstreams of a spawned function along with the key it's stored with.
+    fn pool(&self, key: func::Key<'_>) -> Option<(OwnedKey, proxy::Pool)> {
+        let key = self.instance_key(key);
+        let pool = self
+            .handles
+            .read_sync(&key, |_, instance| instance.pool.clone())?;
+        Some((key, pool))
+    }
+
+    /// Routes public traffic to the host prefix of given key or alias to a spawned function.
+    fn route_fn(&self, key: func::Key<'_>) -> Result<(), Error> {
+        let (instance_key, pool) = self.pool(key).ok_or(Error::FunctionNotRunning)?;
+        self.set_route(
+            key.to_host_prefix(),
+            proxy::Route::single(instance_key, pool),
+        );
+        Ok(())
+    }
+
+    /// Sets the route of public traffic to given host prefix, replacing the previous one.
+    fn set_route(&self, host_prefix: String, route: proxy::Route) {
+        match self.proxies.entry_sync(host_prefix) {
+            scc::hash_index::Entry::Occupied(entry) => entry.update(route),
+            scc::hash_index::Entry::Vacant(entry) => drop(entry.insert_entry(route)),
+        }
+    }
+
+    /// Points all routes targeting given function to given pool of its replacing instance.
+    fn reroute_fn(&self, key: func::Key<'_>, pool: &proxy::Pool) {
+        let guard = scc::Guard::new();
+        let affected: Vec<_> = self
+            .proxies
+            .iter(&guard)
+            .filter(|(_, route)| route.contains(key))
+            .map(|(host_prefix, route)| (host_prefix.clone(), route.with_pool(key, pool)))
+            .collect();
+        drop(guard);
+        for (host_prefix, route) in affected {
+            self.set_route(host_prefix, route);
+        }
+    }
+
+    /// Removes a function from all routes, dropping routes without any target left.
+    fn unroute_fn(&self, key: func::Key<'_>) {
+        let guard = scc::Guard::new();
+        let affected: Vec<_> = self
+            .proxies
+            .iter(&guard)
+            .filter(|(_, route)| route.contains(key))
+            .map(|(host_prefix, route)| (host_prefix.clone(), route.without(key)))
+            .collect();
+        drop(guard);
+        for (host_prefix, route) in affected {
+            match route {
+                Some(route) => self.set_route(host_prefix, route),
+                None => drop(self.proxies.remove_sync(&host_prefix)),
+            }
+        }
+    }
+
+    /// Waits until all replicas of the function are ready, see [`proxy::Upstream::probe_ready`].
+    ///
+    /// Probes are sent in the interval and within the timeout of the readiness probing of the
+    /// function, or the defaults without one. Failures are reported along with the last failed
+    /// probe.
+    async fn wait_ready(&self, pool: &proxy::Pool) -> Result<(), Error> {
+        // nothing is served without replicas
+        if pool.upstreams().is_empty() {
+            return Err(Error::FunctionNotReady(None));
+        }
+        let readiness = pool.readiness();
+        let timeout = readiness
+            .and_then(|readiness| readiness.timeout)
+            .unwrap_or(self.upstream_connect_timeout);
+        let interval =
+            readiness.map_or(func::DEFAULT_READINESS_INTERVAL, func::Readiness::interval);
+        let mut last_probe = None;
+        let poll = async {
+            // replicas are spawned at the same time, so waiting for them one by one takes
+            // no longer than the slowest one
+            for upstream in pool.upstreams() {
+                while let Err(probe) = upstream.probe_ready(self).await {
+                    last_probe = probe;
+                    tokio::time::sleep(interval).await;
+                }
+            }
+        };
+        // probes hanging on functions never responding are cancelled as well
+        let result = tokio::time::timeout(timeout, poll).await;
+        result.map_err(|_| Error::FunctionNotReady(last_probe))
+    }
+
+    /// Returns the grace period of stopping given function, capped by `--max-shutdown-grace`.
+    fn shutdown_grace(&self, snapshot: &func::FunctionSnapshot) -> tokio::time::Duration {
+        snapshot
+            .sandbox
+            .shutdown_grace()
+            .min(self.max_shutdown_grace)
     }
 
     async fn stop_fn(&self, key: func::Key<'_>) -> Result<(), Error> {
-        let (_, handle) = self.handles.remove_sync(&key).ok_or(Error::NotFound)?;
-        sandbox::Handle::kill(handle).await;
-        self.proxies.remove_sync(&key.to_host_prefix());
+        let (key, instance) = self
+            .handles
+            .remove_sync(&self.instance_key(key))
+            .ok_or_else(|| Error::InstanceNotFound(key.into_owned()))?;
+        // no more traffic is routed to replicas shutting down
+        self.unroute_fn(key.as_ref());
+        terminate_replicas(instance.replicas, self.shutdown_grace(&instance.snapshot)).await;
+        self.notify(|o| o.on_stop(key.as_ref()));
         Ok(())
     }
 
+    /// Removes instances with any replica exited on their own, which are considered crashed
+    /// along with the rest of their replicas killed.
+    async fn reap_exited_fns(&self) {
+        let mut exited = Vec::new();
+        self.handles.retain_sync(|key, instance| {
+            for (i, replica) in instance.replicas.iter_mut().enumerate() {
+                if sandbox::Handle::check_exited(&mut replica.handle) {
+                    tracing::warn!("replica {i} of function {key} exited unexpectedly");
+                    exited.push(key.clone());
+                }
+            }
+            true
+        });
+        exited.dedup();
+        for key in exited {
+            // stopped meanwhile
+            let Some((key, instance)) = self.handles.remove_sync(&key) else {
+                continue;
+            };
+            for replica in instance.replicas {
+                if sandbox::Handle::is_running(&replica.handle) {
+                    sandbox::Handle::kill(replica.handle).await;
+                }
+            }
+            self.unroute_fn(key.as_ref());
+            drop(self.failed.insert_sync(key.clone()));
+            self.notify(|o| o.on_crash(key.as_ref()));
+        }
+    }
+
+    /// Drains and stops instances staying idle longer than their idle timeouts.
+    async fn stop_idle_fns(&self) {
+        let mut idle = Vec::new();
+        self.handles.iter_sync(|key, instance| {
+            let idle_for = instance.pool.idle_duration();
+            if instance
+                .snapshot
+                .idle_timeout
+                .is_some_and(|timeout| idle_for >= timeout)
+            {
+                idle.push((key.clone(), idle_for));
+            }
+            true
+        });
+        for (key, idle_for) in idle {
+            tracing::info!("stopping function {key} idle for {}s", idle_for.as_secs());
+            // requests arriving meanwhile are waited for by draining
+            if let Err(err) = self.drain_fn(key.as_ref()).await {
+                tracing::warn!("failed to stop idle function {key}: {err}");
+            }
+        }
+    }
+
+    /// Adds or removes a replica of instances with autoscaling towards the counts of replicas
+    /// their concurrency needs, see [`func::Autoscale`].
+    ///
+    /// Instances are scaled concurrently so that a slow spawn or drain never delays others, while
+    /// this returns only after all of them finish.
+    async fn autoscale_fns(self: &Arc<Self>) {
+        let now = tokio::time::Instant::now();
+        let mut due = Vec::new();
+        self.handles.iter_sync(|key, instance| {
+            if let Some(autoscale) = instance.snapshot.autoscale
+                && now.duration_since(instance.last_scaled) >= autoscale.cooldown()
+            {
+                let replicas = instance.replicas.len();
+                let desired = autoscale.desired_replicas(instance.pool.concurrency());
+                if desired != replicas {
+                    due.push((key.clone(), instance.pool.clone(), desired > replicas));
+                }
+            }
+            true
+        });
+        let mut js = JoinSet::new();
+        for (key, pool, up) in due {
+            let cx = self.clone();
+            js.spawn(async move {
+                if !up {
+                    cx.scale_down_fn(key.as_ref(), &pool).await;
+                } else if let Err(err) = cx.scale_up_fn(key.as_ref(), &pool).await {
+                    tracing::warn!("failed to scale function {key} up: {err}");
+                }
+            });
+        }
+        js.join_all().await;
+    }
+
+    /// Adds a replica to the running instance of a function once it's ready, unless the instance
+    /// no longer has given pool as it's stopped, replaced or scaled meanwhile.
+    async fn scale_up_fn(&self, key: func::Key<'_>, observed: &proxy::Pool) -> Result<(), Error> {
+        let Some((snapshot, replica, surge, files)) = self
+            .handles
+            .read_sync(&key.into_owned(), |_, instance| {
+                instance.pool.ptr_eq(observed).then(|| {
+                    (
+                        instance.snapshot.clone(),
+                        instance.replicas.len(),
+                        instance.surge,
+                        instance.log_files.clone(),
+                    )
+                })
+            })
+            .flatten()
+        else {
+            return Ok(());
+        };
+
+        tracing::info!("scaling function {key} up to {} replicas", replica + 1);
+        let (spawned, upstream) = self
+            .spawn_replica(key, &snapshot, replica, surge, files)
+            .await?;
+        if let Err(err) = self
+            .wait_ready(&proxy::Pool::new(vec![upstream.clone()]))
+            .await
+        {
+            kill_replicas(vec![spawned]).await;
+            return Err(err);
+        }
+
+        let pool = match self.handles.entry_sync(key.into_owned()) {
+            scc::hash_map::Entry::Occupied(mut entry) if entry.get().pool.ptr_eq(observed) => {
+                let instance = entry.get_mut();
+                instance.replicas.push(spawned);
+                instance.pool = observed.with_upstream(upstream);
+                instance.last_scaled = tokio::time::Instant::now();
+                instance.pool.clone()
+            }
+            _ => {
+                kill_replicas(vec![spawned]).await;
+                return Ok(());
+            }
+        };
+        self.reroute_fn(key, &pool);
+        Ok(())
+    }
+
+    /// Removes the last replica from the running instance of a function, then stops it once
+    /// drained, unless the instance no longer has given pool as it's stopped, replaced or scaled
+    /// meanwhile.
+    async fn scale_down_fn(&self, key: func::Key<'_>, observed: &proxy::Pool) {
+        let Some((pool, upstream)) = observed.without_last() else {
+            return;
+        };
+        let (replica, snapshot) = match self.handles.entry_sync(key.into_owned()) {
+            scc::hash_map::Entry::Occupied(mut entry) if entry.get().pool.ptr_eq(observed) => {
+                let instance = entry.get_mut();
+                let Some(replica) = instance.replicas.pop() else {
+                    return;
+                };
+                instance.pool = pool.clone();
+                instance.last_scaled = tokio::time::Instant::now();
+                (replica, instance.snapshot.clone())
+            }
+            _ => return,
+        };
+
+        tracing::info!(
+            "scaling function {key} down to {} replicas",
+            pool.upstreams().len()
+        );
+        self.reroute_fn(key, &pool);
+        self.drain_pool(key, &proxy::Pool::new(vec![upstream]))
+            .await;
+        terminate_replicas(vec![replica], self.shutdown_grace(&snapshot)).await;
+    }
+
+    /// Checks health of running functions due for a check, each in its own task so that slow
+    /// checks never delay others.
+    fn check_health(self: &Arc<Self>) {
+        let now = time::UtcDateTime::now();
+        let mut due = Vec::new();
+        self.handles.iter_sync(|_, instance| {
+            due.extend(
+                instance
+                    .pool
+                    .upstreams()
+                    .iter()
+                    .filter(|upstream| upstream.is_health_check_due(now))
+                    .cloned(),
+            );
+            true
+        });
+        for upstream in due {
+            let cx = self.clone();
+            tokio::spawn(async move { upstream.check_health(&cx).await });
+        }
+    }
+
+    /// Fixes routes drifted from running instances, as a safety net of bugs in paths of starting
+    /// and stopping functions.
+    ///
+    /// Targets without running instances are removed, and targets with upstreams differing from
+    /// their instances are updated. Instances without routes are left as is, as they may be
+    /// prewarmed on purpose.
+    fn reconcile_routes(&self) {
+        let guard = scc::Guard::new();
+        let mut drifted = Vec::new();
+        for (host_prefix, route) in self.proxies.iter(&guard) {
+            let mut orphans = Vec::new();
+            let mut stale = Vec::new();
+            let targets: Vec<_> = route
+                .targets()
+                .iter()
+                .filter_map(|target| {
+                    let Some(pool) = self
+                        .handles
+                        .read_sync(&target.key, |_, instance| instance.pool.clone())
+                    else {
+                        orphans.push(target.key.to_string());
+                        return None;
+                    };
+                    if pool.endpoints() == target.pool.endpoints() {
+                        Some(target.clone())
+                    } else {
+                        stale.push(target.key.to_string());
+                        Some(proxy::Target {
+                            pool,
+                            ..target.clone()
+                        })
+                    }
+                })
+                .collect();
+            if !orphans.is_empty() || !stale.is_empty() {
+                drifted.push((host_prefix.clone(), route.clone(), targets, orphans, stale));
+            }
+        }
+        drop(guard);
+
+        for (host_prefix, observed, targets, orphans, stale) in drifted {
+            // routes changed since observed are left to their writers, which are up to date
+            let reconciled = if targets.is_empty() {
+                self.proxies
+                    .remove_if_sync(&host_prefix, |route| route.ptr_eq(&observed))
+            } else {
+                match self.proxies.get_sync(&host_prefix) {
+                    Some(entry) if entry.get().ptr_eq(&observed) => {
+                        entry.update(proxy::Route::new(targets));
+                        true
+                    }
+                    _ => false,
+                }
+            };
+            if reconciled {
+                tracing::warn!(
+                    "reconciled route of {host_prefix}, removed targets without running \
+                    instances: {orphans:?}, updated targets with stale upstreams: {stale:?}"
+                );
+            }
+        }
+    }
+
     fn is_running(&self, key: func::Key<'_>) -> bool {
+        self.is_instance_running(self.instance_key(key).as_ref())
+    }
+
+    /// Whether the instance of given canonical key is running.
+    ///
+    /// Aliases are never resolved, so it's callable with the function locked.
+    fn is_instance_running(&self, key: func::Key<'_>) -> bool {
         self.handles
-            .read_sync(&key, |_, handle| sandbox::Handle::is_running(handle))
+            .read_sync(&key, |_, instance| {
+                instance
+                    .replicas
+                    .iter()
+                    .any(|replica| sandbox::Handle::is_running(&replica.handle))
+            })
             .unwrap_or_default()
     }
+
+    /// Returns the snapshot a running function was spawned with.
+    fn running_snapshot(&self, key: func::Key<'_>) -> Option<func::FunctionSnapshot> {
+        self.handles
+            .read_sync(&self.instance_key(key), |_, instance| {
+                instance.snapshot.clone()
+            })
+    }
+
+    /// Returns the count of requests being handled by a running function.
+    fn concurrency(&self, key: func::Key<'_>) -> Option<usize> {
+        self.handles
+            .read_sync(&self.instance_key(key), |_, instance| {
+                instance.pool.concurrency()
+            })
+    }
+
+    /// Returns captured outputs of each replica of a running function.
+    fn logs(&self, key: func::Key<'_>) -> Option<Vec<Arc<LogBuffer>>> {
+        self.handles
+            .read_sync(&self.instance_key(key), |_, instance| {
+                instance
+                    .replicas
+                    .iter()
+                    .map(|replica| replica.logs.clone())
+                    .collect()
+            })
+    }
 }
 
+/// Converts an error of spawning a sandbox, recognizing insufficient privileges and malformed
+/// environment variable files.
+fn spawn_error(err: std::io::Error) -> Error {
+    let Some(inner) = err.get_ref() else {
+        return err.into();
+    };
+    if let Some(sandbox::PrivilegeDenied(msg)) = inner.downcast_ref() {
+        Error::SandboxPrivilegeDenied(msg.clone())
+    } else if let Some(env_file_err) = inner.downcast_ref::<sandbox::EnvFileError>() {
+        Error::InvalidEnvFile(env_file_err.to_string())
+    } else {
+        err.into()
+    }
+}
+
+/// Error of spawning a function whose instance is already running, possibly through its alias
+/// or version.
+fn already_running(requested: func::Key<'_>, running: OwnedKey) -> Error {
+    if requested == running.as_ref() {
+        Error::InstanceAlreadyRunning
+    } else {
+        Error::AliasedInstanceRunning {
+            requested: requested.into_owned(),
+            running,
+        }
+    }
+}
+
+/// Directory of upload sessions under the root directory, hidden from functions.
+const DIR_UPLOADS: &str = ".uploads";
+
 type State = axum::extract::State<Arc<LocalCx>>;
 
 bitflags! {
@@ -286,7 +1919,113 @@ impl PermissionFlags {
     }
 }
 
+/// Mode of routing public traffic to functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Routing {
+    /// By subdomains of the platform host, like `version.name.example.com`.
+    Subdomain,
+    /// By leading path segments, like `example.com/_fn/name/version/`, with the prefix stripped
+    /// from forwarded requests.
+    Path,
+}
+
 const AUTH_PREFIX: &str = "Bearer ";
+const AUTH_PREFIX_BASIC: &str = "Basic ";
+const HEADER_API_TOKEN: &str = "x-api-token";
+
+/// Scheme of carrying tokens in requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum AuthScheme {
+    /// `Authorization: Bearer <token>`.
+    Bearer,
+    /// `X-Api-Token: <token>`.
+    Header,
+    /// `Authorization: Basic <credentials>` with the token as password.
+    Basic,
+    /// `?token=<token>` query parameter, which may leak tokens into logs.
+    Query,
+}
+
+impl AuthScheme {
+    /// Extracts token carried in given request parts through this scheme.
+    fn extract(self, parts: &mut http::request::Parts) -> Result<Option<String>, Error> {
+        match self {
+            Self::Bearer | Self::Basic => {
+                let prefix = if self == Self::Bearer {
+                    AUTH_PREFIX
+                } else {
+                    AUTH_PREFIX_BASIC
+                };
+                let Some(value) = parts
+                    .headers
+                    .get(http::header::AUTHORIZATION)
+                    .map(|v| v.to_str())
+                    .transpose()?
+                    .and_then(|v| v.strip_prefix(prefix))
+                    .map(str::trim)
+                else {
+                    return Ok(None);
+                };
+                let token = if self == Self::Bearer {
+                    value.to_owned()
+                } else {
+                    let credentials = base64::engine::general_purpose::STANDARD
+                        .decode(value)
+                        .ok()
+                        .and_then(|c| String::from_utf8(c).ok())
+                        .ok_or(Error::InvalidAuthMethod)?;
+                    let (_, password) = credentials
+                        .split_once(':')
+                        .ok_or(Error::InvalidAuthMethod)?;
+                    password.to_owned()
+                };
+                parts.headers.remove(http::header::AUTHORIZATION);
+                Ok(Some(token))
+            }
+            Self::Header => parts
+                .headers
+                .remove(HEADER_API_TOKEN)
+                .map(|v| Ok(v.to_str()?.trim().to_owned()))
+                .transpose(),
+            Self::Query => {
+                #[derive(serde::Deserialize)]
+                struct TokenQuery {
+                    token: Option<String>,
+                }
+                Ok(axum::extract::Query::<TokenQuery>::try_from_uri(&parts.uri)
+                    .ok()
+                    .and_then(|q| q.0.token))
+            }
+        }
+    }
+}
+
+/// Token carried by a request through any accepted scheme, left unchecked.
+///
+/// For endpoints taking the token itself as the subject, which report unknown or expired tokens
+/// on their own.
+struct Token(String);
+
+impl axum::extract::FromRequestParts<Arc<LocalCx>> for Token {
+    type Rejection = Error;
+
+    async fn from_request_parts(
+        parts: &mut http::request::Parts,
+        state: &Arc<LocalCx>,
+    ) -> Result<Self, Self::Rejection> {
+        for scheme in &state.auth_schemes {
+            if let Some(token) = scheme.extract(parts)? {
+                return Ok(Self(token));
+            }
+        }
+        if parts.headers.contains_key(http::header::AUTHORIZATION) {
+            // carried with a scheme not accepted
+            Err(Error::InvalidAuthMethod)
+        } else {
+            Err(Error::Unauthorized)
+        }
+    }
+}
 
 struct Auth<const P: u32>(String);
 
@@ -298,32 +2037,104 @@ impl<const P: u32> axum::extract::FromRequestParts<Arc<LocalCx>> for Auth<P> {
         state: &Arc<LocalCx>,
     ) -> Result<Self, Self::Rejection> {
         let flags = PermissionFlags::from_bits_retain(P);
-        let header = parts
-            .headers
-            .remove(http::header::AUTHORIZATION)
-            .ok_or(Error::Unauthorized)?;
-
-        let token = header
-            .to_str()?
-            .strip_prefix(AUTH_PREFIX)
-            .ok_or(Error::InvalidAuthMethod)?
-            .trim();
+        let Token(token) = Token::from_request_parts(parts, state).await?;
 
         if state.users.auth(
-            token,
+            &token,
             flags
                 .iter()
                 .filter_map(PermissionFlags::to_permission)
                 .map(user::Group::Permission)
                 .map(Cow::Owned),
         ) {
-            Ok(Self(token.to_owned()))
+            Ok(Self(token))
         } else {
             Err(Error::PermissionDenied)
         }
     }
 }
 
+/// Header to be set on responses of functions, in form of `NAME: VALUE`.
+#[derive(Debug, Clone)]
+struct ResponseHeader {
+    name: http::HeaderName,
+    value: http::HeaderValue,
+}
+
+impl FromStr for ResponseHeader {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected `NAME: VALUE`, found `{s}`"))?;
+        Ok(Self {
+            name: name.trim().parse().map_err(|e| format!("{e}"))?,
+            value: value.trim().parse().map_err(|e| format!("{e}"))?,
+        })
+    }
+}
+
+/// Rule of management API allowlist in form of `METHOD PATH`, where `METHOD` could be `*` for
+/// any method and `PATH` is the route pattern like `/api/user/remove/{user}`.
+#[derive(Debug, Clone)]
+struct ApiAllowRule {
+    method: Option<http::Method>,
+    path: String,
+}
+
+impl FromStr for ApiAllowRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (method, path) = s
+            .trim()
+            .split_once(' ')
+            .ok_or_else(|| format!("expected `METHOD PATH`, found `{s}`"))?;
+        Ok(Self {
+            method: if method == "*" {
+                None
+            } else {
+                Some(method.parse().map_err(|e| format!("{e}"))?)
+            },
+            path: path.trim().to_owned(),
+        })
+    }
+}
+
+/// Parses URL of the lifecycle webhook, which must be of plain HTTP with a host.
+fn parse_webhook(s: &str) -> Result<http::Uri, String> {
+    let uri: http::Uri = s.parse().map_err(|e| format!("{e}"))?;
+    if uri.scheme() != Some(&http::uri::Scheme::HTTP) || uri.host().is_none() {
+        return Err(format!("expected a plain HTTP URL, found `{s}`"));
+    }
+    Ok(uri)
+}
+
+/// Rejects management requests not matching any rule of the allowlist, if present.
+async fn filter_api_allowlist(
+    cx: State,
+    matched: Option<MatchedPath>,
+    request: Request,
+    next: middleware::Next,
+) -> Result<axum::response::Response, Error> {
+    if !cx.api_allowlist.is_empty()
+        && !matched.is_some_and(|path| {
+            cx.api_allowlist.iter().any(|rule| {
+                rule.path == path.as_str()
+                    && rule
+                        .method
+                        .as_ref()
+                        .is_none_or(|method| method == request.method())
+            })
+        })
+    {
+        return Err(Error::ApiNotAllowed);
+    }
+
+    Ok(next.run(request).await)
+}
+
 struct ContentType(String);
 
 impl<S: Sync> axum::extract::FromRequestParts<S> for ContentType {
@@ -350,26 +2161,56 @@ enum Error {
     PermissionDenied,
     #[error("invalid header value: {0}")]
     InvalidHeaderEncoding(#[from] http::header::ToStrError),
-    #[error("invalid authentication method, only bearer authentication is supported.")]
+    #[error("invalid or unaccepted authentication method")]
     InvalidAuthMethod,
     #[error("function manager error: {0}")]
     FunctionManager(#[from] func::ManagerError),
     #[error("user manager error: {0}")]
     UserManager(#[from] user::ManagerError),
+    #[error("invalid group: {0}")]
+    InvalidGroup(#[from] user::ParseGroupError),
     #[error("missing content-type header")]
     MissingContentType,
     #[error(
         "unsupported archive type, the only supported archive type is tarball with optional gzip compression"
     )]
     UnsupportedArchiveType,
+    #[error(
+        "unable to detect archive type from the content, specify the content-type header explicitly"
+    )]
+    UnrecognizedArchive,
     #[error("specified resource not found")]
     NotFound,
+    #[error("function or alias {0} not found")]
+    FunctionNotFound(OwnedKey),
+    #[error("function {0} exists but the token is not a member of the group managing it")]
+    GroupPermissionDenied(OwnedKey),
+    #[error("no running instance of function {0}")]
+    InstanceNotFound(OwnedKey),
+    #[error("alias {requested} was changed to {resolved} while deploying, retry to deploy it")]
+    AliasRetargeted {
+        requested: OwnedKey,
+        resolved: OwnedKey,
+    },
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
-    #[error("invalid key format. the permitted key characters are: a-z, 0-9, -")]
-    InvalidKeyFormat,
+    #[error(
+        "invalid key format. the permitted key characters are: a-z, 0-9, -, \
+        and the length of names, versions and aliases is limited to {0} characters"
+    )]
+    InvalidKeyFormat(usize),
+    #[error("label {0} is reserved and could not be used as function names, versions or aliases")]
+    ReservedLabel(String),
     #[error("another instance of this function is already running")]
     InstanceAlreadyRunning,
+    #[error(
+        "{requested} resolves to the same function as {running} which is already running. \
+        promote {requested} instead to route its host to the running instance"
+    )]
+    AliasedInstanceRunning {
+        requested: OwnedKey,
+        running: OwnedKey,
+    },
     #[error("invalid uri parsed from socket address: {0}")]
     InvalidSocketAddrAsUri(#[from] http::uri::InvalidUri),
     #[error("invalid username format. the permitted key characters are: A-Z, a-z, 0-9, -")]
@@ -384,10 +2225,69 @@ enum Error {
     InvalidUriParts(#[from] http::uri::InvalidUriParts),
     #[error("HTTP client error occurred: {0}")]
     Client(#[from] client::legacy::Error),
+    #[error("timed out connecting to the function")]
+    UpstreamConnectTimeout,
+    #[error("the function did not respond in time")]
+    UpstreamTimeout,
+    #[error("the request body exceeds the limit of {0} bytes")]
+    ProxyBodyTooLarge(u64),
+    #[error(
+        "the function did not get ready in time{}",
+        .0.as_ref().map_or_else(String::new, |probe| format!(", last probe {probe}"))
+    )]
+    FunctionNotReady(Option<String>),
+    #[cfg(not(feature = "upstream-tls"))]
+    #[error("the function serves TLS but upstream TLS support is not enabled in this build")]
+    UpstreamTlsDisabled,
+    #[cfg(feature = "tls")]
+    #[error("host {0} does not match the server name of the connection")]
+    MisdirectedRequest(String),
+    #[error("too many websocket connections to the function")]
+    TooManyWsConnections,
+    #[error("HTTP connection error occurred: {0}")]
+    Connection(#[from] hyper::Error),
+    #[error("failed to read request body: {0}")]
+    Body(#[from] axum::Error),
     #[error("websocket connection error occurred: {0}")]
     WebsocketConnection(#[from] tungstenite::Error),
     #[error("feature {0} is unstable")]
     Unstable(&'static str),
+    #[error("the requested API is not allowed on this instance")]
+    ApiNotAllowed,
+    #[error(
+        "failed to create the sandbox due to insufficient privileges ({0}). \
+        enable unprivileged user namespaces (e.g. `sysctl kernel.unprivileged_userns_clone=1`) \
+        or install bwrap with the setuid bit"
+    )]
+    SandboxPrivilegeDenied(String),
+    #[error("upload session error: {0}")]
+    Upload(#[from] upload::UploadError),
+    #[error("missing or invalid content-range header")]
+    InvalidContentRange,
+    #[error("invalid route: {0}")]
+    InvalidRoute(&'static str),
+    #[error("{0}")]
+    InvalidEnvFile(String),
+    #[error("timed out receiving the uploaded archive")]
+    UploadTimeout,
+    #[error("the uploaded archive exceeds the limit of {0} bytes")]
+    UploadTooLarge(u64),
+    #[error("uploaded contents exceed the disk quota of {0} bytes of the user")]
+    QuotaExceeded(u64),
+    #[error("failed to build the function, {0}")]
+    BuildFailed(String),
+    #[error("invalid file path, which must be relative and stay within the contents")]
+    InvalidFilePath,
+    #[error("migration error: {0}")]
+    Migrate(#[from] migrate::Error),
+    #[error("the instance already holds functions or users, import with `force` to replace them")]
+    InstanceNotEmpty,
+    #[error("functions are running, stop them before forcing an import")]
+    InstancesRunning,
+    #[error("function {0} is running, stop it before renaming")]
+    RenamingRunning(OwnedKey),
+    #[error("alias is required to {0} an alias")]
+    MissingAlias(&'static str),
 }
 
 impl Error {
@@ -397,35 +2297,94 @@ impl Error {
             Self::Unauthorized | Self::InvalidAuthMethod => StatusCode::UNAUTHORIZED,
 
             Self::PermissionDenied
-            | Self::InvalidKeyFormat
+            | Self::InvalidKeyFormat(_)
+            | Self::ReservedLabel(_)
             | Self::InvalidUsernameFormat
             | Self::ModifyRootUser
+            | Self::GroupPermissionDenied(_)
             | Self::FunctionNotRunning
-            | Self::Unstable(_) => StatusCode::FORBIDDEN,
+            | Self::Unstable(_)
+            | Self::ApiNotAllowed => StatusCode::FORBIDDEN,
 
             Self::InvalidHeaderEncoding(_)
             | Self::MissingContentType
             | Self::UnsupportedArchiveType
+            | Self::UnrecognizedArchive
             | Self::MissingHost
-            | Self::InvalidUriParts(_) => StatusCode::BAD_REQUEST,
+            | Self::InvalidUriParts(_)
+            | Self::Body(_)
+            | Self::InvalidRoute(_)
+            | Self::InvalidEnvFile(_)
+            | Self::InvalidFilePath
+            | Self::InvalidGroup(_)
+            | Self::InvalidContentRange
+            | Self::MissingAlias(_) => StatusCode::BAD_REQUEST,
+
+            Self::NotFound | Self::FunctionNotFound(_) | Self::InstanceNotFound(_) => {
+                StatusCode::NOT_FOUND
+            }
+
+            Self::UploadTimeout => StatusCode::REQUEST_TIMEOUT,
 
-            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::UploadTooLarge(_) | Self::QuotaExceeded(_) | Self::ProxyBodyTooLarge(_) => {
+                StatusCode::PAYLOAD_TOO_LARGE
+            }
+
+            Self::BuildFailed(_) => StatusCode::UNPROCESSABLE_ENTITY,
 
             Self::Io(_)
+            | Self::SandboxPrivilegeDenied(_)
             | Self::InvalidSocketAddrAsUri(_)
             | Self::Client(_)
+            | Self::Connection(_)
             | Self::WebsocketConnection(_) => StatusCode::INTERNAL_SERVER_ERROR,
 
-            Self::InstanceAlreadyRunning => StatusCode::CONFLICT,
+            Self::InstanceAlreadyRunning
+            | Self::AliasedInstanceRunning { .. }
+            | Self::AliasRetargeted { .. }
+            | Self::InstanceNotEmpty
+            | Self::InstancesRunning
+            | Self::RenamingRunning(_) => StatusCode::CONFLICT,
+
+            Self::UpstreamConnectTimeout | Self::UpstreamTimeout | Self::FunctionNotReady(_) => {
+                StatusCode::GATEWAY_TIMEOUT
+            }
+
+            Self::TooManyWsConnections => StatusCode::SERVICE_UNAVAILABLE,
+
+            #[cfg(not(feature = "upstream-tls"))]
+            Self::UpstreamTlsDisabled => StatusCode::NOT_IMPLEMENTED,
+
+            #[cfg(feature = "tls")]
+            Self::MisdirectedRequest(_) => StatusCode::MISDIRECTED_REQUEST,
 
             // function manager
             Self::FunctionManager(e) => match e {
-                func::ManagerError::NotAliased => StatusCode::FORBIDDEN,
                 func::ManagerError::Io(_)
                 | func::ManagerError::ParseJson(_)
                 | func::ManagerError::Initialized => StatusCode::INTERNAL_SERVER_ERROR,
-                func::ManagerError::Duplicated => StatusCode::CONFLICT,
-                func::ManagerError::NotFound => StatusCode::NOT_FOUND,
+                func::ManagerError::Duplicated | func::ManagerError::Running => {
+                    StatusCode::CONFLICT
+                }
+                func::ManagerError::NotFound | func::ManagerError::NotAliased => {
+                    StatusCode::NOT_FOUND
+                }
+                func::ManagerError::TooManyEntries { .. }
+                | func::ManagerError::EnvFileNotAllowed(_)
+                | func::ManagerError::InvalidAutoscale => StatusCode::BAD_REQUEST,
+                func::ManagerError::UnpackLimitExceeded => StatusCode::PAYLOAD_TOO_LARGE,
+                _ => StatusCode::IM_A_TEAPOT, // non-exhaustive aftermath
+            },
+
+            // upload sessions
+            Self::Upload(e) => match e {
+                upload::UploadError::NotFound => StatusCode::NOT_FOUND,
+                upload::UploadError::Duplicated | upload::UploadError::Incomplete { .. } => {
+                    StatusCode::CONFLICT
+                }
+                upload::UploadError::OffsetMismatch { .. } => StatusCode::RANGE_NOT_SATISFIABLE,
+                upload::UploadError::TooManySessions { .. } => StatusCode::TOO_MANY_REQUESTS,
+                upload::UploadError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
                 _ => StatusCode::IM_A_TEAPOT, // non-exhaustive aftermath
             },
 
@@ -434,10 +2393,32 @@ impl Error {
                 user::ManagerError::Io(_)
                 | user::ManagerError::ParseJson(_)
                 | user::ManagerError::Initialized => StatusCode::INTERNAL_SERVER_ERROR,
-                user::ManagerError::Duplicated => StatusCode::CONFLICT,
-                user::ManagerError::NotFound => StatusCode::NOT_FOUND,
+                user::ManagerError::Duplicated
+                | user::ManagerError::GroupDuplicated
+                | user::ManagerError::GroupInUse => StatusCode::CONFLICT,
+                user::ManagerError::NotFound | user::ManagerError::GroupNotFound => {
+                    StatusCode::NOT_FOUND
+                }
+                user::ManagerError::Expired => StatusCode::GONE,
+                user::ManagerError::ScopeExceeded => StatusCode::FORBIDDEN,
+                user::ManagerError::DurationExceeded => StatusCode::BAD_REQUEST,
                 _ => StatusCode::IM_A_TEAPOT, // non-exhaustive aftermath
             },
+
+            // migration
+            Self::Migrate(e) => match e {
+                migrate::Error::ParseJson(_)
+                | migrate::Error::InvalidEntry(_)
+                | migrate::Error::Incomplete(_)
+                | migrate::Error::Function(
+                    func::ManagerError::TooManyEntries { .. }
+                    | func::ManagerError::EnvFileNotAllowed(_)
+                    | func::ManagerError::InvalidAutoscale,
+                ) => StatusCode::BAD_REQUEST,
+                migrate::Error::Function(func::ManagerError::Duplicated)
+                | migrate::Error::User(user::ManagerError::Duplicated) => StatusCode::CONFLICT,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            },
         }
     }
 }
@@ -465,33 +2446,461 @@ struct Args {
     #[arg(short, long)]
     path: Option<PathBuf>,
     /// IP address host (without port number) to bind to.
-    #[arg(short, long)]
+    #[arg(short, long, conflicts_with = "unix_socket")]
     addr: Option<IpAddr>,
     /// Port to bind to.
-    #[arg(short, long, default_value_t = 8080)]
+    #[arg(long, default_value_t = 8080, conflicts_with = "unix_socket")]
     port: u16,
+    /// Path to a unix socket to listen on instead of TCP, for deployments behind a local reverse
+    /// proxy.
+    ///
+    /// A stale socket left at the path is replaced, and the socket is removed on shutdown.
+    #[arg(long)]
+    unix_socket: Option<PathBuf>,
     /// Host name to use.
     #[arg(short, long)]
     host: String,
+    /// Interval in seconds of removing expired tokens, jittered by up to a sixth of it.
+    #[arg(long, default_value_t = NonZeroU64::new(60 * 60).unwrap())]
+    token_prune_interval_secs: NonZeroU64,
+    /// Path to a PEM certificate chain for serving HTTPS, which should cover subdomains of the
+    /// host for functions.
+    ///
+    /// Plain HTTP is served if absent.
+    #[cfg(feature = "tls")]
+    #[arg(long, requires = "tls_key", conflicts_with = "unix_socket")]
+    tls_cert: Option<PathBuf>,
+    /// Path to the PEM private key of the certificate given by `--tls-cert`.
+    #[cfg(feature = "tls")]
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+    /// Run without reading from or writing to the filesystem, unpacking function contents into a
+    /// temporary directory removed on shutdown.
+    #[arg(long)]
+    in_memory: bool,
+    /// Allowed management API in form of `METHOD PATH`, for example `DELETE /api/remove/{key}`.
+    ///
+    /// Could be specified multiple times. All APIs are allowed if absent.
+    #[arg(long)]
+    api_allow: Vec<ApiAllowRule>,
+    /// Timeout in seconds for establishing connections to functions.
+    #[arg(long, default_value_t = 10)]
+    upstream_connect_timeout: u64,
+    /// Plain HTTP URL to post lifecycle events of functions to, like
+    /// `http://localhost:9000/events`.
+    #[arg(long, value_parser = parse_webhook)]
+    lifecycle_webhook: Option<http::Uri>,
+    /// Timeout in seconds for functions to respond to proxied requests with headers once their
+    /// bodies are sent, which could be overridden by functions. Disabled if zero.
+    ///
+    /// Bodies streamed in either direction and WebSocket connections are not affected.
+    #[arg(long, default_value_t = 30)]
+    proxy_timeout: u64,
+    /// Times of retrying idempotent requests failed to connect to functions, with exponential
+    /// backoff.
+    #[arg(long, default_value_t = 0)]
+    upstream_retries: u32,
+    /// Path to a PEM bundle of root certificates trusted for functions serving HTTPS, in addition
+    /// to the native ones.
+    #[cfg(feature = "upstream-tls")]
+    #[arg(long, conflicts_with = "upstream_insecure")]
+    upstream_ca: Option<PathBuf>,
+    /// Skip verifying certificates of functions serving HTTPS. For development only.
+    #[cfg(feature = "upstream-tls")]
+    #[arg(long)]
+    upstream_insecure: bool,
+    /// Maximum size in bytes of request bodies buffered for retrying.
+    ///
+    /// Requests with larger bodies or bodies without known length are never retried. Only
+    /// requests without bodies are retried by default.
+    #[arg(long, default_value_t = 0)]
+    buffer_retryable_bodies: usize,
+    /// Threshold in milliseconds of response time of functions, exceeding which is logged at warn
+    /// level.
+    ///
+    /// Measured until response headers are received, thus not applying to WebSocket connections.
+    /// Disabled if absent.
+    #[arg(long)]
+    slow_response_threshold: Option<u64>,
+    /// Timeout in seconds of responses of Server-Sent Events (`text/event-stream`) receiving no
+    /// data from the function, after which the stream is cut off.
+    ///
+    /// Event streams are long-lived so they're never cut off while active. Unlimited if absent.
+    #[arg(long)]
+    sse_idle_timeout: Option<NonZeroU64>,
+    /// Maximum size in bytes of bodies of requests proxied to functions.
+    ///
+    /// Requests exceeding it are rejected, or aborted if streamed without a declared length.
+    /// Frames of WebSocket connections are governed separately. Unlimited if not specified.
+    #[arg(long)]
+    max_proxy_body_bytes: Option<u64>,
+    /// Maximum size in bytes of a single frame received from either side of relayed WebSocket
+    /// connections.
+    ///
+    /// Connections receiving larger frames are closed with `1009 Message Too Big` on both sides.
+    #[arg(long, default_value_t = 16 << 20)]
+    max_ws_frame_bytes: usize,
+    /// Maximum size in bytes of a message received from either side of relayed WebSocket
+    /// connections, which may consist of several frames.
+    ///
+    /// Connections receiving larger messages are closed with `1009 Message Too Big` on both sides.
+    #[arg(long, default_value_t = 64 << 20)]
+    max_ws_message_bytes: usize,
+    /// Interval in seconds of pinging both sides of relayed WebSocket connections for detecting
+    /// dead peers. Disabled if zero.
+    #[arg(long, default_value_t = 30)]
+    ws_ping_interval: u64,
+    /// Timeout in seconds for sides of relayed WebSocket connections to respond to pings, after
+    /// which both sides are torn down.
+    ///
+    /// Also bounds the wait for a side to finish the closing handshake after the other one closed.
+    #[arg(long, default_value_t = 10)]
+    ws_pong_timeout: u64,
+    /// Log kind and size of every relayed WebSocket message for auditing, without payloads.
+    #[arg(long)]
+    ws_audit: bool,
+    /// Timeout in seconds for stopping functions and saving data on shutdown.
+    #[arg(long, default_value_t = 30)]
+    shutdown_timeout: u64,
+    /// Timeout in seconds for requests being handled by a function to finish when draining it,
+    /// after which it is stopped anyway.
+    #[arg(long, default_value_t = 30)]
+    drain_timeout: u64,
+    /// Maximum grace period in seconds between asking a function to terminate and killing it,
+    /// capping `shutdown_grace` of functions so none of them stalls stopping indefinitely.
+    #[arg(long, default_value_t = 30)]
+    max_shutdown_grace: u64,
+    /// Header to be removed from responses of functions.
+    ///
+    /// Could be specified multiple times.
+    #[arg(long)]
+    strip_response_headers: Vec<http::HeaderName>,
+    /// Header in form of `NAME: VALUE` to be set on responses of functions.
+    ///
+    /// Could be specified multiple times.
+    #[arg(long)]
+    add_response_headers: Vec<ResponseHeader>,
+    /// Accepted schemes of carrying tokens, tried in given order.
+    ///
+    /// The `query` scheme is less secure as tokens may leak into logs, thus should be enabled
+    /// explicitly.
+    #[arg(long, value_enum, default_values_t = [AuthScheme::Bearer, AuthScheme::Header])]
+    auth_scheme: Vec<AuthScheme>,
+    /// Mode of routing public traffic to functions.
+    ///
+    /// Subdomains are not routed to functions in path mode, nor are paths in subdomain mode.
+    #[arg(long, value_enum, default_value_t = Routing::Subdomain)]
+    routing: Routing,
+    /// Label not allowed as function names, versions or aliases, which become labels of
+    /// subdomains.
+    ///
+    /// Could be specified multiple times. Labels of the platform host are always reserved.
+    #[arg(long, default_values_t = ["api".to_owned(), "admin".to_owned(), "www".to_owned()])]
+    reserved_label: Vec<String>,
+    /// Maximum length of function names, versions and aliases.
+    ///
+    /// Defaults to the maximum length of a DNS label.
+    #[arg(long, default_value_t = NonZeroUsize::new(63).unwrap())]
+    max_function_name_length: NonZeroUsize,
+    /// Respond requests to subdomains without running functions with a bare 404, logging them at
+    /// trace level only.
+    #[arg(long)]
+    quiet_proxy_404: bool,
+    /// Maximum count of output lines kept for each running function.
+    #[arg(long, default_value_t = 1024)]
+    log_buffer_lines: usize,
+    /// Maximum size in bytes of each output log file of functions writing outputs into files,
+    /// before rotating to a new one.
+    #[arg(long, default_value_t = 10 * 1024 * 1024)]
+    log_file_max_bytes: u64,
+    /// Maximum count of output log files kept for each function, beyond which the oldest ones are
+    /// removed on rotating. Unlimited if zero.
+    #[arg(long, default_value_t = 10)]
+    log_file_max_count: usize,
+    /// Valid duration in days of tokens requested without specifying one.
+    #[arg(
+        long,
+        default_value_t = 10,
+        value_parser = clap::value_parser!(i64).range(1..=user::MAX_TOKEN_DURATION.whole_days()),
+    )]
+    default_token_duration: i64,
+    /// Time in seconds after which inactive upload sessions are removed.
+    #[arg(long, default_value_t = 60 * 60)]
+    upload_session_ttl: u64,
+    /// Maximum count of open upload sessions of each user.
+    #[arg(long, default_value_t = 8)]
+    max_upload_sessions_per_user: usize,
+    /// Maximum count of environment variables in a function configuration.
+    #[arg(long, default_value_t = 1024)]
+    max_config_envs: usize,
+    /// Maximum count of read-only entries in a function configuration.
+    #[arg(long, default_value_t = 256)]
+    max_config_ro_entries: usize,
+    /// Maximum count of filtered syscalls in a function configuration.
+    #[arg(long, default_value_t = 1024)]
+    max_config_syscall_filter: usize,
+    /// Interval in seconds of writing dirty functions and users into the storage.
+    ///
+    /// Data could also be written on demand through `POST /api/admin/flush`.
+    #[arg(long, default_value_t = NonZeroU64::new(720).unwrap())]
+    save_interval_secs: NonZeroU64,
+    /// Count of consecutive failed saves into the storage after which the readiness probe fails,
+    /// until a save succeeds again. Disabled if zero.
+    #[arg(long, default_value_t = 3)]
+    readiness_max_save_failures: u64,
+    /// Duration in seconds saves into the storage could keep failing before the readiness probe
+    /// fails, until a save succeeds again. Disabled if zero.
+    #[arg(long, default_value_t = 60 * 60)]
+    readiness_max_failing_secs: u64,
+    /// Interval in seconds of reconciling routes of public traffic with running functions.
+    #[arg(long, default_value_t = NonZeroU64::new(60).unwrap())]
+    reconcile_interval: NonZeroU64,
+    /// Maximum count of uploaded archives being unpacked simultaneously, with excess ones queued.
+    ///
+    /// Unlimited if not specified.
+    #[arg(long)]
+    max_concurrent_uploads: Option<NonZeroUsize>,
+    /// Maximum size in bytes of an uploaded archive, as received in request bodies.
+    ///
+    /// Uploads exceeding it are aborted and rolled back. Unlimited if not specified.
+    #[arg(long)]
+    max_upload_bytes: Option<u64>,
+    /// Timeout in seconds of receiving and unpacking an uploaded archive, after which the upload
+    /// is aborted and rolled back.
+    #[arg(long, default_value_t = NonZeroU64::new(600).unwrap())]
+    upload_timeout: NonZeroU64,
+    /// Timeout in seconds of running the build command of a function, after which the build is
+    /// killed and fails.
+    #[arg(long, default_value_t = NonZeroU64::new(600).unwrap())]
+    build_timeout: NonZeroU64,
+    /// Path to a JSON file of function configuration new functions start with.
+    ///
+    /// Group of the configuration is ignored as it's always initialized by the uploader.
+    #[arg(long)]
+    default_config: Option<PathBuf>,
+    /// Directory in the host system environment variable files of functions with absolute paths
+    /// are allowed under.
+    ///
+    /// Could be specified multiple times. Absolute paths are rejected if absent, while relative
+    /// ones are always allowed as long as they stay inside contents of the function.
+    #[arg(long)]
+    env_file_dir: Vec<PathBuf>,
+    /// Path to a file containing the token of the root account, kept across restarts.
+    ///
+    /// Takes precedence over `YFASS_ROOT_TOKEN` environment variable. A random token is generated
+    /// for each session if neither is provided.
+    #[arg(long)]
+    root_token_file: Option<PathBuf>,
+    /// Key of function to be deployed on startup before accepting connections.
+    ///
+    /// Could be specified multiple times.
+    #[arg(long)]
+    preload: Vec<OwnedKey>,
+    /// Number of worker threads of the async runtime.
+    ///
+    /// Defaults to the number of CPU cores.
+    #[arg(long)]
+    worker_threads: Option<NonZeroUsize>,
+}
+
+/// Outcomes of saving data into the storage, for monitoring silent persistence failures.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+struct SaveState {
+    /// Count of consecutive failed saves.
+    consecutive_failures: u64,
+    /// Unix timestamp of the last successful save.
+    last_saved: Option<i64>,
+    /// Unix timestamp of the first failed save since the last successful one.
+    failing_since: Option<i64>,
 }
 
-async fn save_data(cx: &LocalCx) {
-    let span = tracing::info_span!("writing data into filesystem");
+impl LocalCx {
+    /// Whether saving data into the storage has kept failing beyond the thresholds of the
+    /// readiness probe.
+    fn is_save_failing(&self) -> bool {
+        let state = *self.save_state.lock();
+        let too_many = self
+            .readiness_max_save_failures
+            .is_some_and(|max| state.consecutive_failures >= max.get());
+        let too_long = self
+            .readiness_max_failing_secs
+            .zip(state.failing_since)
+            .is_some_and(|(max, since)| {
+                let elapsed = time::UtcDateTime::now().unix_timestamp() - since;
+                elapsed >= i64::try_from(max.get()).unwrap_or(i64::MAX)
+            });
+        too_many || too_long
+    }
+}
+
+/// Writes dirty functions and users into the storage.
+///
+/// # Errors
+///
+/// - Returns the first error occurred, after trying to write both functions and users.
+async fn save_data(cx: &LocalCx) -> Result<(), Error> {
+    let _saving = cx.saving.lock().await;
+    let span = tracing::info_span!("writing data into storage");
     let mut e = None;
+    let mut result = Ok(());
 
     if cx.funcs.is_dirty() {
         e = Some(e.unwrap_or_else(|| span.enter()));
-        drop(cx.funcs.write_all_to_fs().await.inspect_err(|err| {
-            tracing::error!("failed to write function information into filesystem: {err}")
-        }))
+        if let Err(err) = cx.funcs.write_all_to_storage().await {
+            tracing::error!("failed to write function information into storage: {err}");
+            result = Err(err.into());
+        }
     }
 
     if cx.users.is_dirty() {
         e = Some(e.unwrap_or_else(|| span.enter()));
-        drop(cx.users.write_all_to_fs().await.inspect_err(|err| {
-            tracing::error!("failed to write user information into filesystem: {err}")
-        }))
+        if let Err(err) = cx.users.write_all_to_storage().await {
+            tracing::error!("failed to write user information into storage: {err}");
+            result = result.and(Err(err.into()));
+        }
+    }
+
+    // nothing is written if nothing is dirty
+    if e.is_some() {
+        let now = time::UtcDateTime::now().unix_timestamp();
+        let mut state = cx.save_state.lock();
+        if result.is_err() {
+            state.consecutive_failures += 1;
+            state.failing_since.get_or_insert(now);
+        } else {
+            *state = SaveState {
+                consecutive_failures: 0,
+                last_saved: Some(now),
+                failing_since: None,
+            };
+        }
     }
 
     drop(e); // emit unread warnings
+    result
+}
+
+#[cfg(unix)]
+type UnixListener = tokio::net::UnixListener;
+// never bound on platforms without unix sockets
+#[cfg(not(unix))]
+type UnixListener = tokio::net::TcpListener;
+
+/// Binds to a unix socket at given path, replacing the stale one left by a previous run.
+#[cfg(unix)]
+async fn bind_unix(path: &Path) -> std::io::Result<UnixListener> {
+    use std::os::unix::fs::FileTypeExt as _;
+
+    // sockets still accepted on belong to running servers, and binding to them fails
+    if tokio::fs::symlink_metadata(path)
+        .await
+        .is_ok_and(|meta| meta.file_type().is_socket())
+        && tokio::net::UnixStream::connect(path).await.is_err()
+    {
+        tracing::info!("removing stale unix socket {}", path.display());
+        tokio::fs::remove_file(path).await?;
+    }
+    UnixListener::bind(path)
+}
+
+#[cfg(not(unix))]
+async fn bind_unix(_: &Path) -> std::io::Result<UnixListener> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// Kills given replicas of a function.
+async fn kill_replicas(replicas: Vec<Replica>) {
+    for replica in replicas {
+        sandbox::Handle::kill(replica.handle).await;
+    }
+}
+
+/// Terminates given replicas of a function at the same time, killing the ones still running
+/// after the grace period.
+async fn terminate_replicas(replicas: Vec<Replica>, grace: tokio::time::Duration) {
+    futures_util::future::join_all(
+        replicas
+            .into_iter()
+            .map(|replica| sandbox::Handle::terminate(replica.handle, grace)),
+    )
+    .await;
+}
+
+/// Serves the platform on given bound listener until the shutdown signal completes.
+async fn serve<L, F>(cx: &LocalCx, listener: L, app: Router, signal: F)
+where
+    L: axum::serve::Listener,
+    L::Addr: std::fmt::Debug,
+    F: Future<Output = ()> + Send + 'static,
+{
+    cx.ready.store(true, Ordering::Relaxed);
+    axum::serve(listener, app.into_make_service())
+        .with_graceful_shutdown(signal)
+        .await
+        .unwrap();
+}
+
+/// Serves the platform over TLS like [`serve`], rejecting requests addressed to hosts other than
+/// the server names of their connections.
+#[cfg(feature = "tls")]
+async fn serve_tls<F>(cx: &LocalCx, listener: tls_listener::TlsListener, app: Router, signal: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    cx.ready.store(true, Ordering::Relaxed);
+    let app = app.layer(middleware::from_fn(tls_listener::check_host));
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<tls_listener::ServerName>(),
+    )
+    .with_graceful_shutdown(signal)
+    .await
+    .unwrap();
+}
+
+/// Replaces errors of unpacking refused by given reservation with a dedicated one.
+fn quota_error(quota: Option<&QuotaReservation>, err: func::ManagerError) -> Error {
+    match (quota, err) {
+        (Some(quota), func::ManagerError::UnpackLimitExceeded) => Error::QuotaExceeded(quota.total),
+        (_, err) => err.into(),
+    }
+}
+
+/// Stops all running functions and saves data within given timeout.
+async fn shutdown(cx: &Arc<LocalCx>, timeout: tokio::time::Duration) {
+    // load balancers drain the node meanwhile
+    cx.ready.store(false, Ordering::Relaxed);
+
+    let mut keys = HashSet::new();
+    cx.handles.iter_sync(|key, _| {
+        keys.insert(key.clone());
+        true
+    });
+    let pending = Arc::new(Mutex::new(keys.clone()));
+
+    let mut js = JoinSet::new();
+    for key in keys {
+        let cx = cx.clone();
+        let pending = pending.clone();
+        js.spawn(async move {
+            drop(cx.stop_fn(key.as_ref()).await);
+            pending.lock().remove(&key);
+        });
+    }
+
+    let sequence = async {
+        // failures are logged by saving
+        drop(tokio::join!(js.join_all(), save_data(cx)));
+    };
+    if tokio::time::timeout(timeout, sequence).await.is_err() {
+        tracing::error!(
+            "shutdown timed out after {}s, functions failed to stop: {:?}",
+            timeout.as_secs(),
+            pending
+                .lock()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        );
+    }
 }
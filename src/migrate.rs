@@ -0,0 +1,264 @@
+//! Export and import of the full platform state.
+//!
+//! # Archive Layout
+//!
+//! The state is packed into an uncompressed tarball with the following structure:
+//!
+//! ```text
+//! - users.json
+//! - (dir) functions
+//!   - [[(dir) name@version]]
+//!     - metadata.json
+//!     - config.json
+//!     - (dir) contents
+//!       - ...
+//! ```
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Component, Path},
+    str::FromStr as _,
+};
+
+use futures_util::StreamExt as _;
+use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWrite};
+use tokio_tar::{Archive, Builder, Header};
+
+use crate::{
+    func::{self, Function, FunctionManager, OwnedKey},
+    user::{self, UserManager},
+};
+
+const FILE_USERS: &str = "users.json";
+const DIR_FUNCTIONS: &str = "functions";
+const FILE_METADATA: &str = "metadata.json";
+const FILE_CONFIG: &str = "config.json";
+const DIR_CONTENTS: &str = "contents";
+
+/// Writes the state of given managers into a tarball, returning the writer after finishing.
+///
+/// # Errors
+///
+/// Returns an error if the state could not be serialized or written.
+pub async fn export<W>(
+    functions: &FunctionManager,
+    users: &UserManager,
+    writer: W,
+) -> Result<W, Error>
+where
+    W: AsyncWrite + Unpin + Send + Sync + 'static,
+{
+    let mut builder = Builder::new(writer);
+    // symbolic links in contents are archived as is, never pulling in files outside
+    builder.follow_symlinks(false);
+    append_blob(&mut builder, FILE_USERS, &users.export()?).await?;
+
+    for func in functions.functions_by_group(None) {
+        let key = OwnedKey {
+            name: func.meta.name.clone(),
+            version: func.meta.version.clone(),
+        };
+        let dir = format!("{DIR_FUNCTIONS}/{key}");
+        append_blob(
+            &mut builder,
+            &format!("{dir}/{FILE_METADATA}"),
+            &serde_json::to_vec_pretty(&func.meta)?,
+        )
+        .await?;
+        append_blob(
+            &mut builder,
+            &format!("{dir}/{FILE_CONFIG}"),
+            &serde_json::to_vec_pretty(&func.config)?,
+        )
+        .await?;
+
+        let contents = functions.contents_path(key.as_ref());
+        if tokio::fs::try_exists(&contents).await? {
+            builder
+                .append_dir_all(format!("{dir}/{DIR_CONTENTS}"), contents)
+                .await?;
+        }
+    }
+
+    Ok(builder.into_inner().await?)
+}
+
+/// Restores the state from a tarball into given managers, returning keys of imported functions.
+///
+/// Everything is unpacked and validated before touching the managers. Existing functions and
+/// users are replaced if `replace` is set, otherwise conflicts are rejected.
+///
+/// # Errors
+///
+/// - Returns an error if the tarball is corrupted or doesn't follow the layout.
+/// - Returns an error if any function or user conflicts with existing ones without `replace`.
+pub async fn import<R>(
+    functions: &FunctionManager,
+    users: &UserManager,
+    archive: &mut Archive<R>,
+    replace: bool,
+) -> Result<Vec<OwnedKey>, Error>
+where
+    R: AsyncRead + Unpin,
+{
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let staging = functions.import_staging_path(&nanos.to_string());
+    tokio::fs::create_dir_all(&staging).await?;
+
+    let result = import_staged(functions, users, archive, replace, &staging).await;
+    drop(
+        tokio::fs::remove_dir_all(&staging)
+            .await
+            .inspect_err(|e| tracing::error!("failed to remove staged imports: {e}")),
+    );
+    result
+}
+
+/// Serialized information of a function read from the archive.
+#[derive(Default)]
+struct FunctionBlobs {
+    metadata: Option<Vec<u8>>,
+    config: Option<Vec<u8>>,
+}
+
+async fn import_staged<R>(
+    functions: &FunctionManager,
+    users: &UserManager,
+    archive: &mut Archive<R>,
+    replace: bool,
+    staging: &Path,
+) -> Result<Vec<OwnedKey>, Error>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut raw_users = None;
+    let mut blobs: BTreeMap<String, FunctionBlobs> = BTreeMap::new();
+
+    let mut entries = archive.entries()?;
+    while let Some(entry) = entries.next().await {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let mut components = Vec::new();
+        for component in path.components() {
+            match component {
+                Component::Normal(c) => components.push(
+                    c.to_str()
+                        .ok_or_else(|| Error::InvalidEntry(path.display().to_string()))?,
+                ),
+                Component::CurDir => {}
+                _ => return Err(Error::InvalidEntry(path.display().to_string())),
+            }
+        }
+
+        match components[..] {
+            [FILE_USERS] => {
+                let mut buf = Vec::new();
+                entry.read_to_end(&mut buf).await?;
+                raw_users = Some(buf);
+            }
+            [DIR_FUNCTIONS, key, file @ (FILE_METADATA | FILE_CONFIG)] => {
+                let mut buf = Vec::new();
+                entry.read_to_end(&mut buf).await?;
+                let slot = blobs.entry(key.to_owned()).or_default();
+                if file == FILE_METADATA {
+                    slot.metadata = Some(buf);
+                } else {
+                    slot.config = Some(buf);
+                }
+            }
+            [DIR_FUNCTIONS, _, DIR_CONTENTS, ..] => {
+                if !entry.unpack_in(staging).await? {
+                    return Err(Error::InvalidEntry(path.display().to_string()));
+                }
+            }
+            // parent directories
+            [] | [DIR_FUNCTIONS] | [DIR_FUNCTIONS, _] if entry.header().entry_type().is_dir() => {}
+            _ => return Err(Error::InvalidEntry(path.display().to_string())),
+        }
+    }
+
+    let mut funcs = Vec::with_capacity(blobs.len());
+    for (dir, blob) in blobs {
+        let key = OwnedKey::from_str(&dir).map_err(|_| Error::InvalidEntry(dir.clone()))?;
+        let FunctionBlobs {
+            metadata: Some(meta),
+            config: Some(config),
+        } = blob
+        else {
+            return Err(Error::Incomplete(dir));
+        };
+        let func = Function {
+            meta: serde_json::from_slice(&meta)?,
+            config: serde_json::from_slice(&config)?,
+        };
+        if func.meta.name != key.name || func.meta.version != key.version {
+            return Err(Error::InvalidEntry(dir));
+        }
+        functions.check_import(&func, replace)?;
+        funcs.push((key, func));
+    }
+    // aliases never shadow versions being imported either
+    let imported: HashSet<_> = funcs.iter().map(|(key, _)| key.as_ref()).collect();
+    if funcs.iter().any(|(key, func)| {
+        func.meta.version_aliases.iter().any(|alias| {
+            imported.contains(&func::Key {
+                name: &key.name,
+                version: alias,
+            })
+        })
+    }) {
+        return Err(func::ManagerError::Duplicated.into());
+    }
+    drop(imported);
+
+    // everything is validated before touching the managers, so failures leave them untouched
+    if let Some(raw) = &raw_users {
+        users.check_import(raw, replace)?;
+    }
+    if let Some(raw) = raw_users {
+        users.import(&raw, replace)?;
+    }
+
+    let mut keys = Vec::with_capacity(funcs.len());
+    for (key, func) in funcs {
+        let contents = staging
+            .join(DIR_FUNCTIONS)
+            .join(key.to_string())
+            .join(DIR_CONTENTS);
+        functions.import_func(func, &contents, replace).await?;
+        keys.push(key);
+    }
+    Ok(keys)
+}
+
+async fn append_blob<W>(builder: &mut Builder<W>, path: &str, data: &[u8]) -> std::io::Result<()>
+where
+    W: AsyncWrite + Unpin + Send + Sync + 'static,
+{
+    let mut header = Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    builder.append_data(&mut header, path, data).await
+}
+
+/// Errors that may occur when exporting or importing the platform state.
+#[derive(Debug, thiserror::Error)]
+#[allow(missing_docs)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("I/O error occurred: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("JSON parsing error: {0}")]
+    ParseJson(#[from] serde_json::Error),
+    #[error("function error: {0}")]
+    Function(#[from] func::ManagerError),
+    #[error("user error: {0}")]
+    User(#[from] user::ManagerError),
+    #[error("unexpected entry in the archive: {0}")]
+    InvalidEntry(String),
+    #[error("missing metadata or configuration of function `{0}` in the archive")]
+    Incomplete(String),
+}
@@ -1,20 +1,32 @@
 //! Linux-specific implementation.
 
-#[cfg(feature = "seccomp")]
-use std::os::raw::c_int;
 use std::{
     borrow::Cow,
     ffi::{OsStr, OsString},
-    os::fd::OwnedFd,
+    io::Read as _,
+    os::{
+        fd::{AsFd as _, AsRawFd as _, BorrowedFd, OwnedFd},
+        raw::c_int,
+    },
     path::Path,
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    task::{Context, Poll},
 };
 
 use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt as _, ReadBuf};
 
-use crate::{NonExhaustiveMarker, dnem, sandbox::SandboxConfig};
+use crate::{
+    NonExhaustiveMarker, dnem,
+    sandbox::{self, LogSink, SandboxConfig},
+};
 
 /// Extended configuration of a sandbox.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SandboxConfigExt {
     /// Allowlist or denylist mode of syscall filtering.
     #[serde(default)]
@@ -23,6 +35,9 @@ pub struct SandboxConfigExt {
     ///
     /// _Make sure the given names are valid for current architecture._
     pub syscall_filter: Box<[String]>,
+    /// Action taken on denied syscalls.
+    #[serde(default)]
+    pub syscall_deny_action: SeccompDenyAction,
 
     /// Whether to provide procfs at `/proc`.
     pub mount_procfs: bool,
@@ -31,6 +46,18 @@ pub struct SandboxConfigExt {
     /// Whether to provide _a new_ tmpfs at `/tmp`.
     pub mount_tmpfs: bool,
 
+    /// Maximum memory in bytes the sandbox could use, beyond which it's reclaimed or killed by
+    /// the OOM killer.
+    ///
+    /// Limited through cgroup v2, and ignored with a warning if unavailable.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_limit_bytes: Option<u64>,
+    /// Maximum CPU time the sandbox could use, in count of cores.
+    ///
+    /// Limited through cgroup v2, and ignored with a warning if unavailable.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_quota: Option<f64>,
+
     #[doc(hidden)]
     #[serde(skip, default = "dnem")]
     pub __ne: NonExhaustiveMarker,
@@ -49,14 +76,50 @@ pub enum SyscallFilterMode {
     Deny,
 }
 
+/// Action taken on syscalls denied by syscall filtering.
+///
+/// The default action is [`SeccompDenyAction::Errno`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[allow(clippy::exhaustive_enums)]
+pub enum SeccompDenyAction {
+    /// Fails the syscall with `EPERM`.
+    #[default]
+    Errno,
+    /// Kills the calling thread.
+    Kill,
+    /// Kills the whole process.
+    KillProcess,
+    /// Allows the syscall after logging it to the kernel audit log.
+    Log,
+    /// Sends `SIGSYS` to the calling thread.
+    Trap,
+}
+
+#[cfg(feature = "seccomp")]
+impl SeccompDenyAction {
+    fn to_scmp_action(self) -> libseccomp::ScmpAction {
+        use libseccomp::ScmpAction;
+        match self {
+            Self::Errno => ScmpAction::Errno(libc::EPERM),
+            Self::Kill => ScmpAction::KillThread,
+            Self::KillProcess => ScmpAction::KillProcess,
+            Self::Log => ScmpAction::Log,
+            Self::Trap => ScmpAction::Trap,
+        }
+    }
+}
+
 impl Default for SandboxConfigExt {
     fn default() -> Self {
         Self {
             syscall_filter_mode: SyscallFilterMode::Deny,
             syscall_filter: Box::default(),
+            syscall_deny_action: SeccompDenyAction::Errno,
             mount_procfs: true,
             mount_devtmpfs: true,
             mount_tmpfs: false,
+            memory_limit_bytes: None,
+            cpu_quota: None,
             __ne: dnem(),
         }
     }
@@ -66,86 +129,505 @@ impl Default for SandboxConfigExt {
 #[derive(Debug, Clone, Copy, Default)]
 pub struct Bubblewrap;
 
+/// Time to wait for outputs of bubblewrap exited on failures of setting up the sandbox, as the
+/// pipe may be held open by descendants.
+const EARLY_EXIT_OUTPUT_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_millis(50);
+
 #[cfg(feature = "seccomp")]
 const BWRAP_SECCOMP_FD: c_int = 3;
 #[cfg(feature = "seccomp")]
 const BWRAP_SECCOMP_FD_STR: &str = "3";
 
-impl crate::sandbox::Sandbox for Bubblewrap {
+impl sandbox::Sandbox for Bubblewrap {
     type Handle = tokio::process::Child;
 
     async fn spawn(
         &self,
         config: &SandboxConfig,
         contents_path: &Path,
+        runtime_path: &Path,
     ) -> std::io::Result<Self::Handle> {
-        const COMMAND_BUBBLEWRAP: &str = "bwrap";
+        // whether outputs are inherited or discarded instead of being piped to the caller
+        let inherit = match config.log_sink {
+            Some(LogSink::Null) => Some(false),
+            Some(LogSink::Inherit) => Some(true),
+            None if config.inherit_stdout => Some(true),
+            _ => None,
+        };
 
-        let scp_fd: Option<OwnedFd>;
-        #[cfg(feature = "seccomp")]
+        // closed by bubblewrap once namespaces of the sandbox are created, or on exiting
+        let (mut info_r, info_w) = std::io::pipe()?;
+        let mut command = bwrap_command(
+            config,
+            &config.command,
+            &config.args,
+            contents_path,
+            runtime_path,
+            false,
+            Some(info_w.as_fd()),
+        )
+        .await?;
+        // stderr is always piped for recognizing failures of bubblewrap, and forwarded later
+        command
+            .stdout(match inherit {
+                Some(true) => std::process::Stdio::inherit(),
+                Some(false) => std::process::Stdio::null(),
+                None => std::process::Stdio::piped(),
+            })
+            .stderr(std::process::Stdio::piped());
+
+        let cgroup_procs = join_cgroup(&mut command, &config.platform_ext).await;
+        let spawned = command.spawn();
+        drop(cgroup_procs);
+        drop(info_w);
+        let mut child = spawned?;
+
+        let info = tokio::task::spawn_blocking(move || {
+            let mut info = Vec::new();
+            info_r.read_to_end(&mut info).map(|_| info)
+        })
+        .await
+        .map_err(std::io::Error::other)??;
+        let status = if info.is_empty() {
+            // exited before creating namespaces
+            Some(child.wait().await?)
+        } else {
+            child.try_wait()?
+        };
+        if let Some(status) = status
+            && !status.success()
         {
-            scp_fd = if config.platform_ext.syscall_filter_mode == SyscallFilterMode::Deny
-                && config.platform_ext.syscall_filter.is_empty()
-            {
-                None
-            } else {
-                || -> std::io::Result<OwnedFd> {
-                    use std::os::fd::{AsFd as _, OwnedFd};
-
-                    let (r, w) = std::io::pipe()?;
-                    compile_seccomp_filter(config, w.as_fd()).map_err(std::io::Error::other)?;
-                    tracing::debug!("os: compiled seccomp filter");
-                    Ok(OwnedFd::from(r))
-                }()
-                .inspect_err(|e| {
-                    tracing::error!("os: failed to create pipe and compile seccomp filter: {e}")
-                })
-                .ok()
-            };
+            return Err(early_exit_error(status, child.stderr.take()).await);
         }
-        #[cfg(not(all(feature = "seccomp", target_os = "linux")))]
-        let scp_fd = None;
 
-        let args = bwrap_args(config, contents_path, scp_fd.is_some());
-        let stdio = || {
-            if config.inherit_stdout {
-                std::process::Stdio::inherit()
-            } else {
-                std::process::Stdio::null()
-            }
-        };
+        if let Some(inherit) = inherit
+            && let Some(mut stderr) = child.stderr.take()
+        {
+            // ends once the pipe is closed by the exiting process
+            tokio::spawn(async move {
+                drop(if inherit {
+                    tokio::io::copy(&mut stderr, &mut tokio::io::stderr()).await
+                } else {
+                    tokio::io::copy(&mut stderr, &mut tokio::io::sink()).await
+                });
+            });
+        }
+        Ok(child)
+    }
 
-        let mut command = tokio::process::Command::new(COMMAND_BUBBLEWRAP);
+    async fn build<F>(
+        &self,
+        config: &SandboxConfig,
+        build: &sandbox::BuildCommand,
+        contents_path: &Path,
+        runtime_path: &Path,
+        output: F,
+    ) -> std::io::Result<std::process::ExitStatus>
+    where
+        F: FnOnce(sandbox::Output) + Send,
+    {
+        let mut command = bwrap_command(
+            config,
+            &build.command,
+            &build.args,
+            contents_path,
+            runtime_path,
+            true,
+            None,
+        )
+        .await?;
         command
-            .current_dir(contents_path)
-            .args(args.iter().map(|cow| &**cow))
-            .stdout(stdio())
-            .stderr(stdio());
-
-        #[cfg(feature = "seccomp")]
-        if let Some(parent_fd) = scp_fd {
-            use command_fds::{CommandFdExt as _, FdMapping};
-            let _ = command
-                .fd_mappings(vec![FdMapping {
-                    parent_fd,
-                    child_fd: BWRAP_SECCOMP_FD,
-                }])
-                .inspect_err(|err| tracing::error!("os: failed to set command fd: {err}"));
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            // in case of timeouts
+            .kill_on_drop(true);
+
+        // builds are limited the same as the function itself
+        let cgroup_procs = join_cgroup(&mut command, &config.platform_ext).await;
+        let mut child = command.spawn()?;
+        drop(cgroup_procs);
+
+        // bubblewrap reports failures of setting up the sandbox before anything of the command
+        let prefix = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        output(sandbox::Output {
+            stdout: child
+                .stdout
+                .take()
+                .map(|r| Box::pin(r) as sandbox::OutputReader),
+            stderr: child.stderr.take().map(|r| {
+                Box::pin(PrefixReader {
+                    inner: r,
+                    prefix: prefix.clone(),
+                }) as sandbox::OutputReader
+            }),
+        });
+        let status = child.wait().await?;
+        if !status.success()
+            && let Some(err) = privilege_denied(&String::from_utf8_lossy(&prefix.lock()))
+        {
+            return Err(err);
+        }
+        Ok(status)
+    }
+}
+
+/// Reader keeping a copy of the first bytes read through it, for recognizing failures of
+/// bubblewrap from outputs consumed elsewhere.
+struct PrefixReader<R> {
+    inner: R,
+    prefix: Arc<parking_lot::Mutex<Vec<u8>>>,
+}
+
+impl<R> PrefixReader<R> {
+    /// Maximum length of the kept prefix.
+    const MAX_LEN: usize = 4096;
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for PrefixReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let filled = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        let mut prefix = self.prefix.lock();
+        let remaining = Self::MAX_LEN.saturating_sub(prefix.len());
+        let read = &buf.filled()[filled..];
+        prefix.extend_from_slice(&read[..read.len().min(remaining)]);
+        result
+    }
+}
+
+/// Prepares the bubblewrap command running given program in a sandbox set up by the config.
+///
+/// Information about the sandbox is written into given file descriptor if any, which is closed
+/// by bubblewrap once namespaces of the sandbox are created.
+async fn bwrap_command(
+    config: &SandboxConfig,
+    program: &str,
+    program_args: &[String],
+    contents_path: &Path,
+    runtime_path: &Path,
+    writable_contents: bool,
+    info_fd: Option<BorrowedFd<'_>>,
+) -> std::io::Result<tokio::process::Command> {
+    const COMMAND_BUBBLEWRAP: &str = "bwrap";
+
+    let scp_fd: Option<OwnedFd>;
+    #[cfg(feature = "seccomp")]
+    {
+        scp_fd = if config.platform_ext.syscall_filter_mode == SyscallFilterMode::Deny
+            && config.platform_ext.syscall_filter.is_empty()
+        {
+            None
+        } else {
+            || -> std::io::Result<OwnedFd> {
+                use std::os::fd::{AsFd as _, OwnedFd};
+
+                let (r, w) = std::io::pipe()?;
+                compile_seccomp_filter(config, w.as_fd()).map_err(std::io::Error::other)?;
+                tracing::debug!("os: compiled seccomp filter");
+                Ok(OwnedFd::from(r))
+            }()
+            .inspect_err(|e| {
+                tracing::error!("os: failed to create pipe and compile seccomp filter: {e}")
+            })
+            .ok()
+        };
+    }
+    #[cfg(not(feature = "seccomp"))]
+    {
+        scp_fd = None;
+    }
+
+    let file_envs = sandbox::read_env_files(config, contents_path).await?;
+    let args = bwrap_args(
+        config,
+        BwrapProgram {
+            command: program,
+            args: program_args,
+            writable_contents,
+        },
+        runtime_path,
+        &file_envs,
+        scp_fd.is_some(),
+        info_fd.map(|fd| fd.as_raw_fd()),
+    );
+
+    let mut command = tokio::process::Command::new(COMMAND_BUBBLEWRAP);
+    command
+        .current_dir(contents_path)
+        .args(args.iter().map(|cow| &**cow));
+
+    #[cfg(feature = "seccomp")]
+    if let Some(parent_fd) = scp_fd {
+        use command_fds::{CommandFdExt as _, FdMapping};
+        let _ = command
+            .fd_mappings(vec![FdMapping {
+                parent_fd,
+                child_fd: BWRAP_SECCOMP_FD,
+            }])
+            .inspect_err(|err| tracing::error!("os: failed to set command fd: {err}"));
+    }
+
+    if let Some(fd) = info_fd {
+        let fd = fd.as_raw_fd();
+        // SAFETY: only the async-signal-safe `fcntl` is called after forking
+        unsafe {
+            // inherited by bubblewrap only, as it's still closed on executing in the parent
+            command.pre_exec(move || {
+                if libc::fcntl(fd, libc::F_SETFD, 0) < 0 {
+                    Err(std::io::Error::last_os_error())
+                } else {
+                    Ok(())
+                }
+            });
         }
+    }
 
-        tracing::info!(
+    if tracing::enabled!(tracing::Level::TRACE) {
+        tracing::trace!(
             "os: spawning bubblewrap with args: \n{:?}",
-            OsString::from_iter(
-                command
-                    .as_std()
-                    .get_args()
-                    .flat_map(|arg| [arg, " ".as_ref()])
-            )
+            redacted_args(command.as_std().get_args())
+        );
+    }
+    Ok(command)
+}
+
+/// Creates the error of a sandbox exited immediately, recognizing failures of creating namespaces
+/// from outputs of bubblewrap if stderr is piped.
+async fn early_exit_error(
+    status: std::process::ExitStatus,
+    stderr: Option<tokio::process::ChildStderr>,
+) -> std::io::Error {
+    let mut output = Vec::new();
+    if let Some(mut stderr) = stderr {
+        // the pipe may be held open by descendants
+        drop(
+            tokio::time::timeout(EARLY_EXIT_OUTPUT_TIMEOUT, stderr.read_to_end(&mut output)).await,
         );
-        command.spawn()
     }
+    let output = String::from_utf8_lossy(&output);
+
+    if let Some(err) = privilege_denied(&output) {
+        return err;
+    }
+    match output.lines().last() {
+        Some(line) => {
+            std::io::Error::other(format!("sandbox exited immediately with {status}: {line}"))
+        }
+        None => std::io::Error::other(format!("sandbox exited immediately with {status}")),
+    }
+}
+
+/// Root of the cgroup v2 hierarchy.
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+/// Cgroup under [`CGROUP_ROOT`] holding cgroups of sandboxes.
+const CGROUP_PARENT: &str = "yfass";
+/// Period of CPU bandwidth control in microseconds.
+const CGROUP_CPU_PERIOD: u64 = 100_000;
+/// Minimum CPU quota per period in microseconds accepted by the kernel.
+const CGROUP_CPU_MIN_QUOTA: u64 = 1000;
+
+/// Makes given command join a new cgroup limiting resources by given configuration before
+/// executing bubblewrap, so no process escapes the limits. Resources are left unlimited with a
+/// warning if the cgroup fails to be created.
+///
+/// The returned value should be held until the command is spawned, see [`create_cgroup`].
+async fn join_cgroup(
+    command: &mut tokio::process::Command,
+    ext: &SandboxConfigExt,
+) -> Option<(std::fs::File, tokio::sync::MutexGuard<'static, ()>)> {
+    let cgroup_procs = create_cgroup(ext)
+        .await
+        .inspect_err(|e| {
+            tracing::warn!("os: failed to limit resources of the sandbox through cgroup: {e}")
+        })
+        .ok()
+        .flatten();
+    if let Some((procs, _)) = &cgroup_procs {
+        use std::os::fd::AsRawFd as _;
+        let fd = procs.as_raw_fd();
+        // SAFETY: only the async-signal-safe `write` is called after forking
+        unsafe {
+            command.pre_exec(move || {
+                if libc::write(fd, b"0".as_ptr().cast(), 1) < 0 {
+                    Err(std::io::Error::last_os_error())
+                } else {
+                    Ok(())
+                }
+            });
+        }
+    }
+    cgroup_procs
+}
+
+/// Creates a cgroup limiting resources by given configuration, returning its opened
+/// `cgroup.procs` file for the sandbox to join, or `None` if there's no limit.
+///
+/// Empty cgroups left by exited sandboxes are removed meanwhile. The returned guard should be
+/// held until the sandbox joins the cgroup, so it's never removed as an empty one before that.
+async fn create_cgroup(
+    ext: &SandboxConfigExt,
+) -> std::io::Result<Option<(std::fs::File, tokio::sync::MutexGuard<'static, ()>)>> {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    static LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    if ext.memory_limit_bytes.is_none() && ext.cpu_quota.is_none() {
+        return Ok(None);
+    }
+    let cpu_max = ext
+        .cpu_quota
+        .map(|cores| {
+            if cores.is_finite() && cores > 0.0 {
+                let quota = (cores * CGROUP_CPU_PERIOD as f64) as u64;
+                Ok(format!(
+                    "{} {CGROUP_CPU_PERIOD}",
+                    quota.max(CGROUP_CPU_MIN_QUOTA)
+                ))
+            } else {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("invalid cpu quota {cores}"),
+                ))
+            }
+        })
+        .transpose()?;
+
+    let root = Path::new(CGROUP_ROOT);
+    if !tokio::fs::try_exists(root.join("cgroup.controllers")).await? {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "cgroup v2 is not mounted",
+        ));
+    }
+    let controllers = match (ext.memory_limit_bytes, &cpu_max) {
+        (Some(_), Some(_)) => "+memory +cpu",
+        (Some(_), None) => "+memory",
+        _ => "+cpu",
+    };
+    let guard = LOCK.lock().await;
+    let parent = root.join(CGROUP_PARENT);
+    tokio::fs::create_dir_all(&parent).await?;
+    tokio::fs::write(root.join("cgroup.subtree_control"), controllers).await?;
+    tokio::fs::write(parent.join("cgroup.subtree_control"), controllers).await?;
+    remove_empty_cgroups(&parent).await;
+
+    let dir = parent.join(format!(
+        "sandbox-{}-{}",
+        std::process::id(),
+        NEXT_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+    tokio::fs::create_dir(&dir).await?;
+    let result = async {
+        if let Some(memory_max) = ext.memory_limit_bytes {
+            tokio::fs::write(dir.join("memory.max"), memory_max.to_string()).await?;
+        }
+        if let Some(cpu_max) = cpu_max {
+            tokio::fs::write(dir.join("cpu.max"), cpu_max).await?;
+        }
+        let procs = tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(dir.join("cgroup.procs"))
+            .await?;
+        Ok(procs.into_std().await)
+    }
+    .await;
+    if result.is_err() {
+        drop(tokio::fs::remove_dir(&dir).await);
+    }
+    result.map(|procs| Some((procs, guard)))
+}
+
+/// Removes cgroups of exited sandboxes under given parent cgroup.
+///
+/// Cgroups with processes fail to be removed, so only empty ones are removed.
+async fn remove_empty_cgroups(parent: &Path) {
+    let Ok(mut entries) = tokio::fs::read_dir(parent).await else {
+        return;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if entry.file_type().await.is_ok_and(|ty| ty.is_dir()) {
+            drop(tokio::fs::remove_dir(entry.path()).await);
+        }
+    }
+}
+
+/// Recognizes failures of creating namespaces from outputs of bubblewrap.
+fn privilege_denied(output: &str) -> Option<std::io::Error> {
+    let line = output.lines().find(|line| {
+        line.strip_prefix("bwrap: ").is_some_and(|msg| {
+            msg.contains("namespace")
+                || msg.contains("uid map")
+                || msg.contains("Operation not permitted")
+        })
+    })?;
+    Some(std::io::Error::new(
+        std::io::ErrorKind::PermissionDenied,
+        sandbox::PrivilegeDenied(line.to_owned()),
+    ))
+}
+
+/// Sends given signal to processes in the sandbox of the bubblewrap process with given pid,
+/// returning whether any of them is signaled.
+///
+/// Bubblewrap never forwards signals, and processes in the sandbox are killed once bubblewrap
+/// exits, so they have to be signaled directly. The init process of the sandbox ignores signals
+/// without handlers, leaving the function itself to handle them.
+pub(crate) fn signal_sandboxed(pid: u32, signal: c_int) -> bool {
+    let mut signaled = false;
+    let mut parents = vec![pid];
+    while let Some(parent) = parents.pop() {
+        let Ok(tasks) = std::fs::read_dir(format!("/proc/{parent}/task")) else {
+            continue;
+        };
+        for task in tasks.flatten() {
+            let Ok(children) = std::fs::read_to_string(task.path().join("children")) else {
+                continue;
+            };
+            for child in children.split_whitespace().filter_map(|c| c.parse().ok()) {
+                // SAFETY: sending signals has no memory safety implications
+                if unsafe { libc::kill(child as libc::pid_t, signal) } == 0 {
+                    signaled = true;
+                }
+                parents.push(child);
+            }
+        }
+    }
+    signaled
+}
+
+/// Joins given arguments for logging, with values of environment variables masked.
+fn redacted_args<'a, I>(args: I) -> OsString
+where
+    I: IntoIterator<Item = &'a OsStr>,
+{
+    const MASK: &str = "***";
+
+    let mut redacted = OsString::new();
+    // remaining arguments until the value of `--setenv KEY VALUE`
+    let mut setenv_countdown = 0u8;
+    for arg in args {
+        if setenv_countdown == 1 {
+            redacted.push(MASK);
+        } else {
+            redacted.push(arg);
+        }
+        redacted.push(" ");
+
+        setenv_countdown = if arg == ARG_SET_ENV {
+            2
+        } else {
+            setenv_countdown.saturating_sub(1)
+        };
+    }
+    redacted
 }
 
+const ARG_SET_ENV: &str = "--setenv";
+
 #[cfg(feature = "seccomp")]
 fn compile_seccomp_filter(
     config: &SandboxConfig,
@@ -153,17 +635,17 @@ fn compile_seccomp_filter(
 ) -> Result<(), libseccomp::error::SeccompError> {
     use libseccomp::{ScmpAction, ScmpArch, ScmpFilterContext, ScmpSyscall};
 
-    const DENY_BEHAVIOR: ScmpAction = ScmpAction::Errno(libc::EPERM);
+    let deny_action = config.platform_ext.syscall_deny_action.to_scmp_action();
 
     let mut fcx = ScmpFilterContext::new(match config.platform_ext.syscall_filter_mode {
         // in reversed order to make difference between rules
         SyscallFilterMode::Deny => ScmpAction::Allow,
-        SyscallFilterMode::Allow => DENY_BEHAVIOR,
+        SyscallFilterMode::Allow => deny_action,
     })?;
 
     let action = match config.platform_ext.syscall_filter_mode {
         SyscallFilterMode::Allow => ScmpAction::Allow,
-        SyscallFilterMode::Deny => DENY_BEHAVIOR,
+        SyscallFilterMode::Deny => deny_action,
     };
 
     fcx.add_arch(ScmpArch::native())?;
@@ -174,20 +656,33 @@ fn compile_seccomp_filter(
     fcx.export_bpf(fd_w)
 }
 
+/// Program run in a sandbox.
+#[derive(Clone, Copy)]
+struct BwrapProgram<'a> {
+    command: &'a str,
+    args: &'a [String],
+    /// Whether the contents are bound writable, for building them.
+    writable_contents: bool,
+}
+
 fn bwrap_args<'a>(
     config: &'a SandboxConfig,
-    contents_path: &'a Path,
+    program: BwrapProgram<'a>,
+    runtime_path: &'a Path,
+    file_envs: &'a [(String, String)],
     seccomp: bool,
+    info_fd: Option<c_int>,
 ) -> Vec<Cow<'a, OsStr>> {
-    let _ = contents_path;
+    #[cfg(not(feature = "seccomp"))]
+    let _ = seccomp;
 
     // const ARG_CHDIR: &str = "--chdir";
     const ARG_UNSHARE_ALL: &str = "--unshare-all";
     const ARG_SHARE_NET: &str = "--share-net";
+    const ARG_BIND: &str = "--bind";
     const ARG_RO_BIND: &str = "--ro-bind";
     const ARG_RO_BIND_TRY: &str = "--ro-bind-try";
     const ARG_NEW_SESSION: &str = "--new-session";
-    const ARG_SET_ENV: &str = "--setenv";
     const ARG_UNSET_ENV: &str = "--unsetenv";
     const ARG_DIE_WITH_PARENT: &str = "--die-with-parent";
     const ARG_PROC: &str = "--proc";
@@ -208,11 +703,14 @@ fn bwrap_args<'a>(
 
         // restrict namespaces
         Cow::Borrowed(ARG_UNSHARE_ALL.as_ref()),
-        Cow::Borrowed(ARG_SHARE_NET.as_ref()),
         // create a new terminal session
         Cow::Borrowed(ARG_NEW_SESSION.as_ref()),
-        // bind contents path as read-only
-        Cow::Borrowed(ARG_RO_BIND.as_ref()), // this should not fail
+        // bind contents path as read-only, unless building
+        Cow::Borrowed(if program.writable_contents {
+            ARG_BIND.as_ref()
+        } else {
+            ARG_RO_BIND.as_ref()
+        }), // this should not fail
         Cow::Borrowed("./".as_ref()),
         Cow::Borrowed(MOUNT_POINT_CONTENTS.as_ref()),
         Cow::Borrowed(ARG_CHDIR.as_ref()),
@@ -221,6 +719,20 @@ fn bwrap_args<'a>(
         Cow::Borrowed(ARG_DIE_WITH_PARENT.as_ref()),
     ];
 
+    if config.network_isolation {
+        // reachable only through the unix socket in the private runtime directory
+        args.extend_from_slice(&[
+            Cow::Borrowed(ARG_BIND.as_ref()),
+            Cow::Borrowed(runtime_path.as_os_str()),
+            Cow::Borrowed(sandbox::SOCKET_DIR.as_ref()),
+            Cow::Borrowed(ARG_SET_ENV.as_ref()),
+            Cow::Borrowed(sandbox::ENV_SOCKET.as_ref()),
+            Cow::Borrowed(sandbox::SOCKET_PATH.as_ref()),
+        ]);
+    } else {
+        args.push(Cow::Borrowed(ARG_SHARE_NET.as_ref()));
+    }
+
     // mount in-memory or real time filesystems
     if config.platform_ext.mount_procfs {
         args.extend_from_slice(&[
@@ -252,7 +764,16 @@ fn bwrap_args<'a>(
         ]
     }));
 
-    // set environment variables
+    // set environment variables, with inline ones taking precedence over files
+    for (k, v) in file_envs {
+        if !config.envs.contains_key(k) {
+            args.extend_from_slice(&[
+                Cow::Borrowed(ARG_SET_ENV.as_ref()),
+                Cow::Borrowed(k.as_ref()),
+                Cow::Borrowed(v.as_ref()),
+            ]);
+        }
+    }
     for (k, v) in &config.envs {
         if let Some(v) = v {
             args.extend_from_slice(&[
@@ -278,14 +799,23 @@ fn bwrap_args<'a>(
         ]);
     }
 
+    // information about the sandbox once set up
+    if let Some(fd) = info_fd {
+        const ARG_INFO_FD: &str = "--info-fd";
+        args.extend_from_slice(&[
+            Cow::Borrowed(ARG_INFO_FD.as_ref()),
+            Cow::Owned(fd.to_string().into()),
+        ]);
+    }
+
     // the command to be executed
     args.extend_from_slice(&[
         Cow::Borrowed("--".as_ref()),
-        Cow::Borrowed(config.command.as_ref()),
+        Cow::Borrowed(program.command.as_ref()),
     ]);
 
     // CLI arguments
-    args.extend(config.args.iter().map(|arg| Cow::Borrowed(arg.as_ref())));
+    args.extend(program.args.iter().map(|arg| Cow::Borrowed(arg.as_ref())));
 
     args
 }
@@ -16,6 +16,7 @@ impl Sandbox for Unimplemented {
         &self,
         _: &sandbox::SandboxConfig,
         _: &std::path::Path,
+        _: &std::path::Path,
     ) -> std::io::Result<Self::Handle> {
         unsupported()
     }
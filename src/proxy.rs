@@ -1,13 +1,704 @@
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+    },
+    task::{Context, Poll},
+};
+
 use axum::{
-    body::{Body, Bytes},
+    body::{Body, Bytes, HttpBody},
     extract::{FromRequestParts as _, Request},
-    http::{self, Uri, uri::Scheme},
-    response::Response,
+    http::{self, Uri, uri::Authority, uri::PathAndQuery, uri::Scheme},
+    response::{IntoResponse, Response},
+};
+use futures_util::{
+    FutureExt as _, SinkExt as _, StreamExt as _, TryFutureExt as _, TryStreamExt as _,
+};
+use hyper_util::{client, rt::TokioIo};
+use parking_lot::Mutex;
+use rand::Rng;
+use time::UtcDateTime;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::oneshot,
+};
+use tokio_tungstenite::{WebSocketStream, tungstenite};
+use tokio_util::sync::CancellationToken;
+use yfass::{
+    func::{self, ErrorPage, FunctionSnapshot, HealthCheck, Readiness, UpstreamScheme},
+    lifecycle::{WsDirection, WsHook},
 };
-use futures_util::{SinkExt as _, StreamExt as _, TryFutureExt as _, TryStreamExt as _};
-use tokio_tungstenite::tungstenite;
 
-use crate::{Error, State};
+use crate::{Error, LocalCx, Routing, State};
+
+#[cfg(unix)]
+type UnixStream = tokio::net::UnixStream;
+// never connected on platforms without unix sockets
+#[cfg(not(unix))]
+type UnixStream = tokio::net::TcpStream;
+
+/// Upstream of a running function.
+#[derive(Debug, Clone)]
+pub struct Upstream {
+    authority: Authority,
+    /// Unix socket to connect through, for network-isolated functions.
+    socket: Option<Arc<Path>>,
+    max_ws_connections: Option<NonZeroUsize>,
+    ws_connections: Arc<AtomicUsize>,
+    /// Count of HTTP requests awaiting responses from the function.
+    in_flight: Arc<AtomicUsize>,
+    /// Unix timestamp in seconds of the last time a request or websocket connection started or
+    /// ended.
+    last_active: Arc<AtomicU64>,
+    no_keepalive: bool,
+    tls: bool,
+    /// Timeout of responses overriding the platform-wide one, with zero disabling it.
+    timeout: Option<tokio::time::Duration>,
+    error_pages: Arc<HashMap<u16, ErrorPage>>,
+    readiness: Option<Arc<Readiness>>,
+    health_check: Option<Arc<HealthCheck>>,
+    health: Arc<Health>,
+}
+
+/// Results of health checks of a running function, shared across clones of its [`Upstream`].
+#[derive(Debug, Default)]
+struct Health {
+    /// Count of consecutive failed checks.
+    failures: AtomicU32,
+    /// Whether a check is in progress.
+    checking: AtomicBool,
+    last_check: Mutex<Option<UtcDateTime>>,
+}
+
+impl Upstream {
+    pub fn new(authority: Authority, snapshot: &FunctionSnapshot, socket: Option<PathBuf>) -> Self {
+        Self {
+            authority,
+            socket: socket.map(Arc::from),
+            max_ws_connections: snapshot.max_ws_connections,
+            ws_connections: Arc::new(AtomicUsize::new(0)),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            last_active: Arc::new(AtomicU64::new(now_secs())),
+            no_keepalive: snapshot.upstream_no_keepalive,
+            tls: snapshot.upstream_scheme == UpstreamScheme::Https,
+            timeout: snapshot.proxy_timeout,
+            error_pages: Arc::new(snapshot.error_pages.clone()),
+            readiness: snapshot.readiness.clone().map(Arc::new),
+            health_check: snapshot.health_check.clone().map(Arc::new),
+            health: Arc::new(Health::default()),
+        }
+    }
+
+    /// Whether readiness of the function is required to be probed after spawned.
+    #[inline]
+    pub fn has_readiness_probe(&self) -> bool {
+        self.readiness.is_some()
+    }
+
+    /// Probes whether the function is ready for requests.
+    ///
+    /// Probes through `GET` requests to the readiness path if configured, or whether the
+    /// function accepts connections otherwise.
+    ///
+    /// Errors with description of the failed HTTP probe, including status and the beginning of
+    /// body of the response, or `None` if the function is not reachable.
+    pub async fn probe_ready(&self, cx: &LocalCx) -> Result<(), Option<String>> {
+        let Some(path) = self.readiness.as_ref().and_then(|r| r.http_path.as_deref()) else {
+            return self.is_reachable().await.then_some(()).ok_or(None);
+        };
+        self.probe_http(cx, path).await.map_err(Some)
+    }
+
+    /// Whether health of the function is required to be checked periodically, and the previous
+    /// check is at least an interval ago.
+    pub fn is_health_check_due(&self, now: UtcDateTime) -> bool {
+        self.health_check.as_ref().is_some_and(|check| {
+            self.health
+                .last_check
+                .lock()
+                .is_none_or(|last| last + check.interval() <= now)
+        })
+    }
+
+    /// Checks health of the function through a `GET` request to the health check path,
+    /// recording the result.
+    ///
+    /// Checks exceeding the interval fail. Does nothing if there's no health check configured or
+    /// a check is already in progress.
+    pub async fn check_health(&self, cx: &LocalCx) {
+        /// Minimum time a check may take, for functions checked in tiny intervals.
+        const MIN_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_secs(1);
+
+        let Some(check) = &self.health_check else {
+            return;
+        };
+        if self.health.checking.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let result = tokio::time::timeout(
+            check.interval().max(MIN_TIMEOUT),
+            self.probe_http(cx, &check.http_path),
+        )
+        .await
+        .unwrap_or_else(|_| Err("timed out".to_owned()));
+        *self.health.last_check.lock() = Some(UtcDateTime::now());
+
+        let threshold = check.unhealthy_threshold.get();
+        match result {
+            Ok(()) => {
+                if self.health.failures.swap(0, Ordering::AcqRel) >= threshold {
+                    tracing::info!("proxy: function at {} is healthy again", self.endpoint());
+                }
+            }
+            Err(err) => {
+                if self.health.failures.fetch_add(1, Ordering::AcqRel) + 1 == threshold {
+                    tracing::warn!(
+                        "proxy: function at {} is unhealthy after {threshold} failed checks, \
+                        last one {err}",
+                        self.endpoint()
+                    );
+                }
+            }
+        }
+        self.health.checking.store(false, Ordering::Release);
+    }
+
+    /// Whether the function passes its health checks, or `None` if there's no health check
+    /// configured.
+    ///
+    /// Functions are healthy until failing enough consecutive checks.
+    pub fn is_healthy(&self) -> Option<bool> {
+        self.health_check.as_ref().map(|check| {
+            self.health.failures.load(Ordering::Acquire) < check.unhealthy_threshold.get()
+        })
+    }
+
+    /// Returns the instant of the last completed health check.
+    pub fn last_health_check(&self) -> Option<UtcDateTime> {
+        *self.health.last_check.lock()
+    }
+
+    /// Sends a `GET` request to given path of the function, expecting a successful response.
+    ///
+    /// Errors with description of the failed probe, including status and the beginning of body
+    /// of the response.
+    async fn probe_http(&self, cx: &LocalCx, path: &str) -> Result<(), String> {
+        let request = Uri::builder()
+            .scheme(if self.tls {
+                Scheme::HTTPS
+            } else {
+                Scheme::HTTP
+            })
+            .authority(self.authority.clone())
+            .path_and_query(path)
+            .build()
+            .and_then(|uri| {
+                Request::get(uri)
+                    .header(http::header::HOST, self.authority.as_str())
+                    .body(Body::empty())
+            });
+        let request = match request {
+            Ok(request) => request,
+            Err(err) => {
+                tracing::warn!("proxy: invalid probe path {path}: {err}");
+                return Err(format!("failed with invalid path {path}: {err}"));
+            }
+        };
+
+        let response = if let Some(socket) = &self.socket {
+            send_unix(cx, socket, request).await
+        } else if self.tls {
+            #[cfg(feature = "upstream-tls")]
+            {
+                cx.client_tls.request(request).await.map_err(Error::from)
+            }
+            #[cfg(not(feature = "upstream-tls"))]
+            Err(Error::UpstreamTlsDisabled)
+        } else if self.no_keepalive {
+            cx.client_no_keepalive
+                .request(request)
+                .await
+                .map_err(Error::from)
+        } else {
+            cx.client.request(request).await.map_err(Error::from)
+        };
+        match response {
+            Ok(r) if r.status().is_success() => Ok(()),
+            Ok(r) => Err(describe_probe_response(r).await),
+            Err(err) => Err(format!("failed: {err}")),
+        }
+    }
+
+    /// Returns the private endpoint for accessing the function directly.
+    ///
+    /// This is either the authority, or the path to the Unix socket prefixed with `unix:`.
+    pub fn endpoint(&self) -> String {
+        match &self.socket {
+            Some(path) => format!("unix:{}", path.display()),
+            None => self.authority.to_string(),
+        }
+    }
+
+    /// Whether the function accepts connections.
+    async fn is_reachable(&self) -> bool {
+        match &self.socket {
+            Some(path) => connect_unix(path).await.is_ok(),
+            None => tokio::net::TcpStream::connect(self.authority.as_str())
+                .await
+                .is_ok(),
+        }
+    }
+
+    /// Returns the count of requests being handled by the function, which are HTTP requests
+    /// whose responses are not fully relayed yet and open websocket connections.
+    pub fn concurrency(&self) -> usize {
+        self.in_flight.load(Ordering::Acquire) + self.ws_connections.load(Ordering::Acquire)
+    }
+
+    /// Returns how long the function has stayed idle, which is zero if it's handling any request.
+    pub fn idle_duration(&self) -> tokio::time::Duration {
+        if self.concurrency() > 0 {
+            return tokio::time::Duration::ZERO;
+        }
+        tokio::time::Duration::from_secs(
+            now_secs().saturating_sub(self.last_active.load(Ordering::Acquire)),
+        )
+    }
+
+    /// Counts an HTTP request as in flight until the returned guard drops.
+    fn track_request(&self) -> InFlightGuard {
+        self.in_flight.fetch_add(1, Ordering::AcqRel);
+        self.last_active.store(now_secs(), Ordering::Release);
+        InFlightGuard {
+            in_flight: self.in_flight.clone(),
+            last_active: self.last_active.clone(),
+        }
+    }
+
+    /// Occupies a websocket connection slot, or returns `None` if the limit is reached.
+    fn try_acquire_ws(&self) -> Option<WsPermit> {
+        self.ws_connections
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                self.max_ws_connections
+                    .is_none_or(|max| count < max.get())
+                    .then_some(count + 1)
+            })
+            .ok()?;
+        self.last_active.store(now_secs(), Ordering::Release);
+        Some(WsPermit {
+            connections: self.ws_connections.clone(),
+            last_active: self.last_active.clone(),
+            released: AtomicBool::new(false),
+        })
+    }
+}
+
+/// Upstreams of replicas of a running function, which requests are distributed across in
+/// round-robin.
+#[derive(Debug, Clone)]
+pub struct Pool {
+    upstreams: Arc<[Upstream]>,
+    /// Index of the upstream the next request goes to, modulo count of upstreams.
+    next: Arc<AtomicUsize>,
+}
+
+impl Pool {
+    /// Creates a pool of given upstreams, which must not be empty.
+    pub fn new(upstreams: Vec<Upstream>) -> Self {
+        debug_assert!(!upstreams.is_empty(), "pool without upstreams");
+        Self {
+            upstreams: upstreams.into(),
+            next: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Picks the upstream for a request.
+    ///
+    /// Unhealthy upstreams are skipped unless all upstreams are unhealthy.
+    pub fn pick(&self) -> &Upstream {
+        if let [upstream] = &*self.upstreams {
+            return upstream;
+        }
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let len = self.upstreams.len();
+        (0..len)
+            .map(|i| &self.upstreams[start.wrapping_add(i) % len])
+            .find(|upstream| upstream.is_healthy() != Some(false))
+            .unwrap_or(&self.upstreams[start % len])
+    }
+
+    /// Returns the upstreams of this pool.
+    #[inline]
+    pub fn upstreams(&self) -> &[Upstream] {
+        &self.upstreams
+    }
+
+    /// Whether given pool is this one rather than a different pool of the same function.
+    #[inline]
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.upstreams, &other.upstreams)
+    }
+
+    /// Creates a new pool with given upstream of an added replica appended.
+    pub fn with_upstream(&self, upstream: Upstream) -> Self {
+        let mut upstreams = self.upstreams.to_vec();
+        upstreams.push(upstream);
+        Self::new(upstreams)
+    }
+
+    /// Creates a new pool without the upstream of the last replica, returning it along with the
+    /// removed upstream, or `None` if it's the only one.
+    pub fn without_last(&self) -> Option<(Self, Upstream)> {
+        let (last, rest) = self.upstreams.split_last()?;
+        (!rest.is_empty()).then(|| (Self::new(rest.to_vec()), last.clone()))
+    }
+
+    /// Whether readiness of the replicas is required to be probed after spawned.
+    #[inline]
+    pub fn has_readiness_probe(&self) -> bool {
+        self.upstreams.iter().any(Upstream::has_readiness_probe)
+    }
+
+    /// Returns the readiness probing shared by the replicas if configured.
+    #[inline]
+    pub fn readiness(&self) -> Option<&Readiness> {
+        self.upstreams.first()?.readiness.as_deref()
+    }
+
+    /// Returns the instant of the last completed health check of any upstream.
+    pub fn last_health_check(&self) -> Option<UtcDateTime> {
+        self.upstreams
+            .iter()
+            .filter_map(Upstream::last_health_check)
+            .max()
+    }
+
+    /// Whether any upstream passes its health checks, see [`Upstream::is_healthy`].
+    pub fn is_healthy(&self) -> Option<bool> {
+        self.upstreams
+            .iter()
+            .filter_map(Upstream::is_healthy)
+            .reduce(|a, b| a || b)
+    }
+
+    /// Returns the sum of [concurrency](Upstream::concurrency) of all upstreams.
+    pub fn concurrency(&self) -> usize {
+        self.upstreams.iter().map(Upstream::concurrency).sum()
+    }
+
+    /// Returns how long all upstreams have stayed idle, see [`Upstream::idle_duration`].
+    pub fn idle_duration(&self) -> tokio::time::Duration {
+        self.upstreams
+            .iter()
+            .map(Upstream::idle_duration)
+            .min()
+            .unwrap_or_default()
+    }
+
+    /// Returns the [endpoints](Upstream::endpoint) of all upstreams.
+    pub fn endpoints(&self) -> Vec<String> {
+        self.upstreams.iter().map(Upstream::endpoint).collect()
+    }
+}
+
+/// A running function public traffic of a [`Route`] is distributed to.
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub key: func::OwnedKey,
+    pub pool: Pool,
+    /// Relative weight of traffic routed to this target.
+    pub weight: u32,
+}
+
+/// Targets public traffic to a host prefix is distributed across, weighted-randomly.
+#[derive(Debug, Clone)]
+pub struct Route(Arc<[Target]>);
+
+impl Route {
+    /// Creates a route with given targets, which must not be empty.
+    pub fn new(targets: Vec<Target>) -> Self {
+        debug_assert!(!targets.is_empty(), "route without targets");
+        Self(targets.into())
+    }
+
+    /// Creates a route to a single function.
+    pub fn single(key: func::OwnedKey, pool: Pool) -> Self {
+        Self::new(vec![Target {
+            key,
+            pool,
+            weight: 1,
+        }])
+    }
+
+    /// Picks the target for a request.
+    ///
+    /// Unhealthy targets are skipped unless all targets are unhealthy. Targets are picked evenly
+    /// if all weights are zero.
+    pub fn pick<R: Rng>(&self, rng: &Mutex<R>) -> &Target {
+        if let [target] = &*self.0 {
+            return target;
+        }
+        let any_healthy = self.0.iter().any(|t| t.pool.is_healthy() != Some(false));
+        let candidates = || {
+            self.0
+                .iter()
+                .filter(move |t| !any_healthy || t.pool.is_healthy() != Some(false))
+        };
+        let total: u64 = candidates().map(|t| u64::from(t.weight)).sum();
+        if total == 0 {
+            let i = rng.lock().random_range(0..candidates().count());
+            return candidates().nth(i).expect("index out of candidates");
+        }
+        let mut point = rng.lock().random_range(0..total);
+        for target in candidates() {
+            match point.checked_sub(u64::from(target.weight)) {
+                Some(rest) => point = rest,
+                None => return target,
+            }
+        }
+        unreachable!("point out of total weight")
+    }
+
+    /// Returns the targets of this route.
+    #[inline]
+    pub fn targets(&self) -> &[Target] {
+        &self.0
+    }
+
+    /// Whether given route is this one rather than an equivalent route set separately.
+    #[inline]
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Whether given function is a target of this route.
+    pub fn contains(&self, key: func::Key<'_>) -> bool {
+        self.0.iter().any(|t| t.key.as_ref() == key)
+    }
+
+    /// Returns this route with the pool of given function replaced.
+    pub fn with_pool(&self, key: func::Key<'_>, pool: &Pool) -> Self {
+        Self::new(
+            self.0
+                .iter()
+                .map(|t| Target {
+                    pool: if t.key.as_ref() == key {
+                        pool.clone()
+                    } else {
+                        t.pool.clone()
+                    },
+                    ..t.clone()
+                })
+                .collect(),
+        )
+    }
+
+    /// Returns this route without given function, or `None` if no target is left.
+    pub fn without(&self, key: func::Key<'_>) -> Option<Self> {
+        let targets: Vec<_> = self
+            .0
+            .iter()
+            .filter(|t| t.key.as_ref() != key)
+            .cloned()
+            .collect();
+        (!targets.is_empty()).then(|| Self::new(targets))
+    }
+}
+
+/// Leading path segment of requests to functions in path-based routing, followed by names and
+/// versions of the functions.
+const PATH_PREFIX: &str = "/_fn/";
+
+/// Header telling functions the path prefix stripped in path-based routing.
+const HEADER_FORWARDED_PREFIX: &str = "x-forwarded-prefix";
+const HEADER_FORWARDED_PROTO: &str = "x-forwarded-proto";
+
+/// Splits the path of given URI in form of `/_fn/{name}/{version}/{rest}` into the host prefix of
+/// the addressed function, the stripped path prefix, and the rest of the path along with the
+/// query to be forwarded.
+fn split_path_prefix(uri: &Uri) -> Option<(String, http::HeaderValue, PathAndQuery)> {
+    let path = uri.path().strip_prefix(PATH_PREFIX)?;
+    let (name, path) = path.split_once('/')?;
+    let (version, rest) = path.split_once('/').unwrap_or((path, ""));
+    // labels never contain dots, which would be ambiguous in host prefixes
+    if [name, version]
+        .iter()
+        .any(|label| label.is_empty() || label.contains('.'))
+    {
+        return None;
+    }
+    let forwarded = match uri.query() {
+        Some(query) => format!("/{rest}?{query}"),
+        None => format!("/{rest}"),
+    };
+    Some((
+        format!("{version}.{name}"),
+        http::HeaderValue::from_str(&format!("{PATH_PREFIX}{name}/{version}")).ok()?,
+        forwarded.parse().ok()?,
+    ))
+}
+
+#[cfg(unix)]
+#[inline]
+async fn connect_unix(path: &Path) -> std::io::Result<UnixStream> {
+    UnixStream::connect(path).await
+}
+
+#[cfg(not(unix))]
+#[inline]
+async fn connect_unix(_: &Path) -> std::io::Result<UnixStream> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// An occupied websocket connection slot of an [`Upstream`].
+///
+/// The slot is freed on first call to [`WsPermit::release`] or on drop.
+#[derive(Debug)]
+struct WsPermit {
+    connections: Arc<AtomicUsize>,
+    last_active: Arc<AtomicU64>,
+    released: AtomicBool,
+}
+
+impl WsPermit {
+    fn release(&self) {
+        if !self.released.swap(true, Ordering::AcqRel) {
+            self.last_active.store(now_secs(), Ordering::Release);
+            self.connections.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+}
+
+impl Drop for WsPermit {
+    #[inline]
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+/// An HTTP request counted as in flight by an [`Upstream`].
+#[derive(Debug)]
+struct InFlightGuard {
+    in_flight: Arc<AtomicUsize>,
+    last_active: Arc<AtomicU64>,
+}
+
+impl Drop for InFlightGuard {
+    #[inline]
+    fn drop(&mut self) {
+        self.last_active.store(now_secs(), Ordering::Release);
+        self.in_flight.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Body of a proxied response keeping the request counted as in flight until the body ends, so
+/// functions streaming long responses are never considered idle or drained.
+#[derive(Debug)]
+struct TrackedBody {
+    inner: Body,
+    in_flight: Option<InFlightGuard>,
+}
+
+impl HttpBody for TrackedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<hyper::body::Frame<Bytes>, axum::Error>>> {
+        let frame = std::task::ready!(Pin::new(&mut self.inner).poll_frame(cx));
+        if !matches!(frame, Some(Ok(_))) {
+            // released on ending or failing, without waiting for the body to be dropped
+            self.in_flight = None;
+        }
+        Poll::Ready(frame)
+    }
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> hyper::body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Body of a proxied request, telling once it's sent entirely.
+struct SentBody {
+    inner: Body,
+    /// Dropped once the body ends or fails.
+    sent: Option<oneshot::Sender<()>>,
+}
+
+impl SentBody {
+    fn new(inner: Body, sent: oneshot::Sender<()>) -> Self {
+        // empty bodies may never be polled
+        let sent = (!inner.is_end_stream()).then_some(sent);
+        Self { inner, sent }
+    }
+}
+
+impl HttpBody for SentBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<hyper::body::Frame<Bytes>, axum::Error>>> {
+        let frame = std::task::ready!(Pin::new(&mut self.inner).poll_frame(cx));
+        if !matches!(frame, Some(Ok(_))) || self.inner.is_end_stream() {
+            self.sent = None;
+        }
+        Poll::Ready(frame)
+    }
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> hyper::body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[inline]
+fn now_secs() -> u64 {
+    UtcDateTime::now()
+        .unix_timestamp()
+        .try_into()
+        .unwrap_or_default()
+}
+
+/// Strips the platform host from given host, returning the host prefix of the addressed
+/// function if it's a subdomain of the platform.
+fn strip_host<'a>(cx: &LocalCx, host: &'a str) -> Option<&'a str> {
+    host.strip_suffix(&cx.host_with_dot_prefixed)
+        .or_else(|| host.strip_suffix(&cx.host_port_with_dot_prefixed))
+}
+
+/// Checks whether given request is addressed to a function rather than the platform itself,
+/// see [`forward_http_req`].
+pub fn is_func_request(cx: &LocalCx, request: &Request) -> bool {
+    match cx.routing {
+        Routing::Subdomain => request
+            .headers()
+            .get(http::header::HOST)
+            .and_then(|host| host.to_str().ok())
+            .is_some_and(|host| strip_host(cx, host).is_some()),
+        Routing::Path => split_path_prefix(request.uri()).is_some(),
+    }
+}
 
 /// Forwards HTTP requests to functions.
 pub async fn forward_http_req(
@@ -15,30 +706,96 @@ pub async fn forward_http_req(
     mut request: Request,
     next: axum::middleware::Next,
 ) -> Result<Response, Error> {
-    let Some(func_key) = request
-        .headers()
-        .get(http::header::HOST)
-        .ok_or(Error::MissingHost)?
-        .to_str()
-        .ok()
-        // .inspect(|host| tracing::debug!("proxy: received request to hostname {host}"))
-        .and_then(|s| {
-            s.strip_suffix(&cx.host_with_dot_prefixed)
-                .or_else(|| s.strip_suffix(&cx.host_port_with_dot_prefixed))
-        })
-    else {
-        // cant strip with dot prefixed host. not a subdomain tho
-        return Ok(next.run(request).await);
+    // path to be forwarded in place of the original one, for path-based routing
+    let mut stripped_path = None;
+    let func_key = match cx.routing {
+        Routing::Subdomain => {
+            let Some(func_key) = request
+                .headers()
+                .get(http::header::HOST)
+                .ok_or(Error::MissingHost)?
+                .to_str()
+                .ok()
+                // .inspect(|host| tracing::debug!("proxy: received request to hostname {host}"))
+                .and_then(|s| strip_host(&cx, s))
+            else {
+                // cant strip with dot prefixed host. not a subdomain tho
+                return Ok(next.run(request).await);
+            };
+            func_key.to_owned()
+        }
+        Routing::Path => {
+            let Some((func_key, prefix, path)) = split_path_prefix(request.uri()) else {
+                return Ok(next.run(request).await);
+            };
+            request.headers_mut().insert(
+                http::HeaderName::from_static(HEADER_FORWARDED_PREFIX),
+                prefix,
+            );
+            stripped_path = Some(path);
+            func_key
+        }
+    };
+    let func_key = func_key.as_str();
+
+    // keys are only required by websocket hooks
+    let with_key = cx.ws_hook.is_some() && maybe_ws_request(&request);
+    let pick = |_: &String, route: &Route| {
+        let target = route.pick(&cx.rng);
+        (
+            target.pool.pick().clone(),
+            with_key.then(|| target.key.clone()),
+        )
     };
+    let routed = match cx.proxies.peek_with(func_key, pick) {
+        Some(routed) => Some(routed),
+        None => match cx.lazy_deploy_fn(func_key).await {
+            Ok(()) => cx.proxies.peek_with(func_key, pick),
+            // only functions with lazy deployment enabled report failures
+            Err(Error::FunctionNotRunning) => None,
+            Err(err) => {
+                tracing::warn!("proxy: failed to lazily deploy function for {func_key}: {err}");
+                return Err(err);
+            }
+        },
+    };
+    let Some((upstream, key)) = routed else {
+        if cx.quiet_proxy_404 {
+            // likely scanning traffic, respond as cheap as possible
+            tracing::trace!("proxy: no function running for {func_key}");
+            return Ok(http::StatusCode::NOT_FOUND.into_response());
+        }
+        tracing::debug!("proxy: no function running for {func_key}");
+        return Err(Error::FunctionNotRunning);
+    };
+    // reported later if the function responds slowly
+    let slow_report = cx.slow_response_threshold.map(|threshold| {
+        (
+            threshold,
+            func_key.to_owned(),
+            request.uri().path().to_owned(),
+        )
+    });
 
-    let authority = cx
-        .proxies
-        .peek_with(func_key, |_, a| a.clone())
-        .ok_or(Error::FunctionNotRunning)?;
+    // scheme claimed by clients is replaced when the platform terminates TLS itself, and kept
+    // otherwise as the platform could be behind a reverse proxy terminating TLS
+    if cx.serve_tls {
+        request.headers_mut().insert(
+            http::HeaderName::from_static(HEADER_FORWARDED_PROTO),
+            http::HeaderValue::from_static("https"),
+        );
+    }
 
     let mut uri_parts = std::mem::take(request.uri_mut()).into_parts();
-    uri_parts.authority = Some(authority);
-    uri_parts.scheme = Some(Scheme::HTTP);
+    uri_parts.authority = Some(upstream.authority.clone());
+    if let Some(path) = stripped_path {
+        uri_parts.path_and_query = Some(path);
+    }
+    uri_parts.scheme = Some(if upstream.tls {
+        Scheme::HTTPS
+    } else {
+        Scheme::HTTP
+    });
     *request.uri_mut() = Uri::from_parts(uri_parts)?;
 
     // forward websocket requests
@@ -53,11 +810,42 @@ pub async fn forward_http_req(
             Request::from_parts(p, body)
         };
 
-        if let Ok(upgrade) =
-            axum::extract::ws::WebSocketUpgrade::from_request_parts(&mut parts, &()).await
+        // validated by axum while the connection is relayed through tungstenite on both sides
+        if axum::extract::ws::WebSocketUpgrade::from_request_parts(&mut parts, &())
+            .await
+            .is_ok()
         {
+            // messages beyond the limits are never buffered from either side
+            let config = tungstenite::protocol::WebSocketConfig::default()
+                .max_frame_size(Some(cx.max_ws_frame_bytes))
+                .max_message_size(Some(cx.max_ws_message_bytes));
+            let hook = cx.ws_hook.clone().zip(key);
+            // released when either side of the relay ends
+            let permit = Arc::new(
+                upstream
+                    .try_acquire_ws()
+                    .ok_or(Error::TooManyWsConnections)?,
+            );
+
+            // neither side of the relay is capable of per-message compression, so extensions
+            // like `permessage-deflate` are negotiated between the client and the function
+            // directly. messages are never compressed otherwise, while messages of connections
+            // with hooks are always inspected thus extensions are never offered to the function.
+            if cx.ws_hook.is_none()
+                && request
+                    .headers()
+                    .contains_key(http::header::SEC_WEBSOCKET_EXTENSIONS)
+            {
+                return relay_ws_negotiated(&cx, &upstream, request, permit, config).await;
+            }
+            request
+                .headers_mut()
+                .remove(http::header::SEC_WEBSOCKET_EXTENSIONS);
+            let accepted = ws_accepted_response(&request);
+            let client = hyper::upgrade::on(&mut request);
+
             let mut uri_parts = std::mem::take(request.uri_mut()).into_parts();
-            uri_parts.scheme = Some("ws".try_into().unwrap());
+            uri_parts.scheme = Some(if upstream.tls { "wss" } else { "ws" }.try_into().unwrap());
             *request.uri_mut() = Uri::from_parts(uri_parts)?;
 
             // elide the request body as it should be empty
@@ -66,30 +854,41 @@ pub async fn forward_http_req(
                 "proxy: forwarding websocket upgrade request with uri {}",
                 request.uri()
             );
-            let (stream, _resp) = tokio_tungstenite::connect_async(request).await?;
-            let resp = upgrade.on_upgrade(|ws| async {
-                let (s2c_sink, c2s_stream) = ws.split();
-                let (s2f_sink, f2s_stream) = stream.split();
-
-                // client -> server -> function
-                tokio::spawn(
-                    c2s_stream
-                        .map_ok(msg_ts_from_axum)
-                        .forward(s2f_sink.sink_map_err(axum::Error::new))
-                        .inspect_err(|err| tracing::warn!("websocket error from connection chain client -> server -> function: {err}")),
-                );
-
-                // function -> server -> client
-                tokio::spawn(
-                    f2s_stream
-                        .try_filter_map(|o| std::future::ready(Ok(msg_axum_from_ts(o))))
-                        .map_err(axum::Error::new)
-                        .forward(s2c_sink)
-                        .inspect_err(|err| tracing::warn!("websocket error from connection chain function -> server -> client: {err}"))
-                );
-            });
-
-            return Ok(resp);
+            if let Some(path) = &upstream.socket {
+                let stream = tokio::time::timeout(cx.upstream_connect_timeout, connect_unix(path))
+                    .await
+                    .map_err(|_| Error::UpstreamConnectTimeout)??;
+                // the handshake is bounded the same as connecting through TCP below
+                let (stream, _resp) = tokio::time::timeout(
+                    cx.upstream_connect_timeout,
+                    tokio_tungstenite::client_async_with_config(request, stream, Some(config)),
+                )
+                .await
+                .map_err(|_| Error::UpstreamConnectTimeout)??;
+                let relay = WsRelay::new(&cx);
+                relay_ws(client, config, stream, permit, hook, relay);
+                return Ok(accepted);
+            }
+            #[cfg(feature = "upstream-tls")]
+            let connect = tokio_tungstenite::connect_async_tls_with_config(
+                request,
+                Some(config),
+                false,
+                upstream
+                    .tls
+                    .then(|| tokio_tungstenite::Connector::Rustls(cx.upstream_tls.clone())),
+            );
+            #[cfg(not(feature = "upstream-tls"))]
+            let connect = if upstream.tls {
+                return Err(Error::UpstreamTlsDisabled);
+            } else {
+                tokio_tungstenite::connect_async_with_config(request, Some(config), false)
+            };
+            let (stream, _resp) = tokio::time::timeout(cx.upstream_connect_timeout, connect)
+                .await
+                .map_err(|_| Error::UpstreamConnectTimeout)??;
+            relay_ws(client, config, stream, permit, hook, WsRelay::new(&cx));
+            return Ok(accepted);
         }
         // else: this is not a websocket request
     }
@@ -99,11 +898,720 @@ pub async fn forward_http_req(
         request.uri()
     );
 
-    cx.client
-        .request(request)
+    // set once a streamed request body exceeds the limit
+    let body_exceeded = Arc::new(AtomicBool::new(false));
+    if let Some(limit) = cx.max_proxy_body_bytes {
+        request = limit_request_body(request, limit, &body_exceeded)?;
+    }
+
+    // only response headers are awaited after the request body is sent, so that neither slow
+    // uploads nor streamed bodies like event streams are cut off by the timeout
+    let timeout = upstream.timeout.map_or(cx.proxy_timeout, |timeout| {
+        (!timeout.is_zero()).then_some(timeout)
+    });
+    let mut sent = None;
+    if timeout.is_some() {
+        let (tx, rx) = oneshot::channel();
+        request = request.map(|body| Body::new(SentBody::new(body, tx)));
+        sent = Some(rx);
+    }
+
+    let is_head = request.method() == http::Method::HEAD;
+    let in_flight = upstream.track_request();
+    let started = tokio::time::Instant::now();
+    let send = send_upstream(&cx, &upstream, request);
+    let response = match timeout.zip(sent) {
+        Some((timeout, sent)) => {
+            tokio::pin!(send);
+            tokio::select! {
+                response = &mut send => response,
+                // either sent entirely or dropped
+                _ = sent => tokio::time::timeout(timeout, send)
+                    .await
+                    .unwrap_or(Err(Error::UpstreamTimeout)),
+            }
+        }
+        None => send.await,
+    };
+    let response = response.map_err(|err| match cx.max_proxy_body_bytes {
+        Some(limit) if body_exceeded.load(Ordering::Relaxed) => Error::ProxyBodyTooLarge(limit),
+        _ => err,
+    });
+    if let Some((threshold, key, path)) = slow_report {
+        let elapsed = started.elapsed();
+        if elapsed > threshold {
+            tracing::warn!(
+                %key,
+                %path,
+                elapsed_ms = elapsed.as_millis() as u64,
+                "proxy: function responded slower than threshold"
+            );
+        }
+    }
+    // proxy-generated errors are subject to error pages as well
+    let mut response = response.map_or_else(IntoResponse::into_response, |r| r.map(Body::new));
+    if let Some(page) = upstream.error_pages.get(&response.status().as_u16()) {
+        response = error_page_response(response, page);
+    }
+    // responses to `HEAD` never carry bodies even if the function sends one, while headers like
+    // `Content-Length` are kept describing the would-be body
+    if is_head {
+        response = response.map(|_| Body::empty());
+    } else if is_event_stream(response.headers()) {
+        // events are relayed frame by frame as they arrive, and long-lived streams are only cut
+        // off after staying idle. reverse proxies in front of the platform are asked not to
+        // buffer them either
+        response.headers_mut().insert(
+            http::HeaderName::from_static("x-accel-buffering"),
+            http::HeaderValue::from_static("no"),
+        );
+        if let Some(idle) = cx.sse_idle_timeout {
+            response = response.map(|body| idle_timeout_body(body, idle));
+        }
+    }
+
+    // bodies are relayed as is along with `Content-Encoding`, never decoded or encoded again by
+    // the proxy, so encodings negotiated between the client and the function are kept intact
+    let headers = response.headers_mut();
+    for name in &cx.strip_response_headers {
+        headers.remove(name);
+    }
+    for header in &cx.add_response_headers {
+        headers.insert(header.name.clone(), header.value.clone());
+    }
+
+    if upstream.no_keepalive {
+        response.headers_mut().insert(
+            http::header::CONNECTION,
+            http::HeaderValue::from_static("close"),
+        );
+    }
+    // counted as in flight until the body is relayed entirely
+    Ok(response.map(|inner| {
+        Body::new(TrackedBody {
+            inner,
+            in_flight: Some(in_flight),
+        })
+    }))
+}
+
+/// Checks whether a response is a stream of Server-Sent Events.
+fn is_event_stream(headers: &http::HeaderMap) -> bool {
+    headers
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("text/event-stream"))
+}
+
+/// Wraps a body to fail once no frame is received within given duration.
+fn idle_timeout_body(body: Body, idle: tokio::time::Duration) -> Body {
+    Body::from_stream(futures_util::stream::unfold(
+        Some(body.into_data_stream()),
+        move |stream| async move {
+            let mut stream = stream?;
+            match tokio::time::timeout(idle, stream.next()).await {
+                Ok(Some(chunk)) => Some((chunk, Some(stream))),
+                Ok(None) => None,
+                // ends the stream after reporting the timeout
+                Err(_) => Some((
+                    Err(axum::Error::new(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "event stream stayed idle for too long",
+                    ))),
+                    None,
+                )),
+            }
+        },
+    ))
+}
+
+/// Bounds the body of given request to `limit` bytes.
+///
+/// Requests declaring larger lengths are rejected right away, while bodies streamed without a
+/// declared length fail once exceeding the limit, with `exceeded` set.
+fn limit_request_body(
+    request: Request,
+    limit: u64,
+    exceeded: &Arc<AtomicBool>,
+) -> Result<Request, Error> {
+    let declared = request
+        .headers()
+        .get(http::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    match declared {
+        Some(len) if len > limit => Err(Error::ProxyBodyTooLarge(limit)),
+        // declared lengths are enforced while receiving the request already
+        Some(_) => Ok(request),
+        // keep empty bodies as is so they stay replayable
+        None if HttpBody::size_hint(request.body()).exact() == Some(0) => Ok(request),
+        None => {
+            let exceeded = exceeded.clone();
+            let mut remaining = limit;
+            Ok(request.map(|body| {
+                Body::from_stream(body.into_data_stream().map(move |chunk| {
+                    let chunk = chunk?;
+                    remaining = remaining.checked_sub(chunk.len() as u64).ok_or_else(|| {
+                        exceeded.store(true, Ordering::Relaxed);
+                        axum::Error::new(std::io::Error::other("request body size limit exceeded"))
+                    })?;
+                    Ok::<_, axum::Error>(chunk)
+                }))
+            }))
+        }
+    }
+}
+
+/// Describes a failed response of probes with its status and the beginning of its body.
+async fn describe_probe_response(response: http::Response<hyper::body::Incoming>) -> String {
+    const SNIPPET_LIMIT: usize = 256;
+
+    let status = response.status();
+    let mut body = Body::new(response.into_body()).into_data_stream();
+    let mut snippet = Vec::new();
+    while snippet.len() < SNIPPET_LIMIT
+        && let Some(Ok(chunk)) = body.next().await
+    {
+        snippet.extend_from_slice(&chunk);
+    }
+    snippet.truncate(SNIPPET_LIMIT);
+    let snippet = String::from_utf8_lossy(&snippet);
+    let snippet = snippet.trim();
+    if snippet.is_empty() {
+        format!("responded with {status}")
+    } else {
+        format!("responded with {status}: {snippet}")
+    }
+}
+
+/// Replaces body of given response with the error page, preserving its status.
+fn error_page_response(response: Response, page: &ErrorPage) -> Response {
+    let (mut parts, _) = response.into_parts();
+    for name in [
+        http::header::CONTENT_LENGTH,
+        http::header::CONTENT_ENCODING,
+        http::header::TRANSFER_ENCODING,
+        http::header::CONTENT_TYPE,
+    ] {
+        parts.headers.remove(name);
+    }
+    match http::HeaderValue::try_from(&page.content_type) {
+        Ok(ty) => drop(parts.headers.insert(http::header::CONTENT_TYPE, ty)),
+        Err(err) => tracing::warn!("proxy: invalid content type of error page: {err}"),
+    }
+    Response::from_parts(parts, Body::from(page.body.clone()))
+}
+
+/// Sends a request through given client, retrying on connection failures if the request could be
+/// replayed.
+///
+/// Requests are replayable if they are idempotent, and their bodies are either empty or small
+/// enough to be buffered.
+async fn send_with_retries<C>(
+    cx: &State,
+    client: &client::legacy::Client<C, Body>,
+    request: Request,
+) -> Result<http::Response<hyper::body::Incoming>, Error>
+where
+    C: client::legacy::connect::Connect + Clone + Send + Sync + 'static,
+{
+    const BACKOFF_BASE: tokio::time::Duration = tokio::time::Duration::from_millis(100);
+
+    let (parts, body) = request.into_parts();
+    // length of the body to be buffered for replaying, or `None` if not replayable
+    let replay_len = if cx.upstream_retries == 0 || !parts.method.is_idempotent() {
+        None
+    } else if HttpBody::size_hint(&body).exact() == Some(0) {
+        Some(0)
+    } else {
+        // bodies without known length are never buffered
+        parts
+            .headers
+            .get(http::header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|len| *len <= cx.buffer_retryable_bodies)
+    };
+    let body = match replay_len {
+        Some(0) => Bytes::new(),
+        Some(len) => axum::body::to_bytes(body, len).await?,
+        None => {
+            return client
+                .request(Request::from_parts(parts, body))
+                .await
+                .map_err(map_client_err);
+        }
+    };
+
+    let mut attempt = 0;
+    loop {
+        let request = Request::from_parts(parts.clone(), Body::from(body.clone()));
+        match client.request(request).await {
+            Err(err) if err.is_connect() && attempt < cx.upstream_retries => {
+                tracing::debug!("proxy: retrying request after connection failure: {err}");
+                tokio::time::sleep(BACKOFF_BASE * 2u32.pow(attempt.min(6))).await;
+                attempt += 1;
+            }
+            result => return result.map_err(map_client_err),
+        }
+    }
+}
+
+/// Sends a request to given upstream through the client fitting it.
+async fn send_upstream(
+    cx: &State,
+    upstream: &Upstream,
+    request: Request,
+) -> Result<http::Response<hyper::body::Incoming>, Error> {
+    if let Some(path) = &upstream.socket {
+        send_unix(cx, path, request).await
+    } else if upstream.tls {
+        #[cfg(feature = "upstream-tls")]
+        {
+            send_with_retries(cx, &cx.client_tls, request).await
+        }
+        #[cfg(not(feature = "upstream-tls"))]
+        Err(Error::UpstreamTlsDisabled)
+    } else if upstream.no_keepalive {
+        send_with_retries(cx, &cx.client_no_keepalive, request).await
+    } else {
+        send_with_retries(cx, &cx.client, request).await
+    }
+}
+
+/// Sends a request through a fresh connection to given Unix socket.
+async fn send_unix(
+    cx: &LocalCx,
+    path: &Path,
+    mut request: Request,
+) -> Result<http::Response<hyper::body::Incoming>, Error> {
+    let stream = tokio::time::timeout(cx.upstream_connect_timeout, connect_unix(path))
         .await
-        .map(|r| r.map(Body::new))
-        .map_err(Into::into)
+        .map_err(|_| Error::UpstreamConnectTimeout)??;
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+    tokio::spawn(
+        conn.with_upgrades()
+            .inspect_err(|err| tracing::warn!("proxy: unix socket connection error: {err}")),
+    );
+
+    // there is no authority to speak of for unix sockets
+    let uri_parts = std::mem::take(request.uri_mut()).into_parts();
+    let mut origin_form = http::uri::Parts::default();
+    origin_form.path_and_query = uri_parts.path_and_query;
+    *request.uri_mut() = Uri::from_parts(origin_form)?;
+
+    Ok(sender.send_request(request).await?)
+}
+
+/// Relays a websocket connection offering extensions to the function, which answers the offer
+/// itself.
+///
+/// Connections the function accepts extensions of are relayed as raw bytes along with the
+/// handshake response of the function, as frames of extensions like `permessage-deflate` can't
+/// be relayed as messages. Others are relayed as messages the same as connections offering no
+/// extensions.
+async fn relay_ws_negotiated(
+    cx: &State,
+    upstream: &Upstream,
+    mut request: Request,
+    permit: Arc<WsPermit>,
+    config: tungstenite::protocol::WebSocketConfig,
+) -> Result<Response, Error> {
+    let accepted = ws_accepted_response(&request);
+    let client_upgrade = hyper::upgrade::on(&mut request);
+    // elide the request body as it should be empty
+    let request = Request::from_parts(request.into_parts().0, Body::empty());
+    tracing::debug!(
+        "proxy: forwarding websocket upgrade request offering extensions with uri {}",
+        request.uri()
+    );
+    let mut response = tokio::time::timeout(
+        cx.upstream_connect_timeout,
+        send_upstream(cx, upstream, request),
+    )
+    .await
+    .map_err(|_| Error::UpstreamConnectTimeout)??;
+    if response.status() != http::StatusCode::SWITCHING_PROTOCOLS {
+        // rejected by the function
+        return Ok(response.map(Body::new));
+    }
+
+    let function_upgrade = hyper::upgrade::on(&mut response);
+    if !response
+        .headers()
+        .contains_key(http::header::SEC_WEBSOCKET_EXTENSIONS)
+    {
+        let stream = WebSocketStream::from_raw_socket(
+            TokioIo::new(function_upgrade.await?),
+            tungstenite::protocol::Role::Client,
+            Some(config),
+        )
+        .await;
+        relay_ws(
+            client_upgrade,
+            config,
+            stream,
+            permit,
+            None,
+            WsRelay::new(cx),
+        );
+        return Ok(accepted);
+    }
+
+    // frames are neither bounded, pinged nor inspected, as they can't be decoded
+    tokio::spawn(async move {
+        match tokio::try_join!(client_upgrade, function_upgrade) {
+            Ok((client, function)) => {
+                if let Err(err) = tokio::io::copy_bidirectional(
+                    &mut TokioIo::new(client),
+                    &mut TokioIo::new(function),
+                )
+                .await
+                {
+                    tracing::warn!(
+                        "websocket error from raw relay between client and function: {err}"
+                    );
+                }
+            }
+            Err(err) => tracing::warn!("proxy: failed to upgrade websocket connection: {err}"),
+        }
+        drop(permit);
+    });
+    // the handshake response of the function is relayed as is, including accepted extensions
+    Ok(response.map(|_| Body::empty()))
+}
+
+/// Relays messages between the client and the function until either side ends, once the client
+/// connection is upgraded by responding with [`ws_accepted_response`].
+///
+/// Both sides are driven by tungstenite with given configuration, so errors of either side are
+/// told apart the same way.
+fn relay_ws<S>(
+    client: hyper::upgrade::OnUpgrade,
+    config: tungstenite::protocol::WebSocketConfig,
+    stream: WebSocketStream<S>,
+    permit: Arc<WsPermit>,
+    hook: Option<(Arc<dyn WsHook>, func::OwnedKey)>,
+    relay: WsRelay,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let ws = match client.await {
+            Ok(upgraded) => {
+                WebSocketStream::from_raw_socket(
+                    TokioIo::new(upgraded),
+                    tungstenite::protocol::Role::Server,
+                    Some(config),
+                )
+                .await
+            }
+            Err(err) => {
+                tracing::warn!("proxy: failed to upgrade websocket connection: {err}");
+                return;
+            }
+        };
+        let (s2c_sink, c2s_stream) = ws.split();
+        let (s2f_sink, f2s_stream) = stream.split();
+        let permit_c2s = permit.clone();
+        let relay = Arc::new(relay);
+        let relay_c2s = relay.clone();
+        // fired by either relay once its side sent a message too big, for closing the other side
+        let (c2s_too_big, f2s_too_big_rx) = oneshot::channel();
+        let (f2s_too_big, c2s_too_big_rx) = oneshot::channel();
+        // milliseconds since the relay started when messages were last received from either side
+        let client_seen = Arc::new(AtomicU64::new(0));
+        let function_seen = Arc::new(AtomicU64::new(0));
+
+        // responses to keepalive pings of the relay are never relayed to the other side
+        let c2s_stream = c2s_stream.try_filter({
+            let relay = relay.clone();
+            let seen = client_seen.clone();
+            move |msg| std::future::ready(relay.receive(&seen, msg))
+        });
+        let f2s_stream = f2s_stream.try_filter({
+            let relay = relay.clone();
+            let seen = function_seen.clone();
+            move |msg| std::future::ready(relay.receive(&seen, msg))
+        });
+        // checked once per connection, leaving the relay untouched without hooks
+        let Some((hook, key)) = hook else {
+            // client -> server -> function
+            tokio::spawn(
+                relay_c2s
+                    .run(
+                        client_seen,
+                        c2s_stream.try_filter_map(|o| std::future::ready(Ok(relay_msg(o)))),
+                        s2f_sink,
+                        (c2s_too_big, c2s_too_big_rx),
+                    )
+                    .inspect_err(|err| {
+                        tracing::warn!(
+                            "websocket error from connection chain client -> server -> function: {err}"
+                        )
+                    })
+                    .inspect(move |_| permit_c2s.release()),
+            );
+
+            // function -> server -> client
+            tokio::spawn(
+                relay
+                    .run(
+                        function_seen,
+                        f2s_stream.try_filter_map(|o| std::future::ready(Ok(relay_msg(o)))),
+                        s2c_sink,
+                        (f2s_too_big, f2s_too_big_rx),
+                    )
+                    .inspect_err(|err| {
+                        tracing::warn!(
+                            "websocket error from connection chain function -> server -> client: {err}"
+                        )
+                    })
+                    .inspect(move |_| permit.release()),
+            );
+            return;
+        };
+
+        let hook_c2s = hook.clone();
+        let key_c2s = key.clone();
+        // client -> server -> function
+        tokio::spawn(
+            relay_c2s
+                .run(
+                    client_seen,
+                    c2s_stream.try_filter_map(move |o| {
+                        std::future::ready(Ok(hook_c2s
+                            .on_message(key_c2s.as_ref(), WsDirection::ClientToFunction, o)
+                            .and_then(relay_msg)))
+                    }),
+                    s2f_sink,
+                    (c2s_too_big, c2s_too_big_rx),
+                )
+                .inspect_err(|err| {
+                    tracing::warn!(
+                        "websocket error from connection chain client -> server -> function: {err}"
+                    )
+                })
+                .inspect(move |_| permit_c2s.release()),
+        );
+
+        // function -> server -> client
+        tokio::spawn(
+            relay
+                .run(
+                    function_seen,
+                    f2s_stream.try_filter_map(move |o| {
+                        std::future::ready(Ok(hook
+                            .on_message(key.as_ref(), WsDirection::FunctionToClient, o)
+                            .and_then(relay_msg)))
+                    }),
+                    s2c_sink,
+                    (f2s_too_big, f2s_too_big_rx),
+                )
+                .inspect_err(|err| {
+                    tracing::warn!(
+                        "websocket error from connection chain function -> server -> client: {err}"
+                    )
+                })
+                .inspect(move |_| permit.release()),
+        );
+    });
+}
+
+/// State shared by both relays of a websocket connection.
+struct WsRelay {
+    /// Cancelled once either side dies, dropping both relays.
+    cancel: CancellationToken,
+    /// Whether either relay has ended normally.
+    ended: AtomicBool,
+    started: tokio::time::Instant,
+    /// Interval of pinging both sides, or `None` if keepalive is disabled.
+    ping_interval: Option<tokio::time::Duration>,
+    /// Timeout of a side responding to pings, or finishing the closing handshake after the other
+    /// side closed.
+    pong_timeout: tokio::time::Duration,
+}
+
+impl WsRelay {
+    const PING_PAYLOAD: &[u8] = b"yfass-keepalive";
+
+    fn new(cx: &LocalCx) -> Self {
+        Self {
+            cancel: CancellationToken::new(),
+            ended: AtomicBool::new(false),
+            started: tokio::time::Instant::now(),
+            ping_interval: cx.ws_ping_interval,
+            pong_timeout: cx.ws_pong_timeout,
+        }
+    }
+
+    /// Records a message received from one side, returning whether it should be relayed.
+    fn receive(&self, seen: &AtomicU64, msg: &tungstenite::Message) -> bool {
+        seen.store(self.started.elapsed().as_millis() as u64, Ordering::Relaxed);
+        !matches!(msg, tungstenite::Message::Pong(data) if data.as_ref() == Self::PING_PAYLOAD)
+    }
+
+    /// Relays messages received from one side to the other until either side ends, pinging the
+    /// receiving side meanwhile.
+    ///
+    /// The receiving side is considered dead once nothing arrives from the sending side, whose
+    /// receipts are recorded in `seen`, within the pong timeout after the ping interval, which
+    /// tears down both relays.
+    ///
+    /// Once a message exceeding the size limits is received, the opposite relay is notified through
+    /// given channel, and both sides are closed with `1009 Message Too Big`. Backpressure is
+    /// applied by the sink, so that no more messages are received before the previous one is
+    /// written out.
+    async fn run<St, Si>(
+        self: Arc<Self>,
+        seen: Arc<AtomicU64>,
+        stream: St,
+        mut sink: Si,
+        (too_big, mut too_big_rx): (oneshot::Sender<()>, oneshot::Receiver<()>),
+    ) -> Result<(), tungstenite::Error>
+    where
+        St: futures_util::Stream<Item = Result<tungstenite::Message, tungstenite::Error>>,
+        Si: futures_util::Sink<tungstenite::Message, Error = tungstenite::Error> + Unpin,
+    {
+        let relay = async {
+            let mut stream = std::pin::pin!(stream);
+            let mut pings = self.ping_interval.map(|interval| {
+                tokio::time::interval_at(tokio::time::Instant::now() + interval, interval)
+            });
+            loop {
+                let ping = async {
+                    match &mut pings {
+                        Some(pings) => drop(pings.tick().await),
+                        None => std::future::pending().await,
+                    }
+                };
+                let silent = async {
+                    match self.ping_interval {
+                        Some(interval) => {
+                            let seen = seen.load(Ordering::Relaxed);
+                            tokio::time::sleep_until(
+                                self.started
+                                    + tokio::time::Duration::from_millis(seen)
+                                    + interval
+                                    + self.pong_timeout,
+                            )
+                            .await;
+                        }
+                        None => std::future::pending().await,
+                    }
+                };
+                tokio::select! {
+                    msg = stream.next() => match msg {
+                        Some(msg) => sink.send(msg?).await?,
+                        None => return Ok(true),
+                    },
+                    () = ping => sink.send(ping_msg()).await?,
+                    () = silent => {
+                        tracing::debug!(
+                            "proxy: tearing down websocket relay as a side stopped responding"
+                        );
+                        return Ok(false);
+                    }
+                    // the opposite relay may end without firing it
+                    Ok(()) = &mut too_big_rx, if !too_big_rx.is_terminated() => {
+                        sink.send(close_too_big_msg()).await?;
+                        return Ok(true);
+                    }
+                }
+            }
+        };
+        let result = tokio::select! {
+            result = relay => result,
+            () = self.cancel.cancelled() => return Ok(()),
+        };
+        match result {
+            // closes both sides with the dedicated code
+            Err(err) if is_ws_too_big(&err) => {
+                tracing::debug!("proxy: closing websocket connection receiving {err}");
+                // the opposite relay might have ended already
+                let _ = too_big.send(());
+                drop(tokio::time::timeout(self.pong_timeout, sink.send(close_too_big_msg())).await);
+                Ok(())
+            }
+            // waits for the other side finishing the closing handshake
+            Ok(true) => {
+                if !self.ended.swap(true, Ordering::Relaxed) {
+                    drop(tokio::time::timeout(self.pong_timeout, self.cancel.cancelled()).await);
+                }
+                self.cancel.cancel();
+                Ok(())
+            }
+            result => {
+                self.cancel.cancel();
+                result.map(|_| ())
+            }
+        }
+    }
+}
+
+const REASON_TOO_BIG: &str = "message too big";
+
+/// Keepalive ping sent by websocket relays, whose pong is never relayed.
+fn ping_msg() -> tungstenite::Message {
+    tungstenite::Message::Ping(Bytes::from_static(WsRelay::PING_PAYLOAD))
+}
+
+/// Close frame with `1009 Message Too Big` sent by websocket relays.
+fn close_too_big_msg() -> tungstenite::Message {
+    tungstenite::Message::Close(Some(tungstenite::protocol::CloseFrame {
+        code: tungstenite::protocol::frame::coding::CloseCode::Size,
+        reason: REASON_TOO_BIG.into(),
+    }))
+}
+
+/// Whether given websocket error is caused by a frame or message exceeding the size limits.
+fn is_ws_too_big(err: &tungstenite::Error) -> bool {
+    matches!(
+        err,
+        tungstenite::Error::Capacity(tungstenite::error::CapacityError::MessageTooLong { .. })
+    )
+}
+
+/// Handshake response accepting the websocket upgrade of given request on behalf of the
+/// function, the same as axum responds.
+fn ws_accepted_response(request: &Request) -> Response {
+    // extended `CONNECT` requests are accepted with a plain success
+    if request.version() >= http::Version::HTTP_2 {
+        return http::StatusCode::OK.into_response();
+    }
+    let mut response = http::StatusCode::SWITCHING_PROTOCOLS.into_response();
+    let headers = response.headers_mut();
+    headers.insert(
+        http::header::CONNECTION,
+        http::HeaderValue::from_static("upgrade"),
+    );
+    headers.insert(
+        http::header::UPGRADE,
+        http::HeaderValue::from_static("websocket"),
+    );
+    if let Some(key) = request.headers().get(http::header::SEC_WEBSOCKET_KEY) {
+        let accept = tungstenite::handshake::derive_accept_key(key.as_bytes());
+        headers.insert(
+            http::header::SEC_WEBSOCKET_ACCEPT,
+            http::HeaderValue::try_from(accept).expect("accept keys are base64"),
+        );
+    }
+    response
+}
+
+fn map_client_err(err: client::legacy::Error) -> Error {
+    // connect timeouts are reported as I/O errors somewhere in the source chain
+    let timed_out = err.is_connect()
+        && std::iter::successors(std::error::Error::source(&err), |e| e.source())
+            .filter_map(|e| e.downcast_ref::<std::io::Error>())
+            .any(|e| e.kind() == std::io::ErrorKind::TimedOut);
+
+    if timed_out {
+        Error::UpstreamConnectTimeout
+    } else {
+        err.into()
+    }
 }
 
 fn maybe_ws_request(request: &Request) -> bool {
@@ -115,17 +1623,6 @@ fn maybe_ws_request(request: &Request) -> bool {
     }
 }
 
-fn utf8_bytes_axum_from_ts(msg: tungstenite::Utf8Bytes) -> axum::extract::ws::Utf8Bytes {
-    //SAFETY: ts' type already guarantees utf8 validity. we have to cancel the check for performance
-    // cant find a better way to do this. tol
-    unsafe { String::from_utf8_unchecked(Bytes::from(msg).into()).into() }
-}
-
-fn utf8_bytes_ts_from_axum(msg: axum::extract::ws::Utf8Bytes) -> tungstenite::Utf8Bytes {
-    //SAFETY: axum's type already guarantees utf8 validity.
-    unsafe { tungstenite::Utf8Bytes::from_bytes_unchecked(msg.into()) }
-}
-
 // helper functions from axum
 
 #[inline]
@@ -152,43 +1649,39 @@ fn header_contains(headers: &http::HeaderMap, key: http::HeaderName, value: &'st
     }
 }
 
-fn msg_axum_from_ts(message: tungstenite::Message) -> Option<axum::extract::ws::Message> {
-    use tokio_tungstenite::tungstenite as ts;
-    match message {
-        ts::Message::Text(text) => Some(axum::extract::ws::Message::Text(utf8_bytes_axum_from_ts(
-            text,
-        ))),
-        ts::Message::Binary(binary) => Some(axum::extract::ws::Message::Binary(binary)),
-        ts::Message::Ping(ping) => Some(axum::extract::ws::Message::Ping(ping)),
-        ts::Message::Pong(pong) => Some(axum::extract::ws::Message::Pong(pong)),
-        ts::Message::Close(Some(close)) => Some(axum::extract::ws::Message::Close(Some(
-            axum::extract::ws::CloseFrame {
-                code: close.code.into(),
-                // copies the slice internally as we don't have the access to private constructor.
-                // but frame closing is not the hot spot anyway.
-                reason: utf8_bytes_axum_from_ts(close.reason),
-            },
-        ))),
-        ts::Message::Close(None) => Some(axum::extract::ws::Message::Close(None)),
-        // we can ignore `Frame` frames as recommended by the tungstenite maintainers
-        // https://github.com/snapview/tungstenite-rs/issues/268
-        ts::Message::Frame(_) => None,
+/// Sanitizes a close code before relaying it to the other side, as codes reserved for local use
+/// must never appear on the wire.
+///
+/// Returns `None` if there is no status code, which is relayed as a close frame without payload
+/// thus the reason is dropped.
+fn relay_close_code(
+    code: tungstenite::protocol::frame::coding::CloseCode,
+) -> Option<tungstenite::protocol::frame::coding::CloseCode> {
+    use tungstenite::protocol::frame::coding::CloseCode;
+    match code {
+        CloseCode::Status => None,
+        // the other side went away without closing properly
+        CloseCode::Abnormal | CloseCode::Tls => Some(CloseCode::Away),
+        code if code.is_allowed() => Some(code),
+        _ => Some(CloseCode::Normal),
     }
 }
 
-fn msg_ts_from_axum(message: axum::extract::ws::Message) -> tungstenite::Message {
-    use tokio_tungstenite::tungstenite as ts;
+/// Prepares a message received from one side to be relayed to the other, sanitizing its close
+/// code, see [`relay_close_code`].
+///
+/// Returns `None` for raw frames, which are never relayed.
+fn relay_msg(message: tungstenite::Message) -> Option<tungstenite::Message> {
     match message {
-        axum::extract::ws::Message::Text(text) => ts::Message::Text(utf8_bytes_ts_from_axum(text)),
-        axum::extract::ws::Message::Binary(binary) => ts::Message::Binary(binary),
-        axum::extract::ws::Message::Ping(ping) => ts::Message::Ping(ping),
-        axum::extract::ws::Message::Pong(pong) => ts::Message::Pong(pong),
-        axum::extract::ws::Message::Close(Some(close)) => {
-            ts::Message::Close(Some(ts::protocol::CloseFrame {
-                code: ts::protocol::frame::coding::CloseCode::from(close.code),
-                reason: utf8_bytes_ts_from_axum(close.reason),
-            }))
-        }
-        axum::extract::ws::Message::Close(None) => ts::Message::Close(None),
+        tungstenite::Message::Close(Some(close)) => Some(tungstenite::Message::Close(
+            relay_close_code(close.code).map(|code| tungstenite::protocol::CloseFrame {
+                code,
+                reason: close.reason,
+            }),
+        )),
+        // we can ignore `Frame` frames as recommended by the tungstenite maintainers
+        // https://github.com/snapview/tungstenite-rs/issues/268
+        tungstenite::Message::Frame(_) => None,
+        message => Some(message),
     }
 }
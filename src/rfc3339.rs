@@ -0,0 +1,68 @@
+//! (De)serializes instants in RFC 3339 format, through `#[serde(with = "...")]`.
+
+use serde::{Deserialize as _, Deserializer, Serialize as _, Serializer, de::Error as _};
+use time::{UtcDateTime, format_description::well_known::Rfc3339};
+
+/// Serializes an instant.
+///
+/// # Errors
+///
+/// Returns an error if the instant could not be formatted or serialized.
+pub fn serialize<S>(datetime: &UtcDateTime, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    datetime
+        .format(&Rfc3339)
+        .map_err(serde::ser::Error::custom)?
+        .serialize(serializer)
+}
+
+/// Deserializes an instant.
+///
+/// # Errors
+///
+/// Returns an error if the value is not a string in RFC 3339 format.
+pub fn deserialize<'de, D>(deserializer: D) -> Result<UtcDateTime, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    UtcDateTime::parse(&String::deserialize(deserializer)?, &Rfc3339).map_err(D::Error::custom)
+}
+
+/// (De)serializes optional instants in RFC 3339 format, with `None` as `null`.
+pub mod option {
+    use serde::{Deserialize as _, Deserializer, Serialize as _, Serializer, de::Error as _};
+    use time::{UtcDateTime, format_description::well_known::Rfc3339};
+
+    /// Serializes an optional instant.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the instant could not be formatted or serialized.
+    #[allow(clippy::ref_option)] // signature required by serde
+    pub fn serialize<S>(datetime: &Option<UtcDateTime>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        datetime
+            .map(|datetime| datetime.format(&Rfc3339))
+            .transpose()
+            .map_err(serde::ser::Error::custom)?
+            .serialize(serializer)
+    }
+
+    /// Deserializes an optional instant.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value is neither `null` nor a string in RFC 3339 format.
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<UtcDateTime>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|s| UtcDateTime::parse(&s, &Rfc3339).map_err(D::Error::custom))
+            .transpose()
+    }
+}
@@ -5,18 +5,27 @@
 //! - Provide *read-only access* to the specified filesystem endpoints. No write access reserved.
 //! - Provide full access to network.
 //! - Pass through environment variables, both in the host system and variables especially passed to the sandbox.
+//!
+//! With [network isolation](SandboxConfig::network_isolation) enabled, the network is unshared instead
+//! and a private writable directory is provided for the function to listen on a Unix socket.
+//!
+//! [Build commands](BuildCommand) are the only exception of read-only access, running once with
+//! write access to the contents before the function is served.
 
 use std::{
     collections::HashMap,
     path::{Path, PathBuf},
+    pin::Pin,
+    time::Duration,
 };
 
 use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt as _};
 
 use crate::{NonExhaustiveMarker, dnem};
 
 /// Configuration of a sandbox.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SandboxConfig {
     /// Command to be executed in the sandbox.
     ///
@@ -44,10 +53,40 @@ pub struct SandboxConfig {
     #[serde(default)]
     pub envs: HashMap<String, Option<String>>,
 
+    /// Paths to dotenv-format files in the host system, whose variables are passed to the sandbox
+    /// in the given order.
+    ///
+    /// Relative paths are resolved against the function's `contents` directory and must stay
+    /// inside it, while absolute ones must be under directories allowed by the platform. Variables
+    /// in [`Self::envs`] take precedence.
+    #[serde(default)]
+    pub env_files: Box<[PathBuf]>,
+
+    /// Whether to run the function in its own network namespace.
+    ///
+    /// The function is then unreachable through the host network, and should listen on the Unix
+    /// socket at [`SOCKET_PATH`], which is also passed through environment variable [`ENV_SOCKET`].
+    #[serde(default)]
+    pub network_isolation: bool,
+
     /// Whether to inherit stdout from the host system.
+    ///
+    /// Outputs are captured for retrieval through the platform instead if not inherited.
     #[serde(default)]
     pub inherit_stdout: bool,
 
+    /// Destination of outputs from the function, overriding [`Self::inherit_stdout`] if
+    /// specified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_sink: Option<LogSink>,
+
+    /// Grace period in seconds between asking the function to terminate and killing it when
+    /// stopped, for flushing states and closing connections.
+    ///
+    /// Defaults to [`DEFAULT_SHUTDOWN_GRACE`].
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "secs")]
+    pub shutdown_grace: Option<Duration>,
+
     /// Platform-specific configuration extension of the sandbox.
     #[serde(flatten)]
     pub platform_ext: SandboxConfigExt,
@@ -57,13 +96,168 @@ pub struct SandboxConfig {
     pub __ne: NonExhaustiveMarker,
 }
 
+/// Destination of stdout and stderr of a function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum LogSink {
+    /// Outputs are discarded.
+    Null,
+    /// Outputs are inherited from the host system.
+    Inherit,
+    /// Outputs are captured for retrieval through the platform, and written into rotated files
+    /// under the `logs` directory of the function as well.
+    File,
+}
+
+/// One-shot command building contents of a function after unpacked, like installing
+/// dependencies.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BuildCommand {
+    /// Command to be executed in the sandbox, in the same form as [`SandboxConfig::command`].
+    pub command: String,
+
+    /// Arguments to be passed to the command.
+    #[serde(default)]
+    pub args: Box<[String]>,
+
+    #[doc(hidden)]
+    #[serde(skip, default = "dnem")]
+    pub __ne: NonExhaustiveMarker,
+}
+
+/// Partial update of a [`SandboxConfig`], where absent fields are left untouched.
+///
+/// Platform-specific fields are not patchable, which are only changed by overriding the whole
+/// configuration.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SandboxPatch {
+    /// Replaces [`SandboxConfig::command`].
+    pub command: Option<String>,
+    /// Replaces [`SandboxConfig::args`].
+    pub args: Option<Box<[String]>>,
+    /// Replaces [`SandboxConfig::ro_entries`] entirely, as `null` values already mean keeping
+    /// the same paths there.
+    pub ro_entries: Option<HashMap<PathBuf, Option<PathBuf>>>,
+    /// Merged into [`SandboxConfig::envs`] key by key, with `null` values removing the keys.
+    #[serde(default)]
+    pub envs: HashMap<String, Option<String>>,
+    /// Replaces [`SandboxConfig::env_files`].
+    pub env_files: Option<Box<[PathBuf]>>,
+    /// Replaces [`SandboxConfig::network_isolation`].
+    pub network_isolation: Option<bool>,
+    /// Replaces [`SandboxConfig::inherit_stdout`].
+    pub inherit_stdout: Option<bool>,
+    /// Replaces [`SandboxConfig::log_sink`].
+    #[serde(default, deserialize_with = "crate::func::present")]
+    pub log_sink: Option<Option<LogSink>>,
+    /// Replaces [`SandboxConfig::shutdown_grace`].
+    #[serde(default, deserialize_with = "secs::deserialize_present")]
+    pub shutdown_grace: Option<Option<Duration>>,
+
+    #[doc(hidden)]
+    #[serde(skip, default = "dnem")]
+    pub __ne: NonExhaustiveMarker,
+}
+
+impl SandboxPatch {
+    /// Merges this patch into given configuration.
+    pub fn apply(self, config: &mut SandboxConfig) {
+        if let Some(command) = self.command {
+            config.command = command;
+        }
+        if let Some(args) = self.args {
+            config.args = args;
+        }
+        if let Some(ro_entries) = self.ro_entries {
+            config.ro_entries = ro_entries;
+        }
+        for (key, value) in self.envs {
+            match value {
+                Some(value) => drop(config.envs.insert(key, Some(value))),
+                None => drop(config.envs.remove(&key)),
+            }
+        }
+        if let Some(env_files) = self.env_files {
+            config.env_files = env_files;
+        }
+        if let Some(network_isolation) = self.network_isolation {
+            config.network_isolation = network_isolation;
+        }
+        if let Some(inherit_stdout) = self.inherit_stdout {
+            config.inherit_stdout = inherit_stdout;
+        }
+        if let Some(log_sink) = self.log_sink {
+            config.log_sink = log_sink;
+        }
+        if let Some(shutdown_grace) = self.shutdown_grace {
+            config.shutdown_grace = shutdown_grace;
+        }
+    }
+}
+
+impl SandboxConfig {
+    /// Returns the [grace period](Self::shutdown_grace) of stopping the function, or the default
+    /// one if not specified.
+    #[inline]
+    pub fn shutdown_grace(&self) -> Duration {
+        self.shutdown_grace.unwrap_or(DEFAULT_SHUTDOWN_GRACE)
+    }
+}
+
+/// Default grace period between asking a function to terminate and killing it.
+pub const DEFAULT_SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+/// (De)serializes optional durations in seconds.
+pub(crate) mod secs {
+    use std::time::Duration;
+
+    use serde::{Deserialize as _, Deserializer, Serialize as _, Serializer, de::Error as _};
+
+    #[allow(clippy::ref_option)] // signature required by serde
+    pub fn serialize<S>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        duration.map(|d| d.as_secs_f64()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<f64>::deserialize(deserializer)?
+            .map(|secs| Duration::try_from_secs_f64(secs).map_err(D::Error::custom))
+            .transpose()
+    }
+
+    /// Deserializes a field which is present, telling `null` apart from absent fields.
+    pub fn deserialize_present<'de, D>(
+        deserializer: D,
+    ) -> Result<Option<Option<Duration>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserialize(deserializer).map(Some)
+    }
+}
+
+/// Directory in the sandbox the private runtime directory is mounted at.
+pub const SOCKET_DIR: &str = "/run/yfass";
+/// Path in the sandbox of the Unix socket a network-isolated function should listen on.
+pub const SOCKET_PATH: &str = "/run/yfass/function.sock";
+/// Name of the Unix socket in the private runtime directory.
+pub const SOCKET_NAME: &str = "function.sock";
+/// Environment variable passing [`SOCKET_PATH`] to network-isolated functions.
+pub const ENV_SOCKET: &str = "YFASS_SOCKET";
+
 #[cfg(target_os = "linux")]
 type SandboxConfigExt = crate::os::linux::SandboxConfigExt;
 
 #[cfg(not(target_os = "linux"))]
 type SandboxConfigExt = SandboxConfigExtFallback;
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 #[allow(unused)]
 struct SandboxConfigExtFallback {}
 
@@ -73,11 +267,51 @@ pub trait Sandbox: Default {
     type Handle: Handle;
 
     /// Spawns a new sandbox task.
+    ///
+    /// The `runtime_path` is a private writable directory on host, mounted at [`SOCKET_DIR`] only
+    /// if network isolation is enabled.
+    ///
+    /// # Errors
+    ///
+    /// Errors with [`PrivilegeDenied`] as the inner error if the sandbox could not be created due
+    /// to insufficient privileges of the platform.
     fn spawn(
         &self,
         config: &SandboxConfig,
         contents_path: &Path,
+        runtime_path: &Path,
     ) -> impl Future<Output = std::io::Result<Self::Handle>> + Send;
+
+    /// Runs a build command to completion, returning its exit status.
+    ///
+    /// The command is isolated the same as [`Self::spawn`] with given configuration, except that
+    /// the contents are writable. Its piped outputs are passed to `output` once spawned, which
+    /// should keep reading them until EOF so the command is never blocked on writing.
+    ///
+    /// Builds are unsupported by default.
+    ///
+    /// # Errors
+    ///
+    /// - Errors with [`PrivilegeDenied`] as the inner error if the sandbox could not be created
+    ///   due to insufficient privileges of the platform.
+    /// - [`std::io::ErrorKind::Unsupported`] if builds are unsupported.
+    fn build<F>(
+        &self,
+        config: &SandboxConfig,
+        build: &BuildCommand,
+        contents_path: &Path,
+        runtime_path: &Path,
+        output: F,
+    ) -> impl Future<Output = std::io::Result<std::process::ExitStatus>> + Send
+    where
+        F: FnOnce(Output) + Send,
+    {
+        drop((config, build, contents_path, runtime_path, output));
+        std::future::ready(Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "builds are unsupported by the sandbox",
+        )))
+    }
 }
 
 /// Handle of a running sandbox.
@@ -85,11 +319,197 @@ pub trait Handle: 'static {
     /// Kills the underlying sandbox task.
     fn kill(self) -> impl Future<Output = ()> + Send;
 
+    /// Stops the underlying sandbox task gracefully, asking it to terminate first then killing it
+    /// if it's still running after given grace period.
+    ///
+    /// Kills the task immediately by default.
+    #[inline]
+    fn terminate(self, _grace: Duration) -> impl Future<Output = ()> + Send
+    where
+        Self: Sized,
+    {
+        self.kill()
+    }
+
     /// Whether this task is still running or not.
     #[inline]
     fn is_running(&self) -> bool {
         true
     }
+
+    /// Takes the piped output streams of this task for capturing.
+    ///
+    /// Returns `None` if outputs are not piped or are already taken.
+    #[inline]
+    fn take_output(&mut self) -> Option<Output> {
+        None
+    }
+
+    /// Checks whether this task has exited on its own, without blocking.
+    #[inline]
+    fn check_exited(&mut self) -> bool {
+        false
+    }
+}
+
+/// Boxed reader of an output stream.
+pub type OutputReader = Pin<Box<dyn AsyncRead + Send>>;
+
+/// Piped output streams of a sandbox task.
+#[derive(Default)]
+pub struct Output {
+    /// The standard output.
+    pub stdout: Option<OutputReader>,
+    /// The standard error.
+    pub stderr: Option<OutputReader>,
+}
+
+/// Error of creating a sandbox due to insufficient privileges, for example when unprivileged
+/// user namespaces are disabled by the kernel.
+#[derive(Debug, thiserror::Error)]
+#[error("insufficient privileges to create the sandbox: {0}")]
+pub struct PrivilegeDenied(pub String);
+
+/// Error of parsing an environment variable file, see [`SandboxConfig::env_files`].
+#[derive(Debug, thiserror::Error)]
+#[error("invalid environment variable file {} at line {line}: {reason}", path.display())]
+pub struct EnvFileError {
+    /// Path to the file.
+    pub path: PathBuf,
+    /// Line number starting from 1.
+    pub line: usize,
+    /// Reason of the failure.
+    pub reason: &'static str,
+}
+
+/// Reads and parses the [environment variable files](SandboxConfig::env_files) of given
+/// configuration, returning the variables in order.
+///
+/// # Errors
+///
+/// - Errors with [`EnvFileError`] as the inner error if any file is malformed.
+/// - Errors with [`std::io::ErrorKind::PermissionDenied`] if any file with relative path
+///   resolves outside of the contents directory through symlinks.
+/// - Other errors if failed to read the files.
+pub async fn read_env_files(
+    config: &SandboxConfig,
+    contents_path: &Path,
+) -> std::io::Result<Vec<(String, String)>> {
+    let mut envs = Vec::new();
+    if config.env_files.is_empty() {
+        return Ok(envs);
+    }
+    let contents_path = tokio::fs::canonicalize(contents_path).await?;
+    for path in &config.env_files {
+        let relative = path.is_relative();
+        let path = contents_path.join(path);
+        let read_err = |err: std::io::Error| {
+            std::io::Error::new(
+                err.kind(),
+                format!(
+                    "failed to read environment variable file {}: {err}",
+                    path.display()
+                ),
+            )
+        };
+        // symlinks in contents may point to arbitrary files in the host system, so the file is
+        // checked by where it's actually opened, leaving no window for swapping them in between
+        let mut file = tokio::fs::File::open(&path).await.map_err(read_err)?;
+        if relative
+            && !opened_path(&file)
+                .await
+                .map_err(read_err)?
+                .starts_with(&contents_path)
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                format!(
+                    "environment variable file {} resolves outside the contents directory",
+                    path.display()
+                ),
+            ));
+        }
+        let mut raw = String::new();
+        file.read_to_string(&mut raw).await.map_err(read_err)?;
+        parse_env_file(&raw, &mut envs).map_err(|(line, reason)| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                EnvFileError { path, line, reason },
+            )
+        })?;
+    }
+    Ok(envs)
+}
+
+/// Returns the path given opened file actually resides at.
+#[cfg(target_os = "linux")]
+async fn opened_path(file: &tokio::fs::File) -> std::io::Result<PathBuf> {
+    use std::os::fd::AsRawFd as _;
+    tokio::fs::read_link(format!("/proc/self/fd/{}", file.as_raw_fd())).await
+}
+
+/// Returns the path given opened file actually resides at.
+#[cfg(not(target_os = "linux"))]
+async fn opened_path(_: &tokio::fs::File) -> std::io::Result<PathBuf> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// Parses lines of `KEY=VALUE` with optional `export` prefixes, quoted values and comments.
+///
+/// Errors with the line number and the reason.
+fn parse_env_file(
+    raw: &str,
+    envs: &mut Vec<(String, String)>,
+) -> Result<(), (usize, &'static str)> {
+    for (i, line) in raw.lines().enumerate() {
+        let err = |reason| (i + 1, reason);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = line.split_once('=').ok_or(err("missing `=`"))?;
+        let key = key.trim();
+        if key.is_empty()
+            || key.starts_with(|c: char| c.is_ascii_digit())
+            || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            return Err(err("invalid variable name"));
+        }
+
+        let value = value.trim();
+        let value = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let inner = &value[1..];
+                let end = inner.find(quote).ok_or(err("unterminated quote"))?;
+                let rest = inner[end + 1..].trim_start();
+                if !(rest.is_empty() || rest.starts_with('#')) {
+                    return Err(err("unexpected characters after quoted value"));
+                }
+                if quote == '"' {
+                    inner[..end].replace("\\n", "\n")
+                } else {
+                    inner[..end].to_owned()
+                }
+            }
+            // inline comments are preceded by whitespace
+            _ => value
+                .find(" #")
+                .map_or(value, |pos| value[..pos].trim_end())
+                .to_owned(),
+        };
+        envs.push((key.to_owned(), value));
+    }
+    Ok(())
+}
+
+impl std::fmt::Debug for Output {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Output")
+            .field("stdout", &self.stdout.is_some())
+            .field("stderr", &self.stderr.is_some())
+            .finish()
+    }
 }
 
 impl Default for SandboxConfig {
@@ -99,7 +519,11 @@ impl Default for SandboxConfig {
             args: vec![].into_boxed_slice(),
             ro_entries: HashMap::new(),
             envs: HashMap::new(),
+            env_files: Box::default(),
+            network_isolation: false,
             inherit_stdout: false,
+            log_sink: None,
+            shutdown_grace: None,
             platform_ext: Default::default(),
             __ne: dnem(),
         }
@@ -115,8 +539,37 @@ impl Handle for tokio::process::Child {
         )
     }
 
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+    async fn terminate(mut self, grace: Duration) {
+        #[cfg(target_os = "linux")]
+        if let Some(pid) = self.id()
+            && crate::os::linux::signal_sandboxed(pid, libc::SIGTERM)
+        {
+            if tokio::time::timeout(grace, self.wait()).await.is_ok() {
+                return;
+            }
+            tracing::warn!(
+                "sandbox process {pid} is still running {:?} after terminated, killing it",
+                grace
+            );
+        }
+        Handle::kill(self).await
+    }
+
     #[inline]
     fn is_running(&self) -> bool {
         self.id().is_some()
     }
+
+    fn take_output(&mut self) -> Option<Output> {
+        let stdout = self.stdout.take().map(|r| Box::pin(r) as OutputReader);
+        let stderr = self.stderr.take().map(|r| Box::pin(r) as OutputReader);
+        (stdout.is_some() || stderr.is_some()).then_some(Output { stdout, stderr })
+    }
+
+    fn check_exited(&mut self) -> bool {
+        self.try_wait()
+            .inspect_err(|e| tracing::error!("failed to check status of sandbox process: {e}"))
+            .is_ok_and(|status| status.is_some())
+    }
 }
@@ -0,0 +1,114 @@
+use axum::{Json, body::Body, extract::Query, http::header, response::IntoResponse};
+use serde::{Deserialize, Serialize};
+use yfass::migrate;
+
+use crate::{Auth, Error, PermissionFlags, State};
+
+const CONTENT_TYPE_TAR: &str = "application/x-tar";
+/// Size of the in-memory pipe between the archive builder and the response body.
+const EXPORT_BUFFER_SIZE: usize = 64 * 1024;
+
+const PERMISSION_EXPORT: u32 = PermissionFlags::ROOT.bits();
+pub(crate) const PATH_EXPORT: &str = "/api/admin/export";
+
+/// Exports the full platform state as a tarball, including users and information and contents
+/// of every function.
+///
+/// # Request
+///
+/// - Authentication is required with permission `ROOT`.
+///
+/// # Response
+///
+/// - Responsed with a streamed tarball following the layout of [`yfass::migrate`].
+pub async fn export(cx: State, Auth(_): Auth<PERMISSION_EXPORT>) -> impl IntoResponse {
+    let (writer, reader) = tokio::io::duplex(EXPORT_BUFFER_SIZE);
+    let cx = cx.0.clone();
+    tokio::spawn(async move {
+        // the response body ends up truncated on failures, which clients detect as corruption
+        if let Err(err) = migrate::export(&cx.funcs, &cx.users, writer).await {
+            tracing::error!("failed to export platform state: {err}");
+        }
+    });
+
+    (
+        [
+            (header::CONTENT_TYPE, CONTENT_TYPE_TAR),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"yfass-export.tar\"",
+            ),
+        ],
+        Body::from_stream(tokio_util::io::ReaderStream::new(reader)),
+    )
+}
+
+#[derive(Deserialize)]
+pub struct ImportQuery {
+    /// Whether to import into a non-empty instance, replacing conflicting entries.
+    #[serde(default)]
+    pub force: bool,
+}
+
+#[derive(Serialize)]
+pub struct ImportResponse {
+    /// Keys of imported functions.
+    pub functions: Vec<String>,
+}
+
+const PERMISSION_IMPORT: u32 = PermissionFlags::ROOT.bits();
+pub(crate) const PATH_IMPORT: &str = "/api/admin/import";
+
+/// Restores the platform state from a tarball produced by [`export`].
+///
+/// # Request
+///
+/// - Authentication is required with permission `ROOT`.
+/// - Body is required to receive an uncompressed tarball.
+/// - The instance is required to have no functions and users other than root, unless query
+///   `force=true` is given, replacing conflicting functions and users. No function is allowed to
+///   be running when forced.
+///
+/// # Response
+///
+/// - Responsed with JSON body [`ImportResponse`].
+pub async fn import(
+    cx: State,
+    Auth(_): Auth<PERMISSION_IMPORT>,
+    Query(ImportQuery { force }): Query<ImportQuery>,
+    body: Body,
+) -> Result<Json<ImportResponse>, Error> {
+    if force {
+        if !cx.handles.is_empty() {
+            return Err(Error::InstancesRunning);
+        }
+    } else if !cx.funcs.is_empty() || !cx.users.is_empty() {
+        return Err(Error::InstanceNotEmpty);
+    }
+
+    let mut archive = tokio_tar::Archive::new(super::func::body_reader(body));
+    let permit = cx.upload_permit().await;
+    let keys = migrate::import(&cx.funcs, &cx.users, &mut archive, force).await;
+    drop(permit);
+
+    Ok(Json(ImportResponse {
+        functions: keys?.iter().map(ToString::to_string).collect(),
+    }))
+}
+
+const PERMISSION_FLUSH: u32 = PermissionFlags::ROOT.bits();
+pub(crate) const PATH_FLUSH: &str = "/api/admin/flush";
+
+/// Writes dirty functions and users into the storage on demand, for checkpointing before planned
+/// restarts without waiting for the periodic save.
+///
+/// # Request
+///
+/// - Authentication is required with permission `ROOT`.
+///
+/// # Response
+///
+/// - Responsed with the error of the first failed write, after trying to write everything.
+pub async fn flush(cx: State, Auth(_): Auth<PERMISSION_FLUSH>) -> Result<(), Error> {
+    crate::save_data(&cx).await
+}
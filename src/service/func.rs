@@ -1,31 +1,234 @@
-use std::borrow::Cow;
+use std::{
+    borrow::Cow,
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::{Context, Poll},
+};
 
-use axum::{Json, body::Body, extract::Path};
+use axum::{
+    Json,
+    body::Body,
+    extract::{Path, Query},
+    http::{StatusCode, header},
+    response::IntoResponse,
+};
 use futures_util::TryStreamExt as _;
 use serde::{Deserialize, Serialize};
-use yfass::{func, user};
+use time::UtcDateTime;
+use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _};
+use yfass::{func, sandbox::LogSink, upload, user};
 
-use crate::{Auth, ContentType, Error, PermissionFlags, State};
+use crate::{
+    Auth, ContentType, Error, PermissionFlags, QuotaReservation, State, proxy, quota_error,
+};
 
-fn validate_key_param(name: &str) -> Result<(), Error> {
-    if name.is_empty() {
-        return Err(Error::InvalidKeyFormat);
+/// Validates a name, version or alias of functions, which becomes a label of subdomains.
+fn validate_key_param(cx: &State, name: &str) -> Result<(), Error> {
+    if name.is_empty()
+        || name.len() > cx.max_function_name_length
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    {
+        return Err(Error::InvalidKeyFormat(cx.max_function_name_length));
     }
-    name.chars()
-        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    if cx.reserved_labels.iter().any(|label| label == name) {
+        return Err(Error::ReservedLabel(name.to_owned()));
+    }
+    Ok(())
+}
+
+/// Checks whether the token is allowed to manage a function by the group requirement of the
+/// function, telling missing functions apart from denied ones.
+fn authorize_group(cx: &State, token: &str, key: func::Key<'_>) -> Result<(), Error> {
+    cx.funcs
+        .with_function(key, |func| {
+            cx.users
+                .auth(token, func.config.group.iter().map(Cow::Borrowed))
+        })
+        .ok_or_else(|| Error::FunctionNotFound(key.into_owned()))?
         .then_some(())
-        .ok_or(Error::InvalidKeyFormat)
+        .ok_or_else(|| Error::GroupPermissionDenied(key.into_owned()))
 }
 
 const PERMISSION_UPLOAD: u32 = PermissionFlags::WRITE.bits();
 pub(crate) const PATH_UPLOAD: &str = "/api/upload/{key}";
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchiveFormat {
+    Tar,
+    Gzip,
+}
+
+/// Reads the leading bytes of the given stream into `head` and detects the archive format from
+/// magic bytes, or `None` if the format is unrecognized.
+async fn sniff_archive_format<R>(
+    reader: &mut R,
+    head: &mut Vec<u8>,
+) -> std::io::Result<Option<ArchiveFormat>>
+where
+    R: AsyncRead + Unpin,
+{
+    const MAGIC_GZIP: &[u8] = &[0x1f, 0x8b];
+    const MAGIC_TAR: &[u8] = b"ustar";
+    const OFFSET_MAGIC_TAR: usize = 257;
+    const LEN_SNIFF: usize = OFFSET_MAGIC_TAR + MAGIC_TAR.len();
+
+    reader.take(LEN_SNIFF as u64).read_to_end(head).await?;
+
+    Ok(if head.starts_with(MAGIC_GZIP) {
+        Some(ArchiveFormat::Gzip)
+    } else if head.get(OFFSET_MAGIC_TAR..LEN_SNIFF) == Some(MAGIC_TAR) {
+        Some(ArchiveFormat::Tar)
+    } else {
+        None
+    })
+}
+
+const CONTENT_TYPE_TAR: &str = "application/x-tar";
+const CONTENT_TYPE_GZIP: &str = "application/gzip";
+const CONTENT_TYPE_GZIP_NON_STANDARD: &str = "application/x-gzip";
+const CONTENT_TYPE_OCTET_STREAM: &str = "application/octet-stream";
+
+/// Converts given body into a reader.
+pub(super) fn body_reader(body: Body) -> impl AsyncRead + Unpin {
+    tokio_util::io::StreamReader::new(body.into_data_stream().map_err(std::io::Error::other))
+}
+
+/// Converts given body of an uploaded archive into a reader failing once the body exceeds
+/// `--max-upload-bytes`, along with the tracker of whether it happened.
+fn limited_body_reader(
+    cx: &State,
+    body: Body,
+) -> (LimitedReader<impl AsyncRead + Unpin>, UploadLimit) {
+    limited_reader(cx, body_reader(body))
+}
+
+/// Same as [`limited_body_reader`], but for an archive read from given reader.
+fn limited_reader<R>(cx: &State, inner: R) -> (LimitedReader<R>, UploadLimit)
+where
+    R: AsyncRead + Unpin,
+{
+    let limit = UploadLimit {
+        max: cx.max_upload_bytes,
+        exceeded: Arc::default(),
+    };
+    let reader = LimitedReader {
+        inner,
+        remaining: cx.max_upload_bytes.unwrap_or(u64::MAX),
+        exceeded: limit.exceeded.clone(),
+    };
+    (reader, limit)
+}
+
+/// Reserves the disk quota of the user owning given function for replacing its contents, with the
+/// size of the current contents left available as they're replaced.
+///
+/// Functions owned by groups other than a single user are never limited.
+async fn reserve_replace_quota(
+    cx: &State,
+    key: func::Key<'_>,
+) -> Result<Option<QuotaReservation>, Error> {
+    let owner = cx
+        .funcs
+        .with_function(key, |func| match &func.config.group {
+            Some(user::Group::Singular(owner)) => Some(owner.clone()),
+            _ => None,
+        })
+        .ok_or_else(|| Error::FunctionNotFound(key.into_owned()))?;
+    let Some(owner) = owner else {
+        return Ok(None);
+    };
+    let current = cx.funcs.contents_bytes(key).await?;
+    cx.reserve_quota(&owner, current).await
+}
+
+/// Reader failing once more bytes than the limit are read, which aborts unpacking.
+struct LimitedReader<R> {
+    inner: R,
+    remaining: u64,
+    exceeded: Arc<AtomicBool>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for LimitedReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let filled = buf.filled().len();
+        std::task::ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        let read = (buf.filled().len() - filled) as u64;
+        match self.remaining.checked_sub(read) {
+            Some(remaining) => {
+                self.remaining = remaining;
+                Poll::Ready(Ok(()))
+            }
+            None => {
+                self.exceeded.store(true, Ordering::Relaxed);
+                Poll::Ready(Err(std::io::Error::other("upload size limit exceeded")))
+            }
+        }
+    }
+}
+
+/// Tracker of whether an uploaded body exceeded the size limit.
+struct UploadLimit {
+    max: Option<u64>,
+    exceeded: Arc<AtomicBool>,
+}
+
+impl UploadLimit {
+    /// Replaces errors caused by exceeding the limit with a dedicated one.
+    fn map_err(&self, err: Error) -> Error {
+        match self.max {
+            Some(max) if self.exceeded.load(Ordering::Relaxed) => Error::UploadTooLarge(max),
+            _ => err,
+        }
+    }
+}
+
+/// Resolves the archive format of given stream and returns a reader of the tarball inside.
+async fn tarball_reader<R>(ty: &str, mut reader: R) -> Result<impl AsyncRead + Unpin, Error>
+where
+    R: AsyncRead + Unpin,
+{
+    // bytes consumed from the body while sniffing, chained back in front of the stream
+    let mut head = Vec::new();
+    let format = match ty {
+        // .tar file
+        CONTENT_TYPE_TAR => ArchiveFormat::Tar,
+        // .tar.gz / .tgz file
+        CONTENT_TYPE_GZIP | CONTENT_TYPE_GZIP_NON_STANDARD => ArchiveFormat::Gzip,
+        // clients unable to set a precise content type
+        CONTENT_TYPE_OCTET_STREAM => sniff_archive_format(&mut reader, &mut head)
+            .await?
+            .ok_or(Error::UnrecognizedArchive)?,
+        _ => return Err(Error::UnsupportedArchiveType),
+    };
+    let reader = std::io::Cursor::new(head).chain(reader);
+
+    match format {
+        ArchiveFormat::Tar => Ok(reader),
+        ArchiveFormat::Gzip => {
+            // Ok(async_compression::tokio::bufread::GzipDecoder::new(reader))
+
+            Err(Error::Unstable("upload-tar-gz"))
+        }
+    }
+}
+
 /// Deploys a function by uploading a tarball.
 ///
 /// # Request
 ///
 /// - Authentication is required with permission `WRITE`.
 /// - Body is required to receive a tarball or gzipped tarball.
+/// - For `application/octet-stream` bodies the archive format is detected from magic bytes.
+/// - The archive must fit in the remaining disk quota of the user, if any.
 pub async fn upload(
     cx: State,
     Auth(token): Auth<PERMISSION_UPLOAD>,
@@ -33,46 +236,425 @@ pub async fn upload(
     Path(key): Path<func::OwnedKey>,
     body: Body,
 ) -> Result<(), Error> {
-    validate_key_param(&key.name)?;
-    validate_key_param(&key.version)?;
+    validate_key_param(&cx, &key.name)?;
+    validate_key_param(&cx, &key.version)?;
 
     let user = cx.users.user_name(&token).ok_or(Error::Unauthorized)?;
+    let quota = cx.reserve_quota(&user, 0).await?;
+    let group = Some(user::Group::Singular(user));
+    let (reader, limit) = limited_body_reader(&cx, body);
+    let reader = tarball_reader(&ty, reader)
+        .await
+        .map_err(|err| limit.map_err(err))?;
 
-    const CONTENT_TYPE_TAR: &str = "application/x-tar";
-    const CONTENT_TYPE_GZIP: &str = "application/gzip";
-    const CONTENT_TYPE_GZIP_NON_STANDARD: &str = "application/x-gzip";
+    cx.add_func(
+        key.as_ref(),
+        group,
+        &mut tokio_tar::Archive::new(reader),
+        quota,
+    )
+    .await
+    .map_err(|err| limit.map_err(err))?;
+    Ok(())
+}
+
+#[derive(Serialize)]
+pub struct UploadDeployResponse {
+    /// Whether the function was uploaded.
+    pub uploaded: bool,
+    /// Whether the function was deployed.
+    pub deployed: bool,
+    /// Error occurred when deploying the uploaded function.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+const PERMISSION_UPLOAD_DEPLOY: u32 = PermissionFlags::WRITE
+    .union(PermissionFlags::EXECUTE)
+    .bits();
+pub(crate) const PATH_UPLOAD_DEPLOY: &str = "/api/upload-deploy/{key}";
+
+/// Uploads a tarball then deploys the function in one call.
+///
+/// The function stays uploaded if deploying fails.
+///
+/// # Request
+///
+/// - Authentication is required with permission `WRITE` and `EXECUTE`.
+/// - Body is required to receive a tarball, the same as [`upload`].
+///
+/// # Response
+///
+/// - Responsed with JSON body [`UploadDeployResponse`], with status code of the deploy error if
+///   deploying failed.
+pub async fn upload_deploy(
+    cx: State,
+    Auth(token): Auth<PERMISSION_UPLOAD_DEPLOY>,
+    ContentType(ty): ContentType,
+    Path(key): Path<func::OwnedKey>,
+    body: Body,
+) -> Result<impl IntoResponse, Error> {
+    validate_key_param(&cx, &key.name)?;
+    validate_key_param(&cx, &key.version)?;
 
+    let user = cx.users.user_name(&token).ok_or(Error::Unauthorized)?;
+    let quota = cx.reserve_quota(&user, 0).await?;
     let group = Some(user::Group::Singular(user));
-    let reader =
-        tokio_util::io::StreamReader::new(body.into_data_stream().map_err(std::io::Error::other));
+    let (reader, limit) = limited_body_reader(&cx, body);
+    let reader = tarball_reader(&ty, reader)
+        .await
+        .map_err(|err| limit.map_err(err))?;
 
-    match &*ty {
-        // .tar file
-        CONTENT_TYPE_TAR => {
-            cx.funcs
-                .add_func(key.as_ref(), group, &mut tokio_tar::Archive::new(reader))
-                .await?;
-        }
-        // .tar.gz / .tgz file
-        CONTENT_TYPE_GZIP | CONTENT_TYPE_GZIP_NON_STANDARD => {
-            // cx.funcs
-            //     .add_func(
-            //         key.as_ref(),
-            //         group,
-            //         &mut tokio_tar::Archive::new(
-            //             async_compression::tokio::bufread::GzipDecoder::new(reader),
-            //         ),
-            //     )
-            //     .await?
-
-            return Err(Error::Unstable("upload-tar-gz"));
-        }
-        _ => return Err(Error::UnsupportedArchiveType),
+    cx.add_func(
+        key.as_ref(),
+        group,
+        &mut tokio_tar::Archive::new(reader),
+        quota,
+    )
+    .await
+    .map_err(|err| limit.map_err(err))?;
+
+    Ok(match cx.start_fn(key.as_ref()).await {
+        Ok(()) => (
+            StatusCode::OK,
+            Json(UploadDeployResponse {
+                uploaded: true,
+                deployed: true,
+                error: None,
+            }),
+        ),
+        Err(err) => (
+            err.status_code(),
+            Json(UploadDeployResponse {
+                uploaded: true,
+                deployed: false,
+                error: Some(err.to_string()),
+            }),
+        ),
+    })
+}
+
+#[derive(Serialize)]
+pub struct UploadSessionResponse {
+    /// Id of the upload session.
+    pub id: String,
+}
+
+const PERMISSION_UPLOAD_SESSION: u32 = PermissionFlags::WRITE.bits();
+/// Path of an upload session, except for creating one where the parameter is the function key.
+///
+/// The parameter is shared as routes with different parameter names at the same position
+/// conflict.
+pub(crate) const PATH_UPLOAD_SESSION: &str = "/api/upload-session/{id}";
+pub(crate) const PATH_UPLOAD_SESSION_COMMIT: &str = "/api/upload-session/{id}/commit";
+
+/// Starts a resumable upload of a function, whose archive is uploaded in chunks through
+/// [`append_upload_session`] and unpacked by [`commit_upload_session`].
+///
+/// Sessions inactive for longer than the TTL configured for the platform are removed, and each
+/// user could hold a limited count of open sessions.
+///
+/// # Request
+///
+/// - Authentication is required with permission `WRITE`.
+/// - Path parameter is the function key.
+/// - Content type is required to be the one of the archive, the same as [`upload`].
+///
+/// # Response
+///
+/// - Responsed with JSON body [`UploadSessionResponse`].
+pub async fn create_upload_session(
+    cx: State,
+    Auth(token): Auth<PERMISSION_UPLOAD_SESSION>,
+    ContentType(ty): ContentType,
+    Path(key): Path<func::OwnedKey>,
+) -> Result<Json<UploadSessionResponse>, Error> {
+    validate_key_param(&cx, &key.name)?;
+    validate_key_param(&cx, &key.version)?;
+    if !matches!(
+        &*ty,
+        CONTENT_TYPE_TAR
+            | CONTENT_TYPE_GZIP
+            | CONTENT_TYPE_GZIP_NON_STANDARD
+            | CONTENT_TYPE_OCTET_STREAM
+    ) {
+        return Err(Error::UnsupportedArchiveType);
+    }
+    // fails fast instead of after the whole archive is uploaded
+    if cx.funcs.get(key.as_ref()).is_some() {
+        return Err(func::ManagerError::Duplicated.into());
+    }
+
+    let owner = cx.users.user_name(&token).ok_or(Error::Unauthorized)?;
+    let id = user::gen_token(&mut *cx.rng.lock());
+    cx.uploads
+        .create(
+            id.clone(),
+            upload::SessionInfo {
+                key,
+                owner,
+                content_type: ty,
+            },
+        )
+        .await?;
+    Ok(Json(UploadSessionResponse { id }))
+}
+
+/// Returns information of an upload session owned by the user of given token.
+async fn owned_upload_session(
+    cx: &State,
+    token: &str,
+    id: &str,
+) -> Result<(upload::SessionInfo, u64), Error> {
+    let user = cx.users.user_name(token).ok_or(Error::Unauthorized)?;
+    let (info, received) = cx
+        .uploads
+        .get(id)
+        .await
+        .ok_or(upload::UploadError::NotFound)?;
+    if info.owner == user {
+        Ok((info, received))
+    } else {
+        Err(Error::PermissionDenied)
+    }
+}
+
+#[derive(Serialize)]
+pub struct UploadSessionStatusResponse {
+    /// Key of the function being uploaded.
+    pub key: String,
+    /// Count of bytes received, which is the offset of the next chunk.
+    pub received: u64,
+}
+
+/// Returns status of an upload session, for resuming from the bytes received.
+///
+/// # Request
+///
+/// - Authentication is required with permission `WRITE` and _ownership of the session._
+///
+/// # Response
+///
+/// - Responsed with JSON body [`UploadSessionStatusResponse`].
+pub async fn upload_session_status(
+    cx: State,
+    Auth(token): Auth<PERMISSION_UPLOAD_SESSION>,
+    Path(id): Path<String>,
+) -> Result<Json<UploadSessionStatusResponse>, Error> {
+    let (info, received) = owned_upload_session(&cx, &token, &id).await?;
+    Ok(Json(UploadSessionStatusResponse {
+        key: info.key.to_string(),
+        received,
+    }))
+}
+
+/// Parses value of `Content-Range` in form of `bytes START-END/TOTAL` into the inclusive range
+/// and the total length, which could be `*` if unknown.
+fn parse_content_range(value: &str) -> Option<(u64, u64, Option<u64>)> {
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let (start, end) = range.split_once('-')?;
+    let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+    let total = match total {
+        "*" => None,
+        total => Some(total.parse().ok()?),
+    };
+    (start <= end && total.is_none_or(|total| end < total)).then_some((start, end, total))
+}
+
+/// Appends a chunk of the archive to an upload session.
+///
+/// Bytes received before the connection breaks are kept, so clients could resume from the count
+/// reported by [`upload_session_status`].
+///
+/// # Request
+///
+/// - Authentication is required with permission `WRITE` and _ownership of the session._
+/// - Header `Content-Range` is required, with the start being the count of bytes received.
+///   Excess bytes of the body beyond the range are ignored.
+///
+/// # Response
+///
+/// - Responsed with JSON body [`UploadSessionStatusResponse`].
+pub async fn append_upload_session(
+    cx: State,
+    Auth(token): Auth<PERMISSION_UPLOAD_SESSION>,
+    Path(id): Path<String>,
+    headers: header::HeaderMap,
+    body: Body,
+) -> Result<Json<UploadSessionStatusResponse>, Error> {
+    let (info, _) = owned_upload_session(&cx, &token, &id).await?;
+    let (start, end, total) = headers
+        .get(header::CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_content_range)
+        .ok_or(Error::InvalidContentRange)?;
+    if let Some(max) = cx.max_upload_bytes
+        && total.unwrap_or(end + 1).max(end + 1) > max
+    {
+        return Err(Error::UploadTooLarge(max));
     }
 
+    let mut reader = body_reader(body).take(end - start + 1);
+    let received = cx.uploads.append(&id, start, total, &mut reader).await?;
+    Ok(Json(UploadSessionStatusResponse {
+        key: info.key.to_string(),
+        received,
+    }))
+}
+
+/// Finishes an upload session by unpacking the uploaded archive, the same as [`upload`].
+///
+/// # Request
+///
+/// - Authentication is required with permission `WRITE` and _ownership of the session._
+/// - All bytes of the archive are required to be received if its total length is given.
+/// - The archive must fit in the remaining disk quota of the user, if any.
+pub async fn commit_upload_session(
+    cx: State,
+    Auth(token): Auth<PERMISSION_UPLOAD_SESSION>,
+    Path(id): Path<String>,
+) -> Result<(), Error> {
+    owned_upload_session(&cx, &token, &id).await?;
+    let (info, file) = cx.uploads.take(&id).await?;
+    let quota = cx.reserve_quota(&info.owner, 0).await?;
+    let group = Some(user::Group::Singular(info.owner));
+    let (reader, limit) = limited_reader(&cx, file);
+    let reader = tarball_reader(&info.content_type, reader)
+        .await
+        .map_err(|err| limit.map_err(err))?;
+
+    cx.add_func(
+        info.key.as_ref(),
+        group,
+        &mut tokio_tar::Archive::new(reader),
+        quota,
+    )
+    .await
+    .map_err(|err| limit.map_err(err))?;
+    Ok(())
+}
+
+/// Aborts an upload session, removing the received bytes.
+///
+/// # Request
+///
+/// - Authentication is required with permission `WRITE` and _ownership of the session._
+pub async fn remove_upload_session(
+    cx: State,
+    Auth(token): Auth<PERMISSION_UPLOAD_SESSION>,
+    Path(id): Path<String>,
+) -> Result<(), Error> {
+    owned_upload_session(&cx, &token, &id).await?;
+    cx.uploads.remove(&id).await?;
     Ok(())
 }
 
+#[derive(Deserialize)]
+pub struct ReplaceContentsQuery {
+    /// Whether to restart the function if it is running.
+    #[serde(default)]
+    pub restart: bool,
+}
+
+#[derive(Serialize)]
+pub struct ReplaceContentsResponse {
+    /// Whether the function was restarted with the new contents.
+    pub restarted: bool,
+    /// Whether the function is still running with the old contents thus requires a redeploy.
+    pub redeploy_required: bool,
+}
+
+const PERMISSION_REPLACE_CONTENTS: u32 = PermissionFlags::WRITE.bits();
+pub(crate) const PATH_REPLACE_CONTENTS: &str = "/api/contents/{key}";
+
+/// Replaces contents of a function by uploading a tarball, leaving its metadata and configuration
+/// untouched.
+///
+/// # Request
+///
+/// - Authentication is required with permission `WRITE` and _the group requirement by the function._
+/// - Body is required to receive a tarball, the same as [`upload`].
+/// - Query parameter `restart` for restarting the function if it is running.
+/// - The archive must fit in the remaining disk quota of the user owning the function, if any,
+///   with the current contents deducted.
+///
+/// # Response
+///
+/// - Responsed with JSON body [`ReplaceContentsResponse`].
+pub async fn replace_contents(
+    cx: State,
+    Auth(token): Auth<PERMISSION_REPLACE_CONTENTS>,
+    ContentType(ty): ContentType,
+    Path(key): Path<func::OwnedKey>,
+    Query(ReplaceContentsQuery { restart }): Query<ReplaceContentsQuery>,
+    body: Body,
+) -> Result<Json<ReplaceContentsResponse>, Error> {
+    authorize_group(&cx, &token, key.as_ref())?;
+
+    let mut quota = reserve_replace_quota(&cx, key.as_ref()).await?;
+    let (reader, limit) = limited_body_reader(&cx, body);
+    let reader = tarball_reader(&ty, reader)
+        .await
+        .map_err(|err| limit.map_err(err))?;
+    let permit = cx.upload_permit().await;
+    // contents are swapped only after unpacked and built entirely
+    let staging = tokio::time::timeout(
+        cx.upload_timeout,
+        cx.funcs.stage_contents(
+            key.as_ref(),
+            &mut tokio_tar::Archive::new(reader),
+            |bytes| quota.as_ref().is_none_or(|quota| quota.charge(bytes)),
+        ),
+    )
+    .await
+    .map_err(|_| Error::UploadTimeout)?
+    .map_err(|err| limit.map_err(quota_error(quota.as_ref(), err)))?;
+    drop(permit);
+    let built = match cx.build_fn(key.as_ref(), &staging).await {
+        Ok(()) => match &quota {
+            Some(quota) => cx.charge_build(&staging, quota).await,
+            None => Ok(()),
+        },
+        Err(err) => Err(err),
+    };
+    if let Err(err) = built {
+        cx.funcs.discard_staged_contents(key.as_ref()).await;
+        return Err(err);
+    }
+    cx.funcs.swap_staged_contents(key.as_ref()).await?;
+    if let Some(quota) = &mut quota {
+        quota.commit();
+    }
+
+    let running = cx.is_running(key.as_ref());
+    if running && restart {
+        cx.restart_fn(key.as_ref()).await?;
+    } else if running {
+        tracing::warn!("contents of running function {key} replaced, redeploy is required");
+    }
+
+    Ok(Json(ReplaceContentsResponse {
+        restarted: running && restart,
+        redeploy_required: running && !restart,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct GetQuery {
+    /// Whether to report the key of the version the requested key or alias resolves to.
+    #[serde(default)]
+    pub resolve: bool,
+}
+
+#[derive(Serialize)]
+pub struct GetResponse {
+    #[serde(flatten)]
+    pub function: func::Function,
+    /// Key of the version the requested key or alias resolves to, in form of `name@version`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolved: Option<String>,
+}
+
 const PERMISSION_GET: u32 = PermissionFlags::READ.bits();
 pub(crate) const PATH_GET: &str = "/api/get/{key}";
 
@@ -81,20 +663,210 @@ pub(crate) const PATH_GET: &str = "/api/get/{key}";
 /// # Request
 ///
 /// - Authentication is required with permission `READ`.
+/// - Query parameter `resolve` for reporting the version an alias resolves to.
 ///
 /// # Response
 ///
-/// - Responsed with json body [`func::Function`].
+/// - Responsed with json body [`GetResponse`].
 pub async fn get(
     cx: State,
     Auth(_): Auth<PERMISSION_GET>,
     Path(key): Path<func::OwnedKey>,
-) -> Result<Json<func::Function>, Error> {
-    cx.funcs
+    Query(GetQuery { resolve }): Query<GetQuery>,
+) -> Result<Json<GetResponse>, Error> {
+    let function = cx
+        .funcs
         .get(key.as_ref())
         .map(|f| f.read().clone())
-        .ok_or(Error::NotFound)
-        .map(Json)
+        .ok_or(Error::NotFound)?;
+    let resolved = resolve
+        .then(|| cx.funcs.canonical_key(key.as_ref()))
+        .flatten()
+        .map(|key| key.to_string());
+    Ok(Json(GetResponse { function, resolved }))
+}
+
+const PERMISSION_MY_FUNCTIONS: u32 = PermissionFlags::READ.bits();
+pub(crate) const PATH_MY_FUNCTIONS: &str = "/api/my-functions";
+
+/// Lists functions owned by the requesting user, or all functions for root user.
+///
+/// # Request
+///
+/// - Authentication is required with permission `READ`.
+///
+/// # Response
+///
+/// - Responsed with JSON array of [`func::Function`].
+pub async fn my_functions(
+    cx: State,
+    Auth(token): Auth<PERMISSION_MY_FUNCTIONS>,
+) -> Result<Json<Vec<func::Function>>, Error> {
+    if cx.users.is_root(&token) {
+        return Ok(Json(cx.funcs.functions_by_group(None)));
+    }
+    let user = cx.users.user_name(&token).ok_or(Error::Unauthorized)?;
+    Ok(Json(
+        cx.funcs
+            .functions_by_group(Some(&user::Group::Singular(user))),
+    ))
+}
+
+/// Default count of functions in a page of [`list`].
+const LIST_DEFAULT_LIMIT: usize = 100;
+/// Maximum count of functions in a page of [`list`].
+const LIST_MAX_LIMIT: usize = 1000;
+
+#[derive(Deserialize)]
+pub struct ListQuery {
+    /// Count of functions to skip.
+    #[serde(default)]
+    pub offset: usize,
+    /// Maximum count of functions to respond, capped at [`LIST_MAX_LIMIT`].
+    #[serde(default = "list_default_limit")]
+    pub limit: usize,
+    /// Prefix of names of functions to list.
+    #[serde(default)]
+    pub prefix: Option<String>,
+}
+
+#[inline]
+const fn list_default_limit() -> usize {
+    LIST_DEFAULT_LIMIT
+}
+
+#[derive(Serialize)]
+pub struct ListResponse {
+    /// Keys of functions in the page, in format `name@version`.
+    pub keys: Vec<String>,
+    /// Total count of matching functions.
+    pub total: usize,
+}
+
+const PERMISSION_LIST: u32 = PermissionFlags::READ.bits();
+pub(crate) const PATH_LIST: &str = "/api/functions";
+
+/// Lists keys of functions page by page, sorted by name then version.
+///
+/// # Request
+///
+/// - Authentication is required with permission `READ`.
+/// - Query parameters [`ListQuery`].
+///
+/// # Response
+///
+/// - Responsed with JSON body [`ListResponse`].
+pub async fn list(
+    cx: State,
+    Auth(_): Auth<PERMISSION_LIST>,
+    Query(ListQuery {
+        offset,
+        limit,
+        prefix,
+    }): Query<ListQuery>,
+) -> Json<ListResponse> {
+    let (keys, total) = cx
+        .funcs
+        .list_page(prefix.as_deref(), offset, limit.min(LIST_MAX_LIMIT));
+    Json(ListResponse {
+        keys: keys.iter().map(ToString::to_string).collect(),
+        total,
+    })
+}
+
+#[derive(Serialize)]
+pub struct VersionEntry {
+    pub version: String,
+    /// Aliases pointing to this version, sorted.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+}
+
+const PERMISSION_VERSIONS: u32 = PermissionFlags::READ.bits();
+pub(crate) const PATH_VERSIONS: &str = "/api/versions/{name}";
+
+/// Lists versions of functions with given name, along with the aliases pointing to them.
+///
+/// # Request
+///
+/// - Authentication is required with permission `READ`.
+///
+/// # Response
+///
+/// - Responsed with JSON array of [`VersionEntry`] sorted by versions.
+pub async fn versions(
+    cx: State,
+    Auth(_): Auth<PERMISSION_VERSIONS>,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<VersionEntry>>, Error> {
+    let entries: Vec<_> = cx
+        .funcs
+        .versions(&name)
+        .into_iter()
+        .filter_map(|version| {
+            // skips versions removed meanwhile
+            let aliases = cx.funcs.with_function(
+                func::Key {
+                    name: &name,
+                    version: &version,
+                },
+                |func| func.meta.version_aliases.iter().cloned().collect(),
+            )?;
+            Some(VersionEntry { version, aliases })
+        })
+        .collect();
+    if entries.is_empty() {
+        return Err(Error::NotFound);
+    }
+    Ok(Json(entries))
+}
+
+const PERMISSION_RAW_CONFIG: u32 = PermissionFlags::READ.bits();
+pub(crate) const PATH_RAW_CONFIG: &str = "/api/raw-config/{key}";
+
+/// Retrieves the raw configuration file of a function verbatim.
+///
+/// # Request
+///
+/// - Authentication is required with permission `READ`.
+///
+/// # Response
+///
+/// - Responsed with the `config.json` file of the function, or serialized [`func::Config`] if
+///   the file is not written yet.
+pub async fn raw_config(
+    cx: State,
+    Auth(_): Auth<PERMISSION_RAW_CONFIG>,
+    Path(key): Path<func::OwnedKey>,
+) -> Result<impl IntoResponse, Error> {
+    let raw = cx.funcs.read_raw_config(key.as_ref()).await?;
+    Ok(([(header::CONTENT_TYPE, "application/json")], raw))
+}
+
+/// Mode of redeploying a running function after its configuration is overridden.
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Redeploy {
+    /// Keeps the running instance, which is stale until redeployed manually.
+    #[default]
+    False,
+    /// Drains the running instance then restarts it with the new configuration.
+    Drain,
+}
+
+#[derive(Deserialize)]
+pub struct OverrideConfigQuery {
+    #[serde(default)]
+    pub redeploy: Redeploy,
+}
+
+#[derive(Serialize)]
+pub struct OverrideConfigResponse {
+    /// Whether the function was redeployed with the new configuration.
+    pub redeployed: bool,
+    /// Whether the function is still running with the old configuration thus requires a
+    /// redeploy.
+    pub redeploy_required: bool,
 }
 
 const PERMISSION_OVERRIDE_CONFIG: u32 = PermissionFlags::WRITE.bits();
@@ -106,32 +878,158 @@ pub(crate) const PATH_OVERRIDE_CONFIG: &str = "/api/override/{key}";
 ///
 /// - Authentication is required with permission `WRITE` and _the group requirement by the function._
 /// - Request body is JSON format of [`func::Config`].
+/// - Query parameter `redeploy=drain` for draining and restarting the function if it is running
+///   with a different configuration, returning once the new instance is ready.
+///
+/// # Response
+///
+/// - Responsed with JSON body [`OverrideConfigResponse`].
 pub async fn override_config(
     cx: State,
     Auth(token): Auth<PERMISSION_OVERRIDE_CONFIG>,
     Path(key): Path<func::OwnedKey>,
+    Query(OverrideConfigQuery { redeploy }): Query<OverrideConfigQuery>,
     Json(config): Json<func::Config>,
-) -> Result<(), Error> {
-    let func = cx.funcs.get(key.as_ref()).ok_or(Error::NotFound)?;
-    cx.users
-        .auth(&token, func.read().config.group.iter().map(Cow::Borrowed))
-        .then_some(())
-        .ok_or(Error::PermissionDenied)?;
+) -> Result<Json<OverrideConfigResponse>, Error> {
+    authorize_group(&cx, &token, key.as_ref())?;
     cx.funcs.modify_config(key.as_ref(), config)?;
+    redeploy_if_stale(&cx, key.as_ref(), redeploy)
+        .await
+        .map(Json)
+}
+
+/// Redeploys a function in given mode if it's running with a configuration different from the
+/// stored one.
+async fn redeploy_if_stale(
+    cx: &State,
+    key: func::Key<'_>,
+    redeploy: Redeploy,
+) -> Result<OverrideConfigResponse, Error> {
+    let stale = cx.running_snapshot(key).is_some_and(|running| {
+        cx.funcs
+            .get_snapshot(key)
+            .is_some_and(|stored| stored != running)
+    });
+    let redeploy = stale && redeploy == Redeploy::Drain;
+    if redeploy {
+        cx.redeploy_fn(key).await?;
+    }
+
+    Ok(OverrideConfigResponse {
+        redeployed: redeploy,
+        redeploy_required: stale && !redeploy,
+    })
+}
+
+const PERMISSION_PATCH_CONFIG: u32 = PermissionFlags::WRITE.bits();
+pub(crate) const PATH_PATCH_CONFIG: &str = "/api/config/{key}";
+
+/// Merges a partial update into configuration of a function, leaving absent fields untouched.
+///
+/// # Request
+///
+/// - Authentication is required with permission `WRITE` and _the group requirement by the function._
+/// - Request body is JSON format of [`func::ConfigPatch`].
+/// - Query parameter `redeploy` is the same as [`override_config`].
+///
+/// # Response
+///
+/// - Responsed with JSON body [`OverrideConfigResponse`].
+pub async fn patch_config(
+    cx: State,
+    Auth(token): Auth<PERMISSION_PATCH_CONFIG>,
+    Path(key): Path<func::OwnedKey>,
+    Query(OverrideConfigQuery { redeploy }): Query<OverrideConfigQuery>,
+    Json(patch): Json<func::ConfigPatch>,
+) -> Result<Json<OverrideConfigResponse>, Error> {
+    authorize_group(&cx, &token, key.as_ref())?;
+    cx.funcs.patch_config(key.as_ref(), patch)?;
+    redeploy_if_stale(&cx, key.as_ref(), redeploy)
+        .await
+        .map(Json)
+}
+
+#[derive(Deserialize)]
+pub struct RenameRequest {
+    /// New name of the function.
+    pub to_name: String,
+    /// New version of the function.
+    pub to_version: String,
+}
+
+const PERMISSION_RENAME: u32 = PermissionFlags::WRITE.bits();
+pub(crate) const PATH_RENAME: &str = "/api/rename/{key}";
+
+/// Renames a function to another key, keeping its aliases under the new name.
+///
+/// # Request
+///
+/// - Authentication is required with permission `WRITE` and _the group requirement by the function._
+/// - Request body is JSON format of [`RenameRequest`].
+/// - The function must not be running.
+pub async fn rename(
+    cx: State,
+    Auth(token): Auth<PERMISSION_RENAME>,
+    Path(key): Path<func::OwnedKey>,
+    Json(RenameRequest {
+        to_name,
+        to_version,
+    }): Json<RenameRequest>,
+) -> Result<(), Error> {
+    validate_key_param(&cx, &to_name)?;
+    validate_key_param(&cx, &to_version)?;
+
+    authorize_group(&cx, &token, key.as_ref())?;
+    let key = cx
+        .funcs
+        .canonical_key(key.as_ref())
+        .ok_or_else(|| Error::FunctionNotFound(key))?;
+    cx.funcs
+        .rename(
+            key.as_ref(),
+            func::Key {
+                name: &to_name,
+                version: &to_version,
+            },
+            |key| cx.is_instance_running(key),
+        )
+        .await
+        .map_err(|err| match err {
+            func::ManagerError::Running => Error::RenamingRunning(key.clone()),
+            err => err.into(),
+        })?;
+    // failures of the old key are never relevant to the new one
+    cx.failed.remove_sync(&key);
     Ok(())
 }
 
 #[derive(Deserialize)]
 pub struct AliasRequest {
-    /// `Some` for alias addition or modification;
-    /// `None` for removals.
+    /// How the alias is applied, defaulting to [`AliasAction::Set`].
+    #[serde(default)]
+    pub action: AliasAction,
+    /// Alias to be applied, which is required unless setting.
+    ///
+    /// `None` for removing all aliases when setting.
     pub alias: Option<String>,
 }
 
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AliasAction {
+    /// Replaces all aliases with the given one.
+    #[default]
+    Set,
+    /// Adds the alias, leaving other aliases untouched.
+    Add,
+    /// Removes the alias, leaving other aliases untouched.
+    Remove,
+}
+
 const PERMISSION_ALIAS: u32 = PermissionFlags::WRITE.bits();
 pub(crate) const PATH_ALIAS: &str = "/api/alias/{key}";
 
-/// Overrides alias of a function.
+/// Adds, removes or overrides aliases of a function.
 ///
 /// # Request
 ///
@@ -141,18 +1039,21 @@ pub async fn alias(
     cx: State,
     Auth(token): Auth<PERMISSION_ALIAS>,
     Path(key): Path<func::OwnedKey>,
-    Json(AliasRequest { alias }): Json<AliasRequest>,
+    Json(AliasRequest { action, alias }): Json<AliasRequest>,
 ) -> Result<(), Error> {
     if let Some(alias) = &alias {
-        validate_key_param(alias)?;
+        validate_key_param(&cx, alias)?;
     }
+    let modification = match (action, alias) {
+        (AliasAction::Set, alias) => func::AliasModification::Set(alias),
+        (AliasAction::Add, Some(alias)) => func::AliasModification::Add(alias),
+        (AliasAction::Remove, Some(alias)) => func::AliasModification::Remove(alias),
+        (AliasAction::Add, None) => return Err(Error::MissingAlias("add")),
+        (AliasAction::Remove, None) => return Err(Error::MissingAlias("remove")),
+    };
 
-    let func = cx.funcs.get(key.as_ref()).ok_or(Error::NotFound)?;
-    cx.users
-        .auth(&token, func.read().config.group.iter().map(Cow::Borrowed))
-        .then_some(())
-        .ok_or(Error::PermissionDenied)?;
-    cx.funcs.modify_alias(key.as_ref(), alias)?;
+    authorize_group(&cx, &token, key.as_ref())?;
+    cx.funcs.modify_alias(key.as_ref(), modification)?;
     Ok(())
 }
 
@@ -169,11 +1070,7 @@ pub async fn remove(
     Auth(token): Auth<PERMISSION_REMOVE>,
     Path(key): Path<func::OwnedKey>,
 ) -> Result<(), Error> {
-    let func = cx.funcs.get(key.as_ref()).ok_or(Error::NotFound)?;
-    cx.users
-        .auth(&token, func.read().config.group.iter().map(Cow::Borrowed))
-        .then_some(())
-        .ok_or(Error::PermissionDenied)?;
+    authorize_group(&cx, &token, key.as_ref())?;
     cx.funcs.remove_func(key.as_ref()).await?;
     Ok(())
 }
@@ -191,14 +1088,129 @@ pub async fn deploy(
     Auth(token): Auth<PERMISSION_DEPLOY>,
     Path(key): Path<func::OwnedKey>,
 ) -> Result<(), Error> {
-    let func = cx.funcs.get(key.as_ref()).ok_or(Error::NotFound)?;
-    cx.users
-        .auth(&token, func.read().config.group.iter().map(Cow::Borrowed))
-        .then_some(())
-        .ok_or(Error::PermissionDenied)?;
+    authorize_group(&cx, &token, key.as_ref())?;
     cx.start_fn(key.as_ref()).await
 }
 
+#[derive(Serialize)]
+pub struct PrewarmResponse {
+    /// Private authority of the instance for testing it directly, which is the one of the first
+    /// replica.
+    ///
+    /// For network-isolated functions this is the path to the Unix socket prefixed with `unix:`.
+    pub authority: String,
+    /// Private authorities of all replicas, in the same form as [`Self::authority`].
+    pub replicas: Vec<String>,
+}
+
+const PERMISSION_PREWARM: u32 = PermissionFlags::EXECUTE.bits();
+pub(crate) const PATH_PREWARM: &str = "/api/prewarm/{key}";
+
+/// Starts a function without routing public traffic to it.
+///
+/// The instance is ready for connections once responded.
+///
+/// # Request
+///
+/// - Authentication is required with permission `EXECUTE` and _the group requirement by the function._
+///
+/// # Response
+///
+/// - Responsed with JSON body [`PrewarmResponse`].
+pub async fn prewarm(
+    cx: State,
+    Auth(token): Auth<PERMISSION_PREWARM>,
+    Path(key): Path<func::OwnedKey>,
+) -> Result<Json<PrewarmResponse>, Error> {
+    authorize_group(&cx, &token, key.as_ref())?;
+
+    let (instance_key, pool) = cx.spawn_fn(key.as_ref()).await?;
+    if let Err(err) = cx.wait_ready(&pool).await {
+        drop(cx.stop_fn(instance_key.as_ref()).await);
+        return Err(err);
+    }
+    let replicas = pool.endpoints();
+    Ok(Json(PrewarmResponse {
+        authority: replicas[0].clone(),
+        replicas,
+    }))
+}
+
+const PERMISSION_PROMOTE_INSTANCE: u32 = PermissionFlags::EXECUTE.bits();
+pub(crate) const PATH_PROMOTE_INSTANCE: &str = "/api/promote-instance/{key}";
+
+/// Routes public traffic to a function started through [`prewarm`].
+///
+/// # Request
+///
+/// - Authentication is required with permission `EXECUTE` and _the group requirement by the function._
+pub async fn promote_instance(
+    cx: State,
+    Auth(token): Auth<PERMISSION_PROMOTE_INSTANCE>,
+    Path(key): Path<func::OwnedKey>,
+) -> Result<(), Error> {
+    authorize_group(&cx, &token, key.as_ref())?;
+    cx.route_fn(key.as_ref())
+}
+
+#[derive(Deserialize)]
+pub struct RouteWeightRequest {
+    /// Host prefix to route, in form of `version.name`.
+    ///
+    /// This must be the host prefix of one of the targets.
+    pub host: String,
+    /// Running functions to distribute public traffic to.
+    pub weights: Vec<RouteWeight>,
+}
+
+#[derive(Deserialize)]
+pub struct RouteWeight {
+    pub key: func::OwnedKey,
+    /// Relative weight of traffic routed to this function.
+    pub weight: u32,
+}
+
+const PERMISSION_ROUTE_WEIGHT: u32 = PermissionFlags::EXECUTE.bits();
+pub(crate) const PATH_ROUTE_WEIGHT: &str = "/api/route-weight";
+
+/// Distributes public traffic to a host prefix across running functions weighted-randomly.
+///
+/// Traffic is distributed evenly if all weights are zero. Stopped functions are removed from the
+/// route, and the route to the host prefix of a function is reset once it's started or promoted.
+///
+/// # Request
+///
+/// - Authentication is required with permission `EXECUTE` and _the group requirement by each
+///   target function._
+/// - Request body is JSON form of [`RouteWeightRequest`].
+pub async fn route_weight(
+    cx: State,
+    Auth(token): Auth<PERMISSION_ROUTE_WEIGHT>,
+    Json(req): Json<RouteWeightRequest>,
+) -> Result<(), Error> {
+    if req.weights.is_empty() {
+        return Err(Error::InvalidRoute("no target specified"));
+    }
+    if !req
+        .weights
+        .iter()
+        .any(|w| w.key.as_ref().to_host_prefix() == req.host)
+    {
+        return Err(Error::InvalidRoute(
+            "host prefix does not belong to any target",
+        ));
+    }
+
+    let mut targets = Vec::with_capacity(req.weights.len());
+    for RouteWeight { key, weight } in req.weights {
+        authorize_group(&cx, &token, key.as_ref())?;
+        let (key, pool) = cx.pool(key.as_ref()).ok_or(Error::FunctionNotRunning)?;
+        targets.push(proxy::Target { key, pool, weight });
+    }
+    cx.set_route(req.host, proxy::Route::new(targets));
+    Ok(())
+}
+
 const PERMISSION_KILL: u32 = PermissionFlags::EXECUTE.bits();
 pub(crate) const PATH_KILL: &str = "/api/kill/{key}";
 
@@ -212,27 +1224,423 @@ pub async fn kill(
     Auth(token): Auth<PERMISSION_KILL>,
     Path(key): Path<func::OwnedKey>,
 ) -> Result<(), Error> {
-    let func = cx.funcs.get(key.as_ref()).ok_or(Error::NotFound)?;
-    cx.users
-        .auth(&token, func.read().config.group.iter().map(Cow::Borrowed))
-        .then_some(())
-        .ok_or(Error::PermissionDenied)?;
+    authorize_group(&cx, &token, key.as_ref())?;
     cx.stop_fn(key.as_ref()).await
 }
 
 #[derive(Serialize)]
 pub struct StatusResponse {
     pub running: bool,
+    /// Snapshot of the function the running instance was spawned with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub running_config: Option<func::FunctionSnapshot>,
+    /// Whether the stored configuration differs from the running one thus requires a redeploy.
+    pub config_drift: bool,
+    /// Count of HTTP requests awaiting responses and open websocket connections of the running
+    /// instance.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<usize>,
+    /// Whether the running instance passes its health checks, absent without health checks.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub healthy: Option<bool>,
+    /// Instant of the last completed health check of the running instance.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "yfass::rfc3339::option::serialize"
+    )]
+    pub last_check: Option<UtcDateTime>,
 }
 
 const PERMISSION_STATUS: u32 = PermissionFlags::READ.bits();
 pub(crate) const PATH_STATUS: &str = "/api/status/{key}";
 
+/// Retrieves running status of a function.
+///
+/// # Request
+///
+/// - Authentication is required with permission `READ`.
+///
+/// # Response
+///
+/// - Responsed with JSON body [`StatusResponse`].
 pub async fn status(
     cx: State,
     Auth(_): Auth<PERMISSION_STATUS>,
     Path(key): Path<func::OwnedKey>,
 ) -> Result<Json<StatusResponse>, Error> {
     let running = cx.is_running(key.as_ref());
-    Ok(Json(StatusResponse { running }))
+    let running_config = cx.running_snapshot(key.as_ref());
+    let pool = cx.pool(key.as_ref()).map(|(_, pool)| pool);
+    let config_drift = running_config.as_ref().is_some_and(|running| {
+        cx.funcs
+            .get_snapshot(key.as_ref())
+            .is_some_and(|stored| &stored != running)
+    });
+    Ok(Json(StatusResponse {
+        running,
+        running_config,
+        config_drift,
+        concurrency: cx.concurrency(key.as_ref()),
+        healthy: pool.as_ref().and_then(proxy::Pool::is_healthy),
+        last_check: pool.as_ref().and_then(proxy::Pool::last_health_check),
+    }))
+}
+
+#[derive(Serialize)]
+pub struct HealthResponse {
+    /// Count of functions, with each version counted separately.
+    pub functions: usize,
+    /// Count of running instances.
+    pub running: usize,
+    /// Keys of functions whose last deploy failed their readiness probe, or which exited on
+    /// their own.
+    pub failed: Vec<String>,
+    /// Count of restarts of functions performed by the platform.
+    pub restarts: u64,
+    /// Outcomes of saving data into the storage.
+    pub persistence: crate::SaveState,
+}
+
+const PERMISSION_HEALTH: u32 = PermissionFlags::READ.bits();
+pub(crate) const PATH_HEALTH: &str = "/api/health";
+
+/// Retrieves a summary of health of all functions.
+///
+/// # Request
+///
+/// - Authentication is required with permission `READ`.
+///
+/// # Response
+///
+/// - Responsed with JSON body [`HealthResponse`].
+pub async fn health(cx: State, Auth(_): Auth<PERMISSION_HEALTH>) -> Json<HealthResponse> {
+    let mut failed = Vec::new();
+    cx.failed.iter_sync(|key| {
+        failed.push(key.to_string());
+        true
+    });
+    failed.sort_unstable();
+    Json(HealthResponse {
+        functions: cx.funcs.len(),
+        running: cx.handles.len(),
+        failed,
+        restarts: cx.restarts.load(Ordering::Relaxed),
+        persistence: *cx.save_state.lock(),
+    })
+}
+
+const PERMISSION_LOGS: u32 = PermissionFlags::READ.bits();
+pub(crate) const PATH_LOGS: &str = "/api/logs/{key}";
+
+const CONTENT_TYPE_NDJSON: &str = "application/x-ndjson";
+
+#[derive(Deserialize)]
+pub struct LogsQuery {
+    /// Maximum count of the most recent lines to be responsed.
+    pub lines: Option<usize>,
+}
+
+/// Retrieves captured outputs of a function.
+///
+/// Outputs of functions with the `file` log sink are read from the most recent log file, even if
+/// the function is not running. Outputs of other functions are read from the in-memory buffer of
+/// the running instance.
+///
+/// # Request
+///
+/// - Authentication is required with permission `READ` and _the group requirement by the function._
+/// - Header `Accept: application/x-ndjson` for lines as JSON objects of [`yfass::logs::LogLine`].
+/// - Query parameter `lines` for the maximum count of the most recent lines to be responsed.
+///
+/// # Response
+///
+/// - Responsed with captured lines in plain text, or JSON Lines if requested.
+pub async fn logs(
+    cx: State,
+    Auth(token): Auth<PERMISSION_LOGS>,
+    Path(key): Path<func::OwnedKey>,
+    Query(LogsQuery { lines: count }): Query<LogsQuery>,
+    headers: header::HeaderMap,
+) -> Result<impl IntoResponse, Error> {
+    authorize_group(&cx, &token, key.as_ref())?;
+
+    let sink = cx
+        .funcs
+        .with_function(key.as_ref(), |func| func.config.sandbox.log_sink)
+        .flatten();
+    let lines = if sink == Some(LogSink::File) {
+        let key = cx
+            .funcs
+            .canonical_key(key.as_ref())
+            .ok_or_else(|| Error::FunctionNotFound(key))?;
+        yfass::logs::tail_files(
+            &cx.funcs.logs_path(key.as_ref()),
+            count.unwrap_or(cx.log_buffer_lines),
+        )
+        .await?
+    } else {
+        // lines of replicas are interleaved by the instants they're captured at
+        let mut lines: Vec<_> = cx
+            .logs(key.as_ref())
+            .ok_or(Error::FunctionNotRunning)?
+            .iter()
+            .flat_map(|logs| logs.snapshot())
+            .collect();
+        lines.sort_by_key(|line| line.ts);
+        if let Some(count) = count {
+            lines.drain(..lines.len().saturating_sub(count));
+        }
+        lines
+    };
+    let ndjson = headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|v| v.split(';').next().unwrap_or_default().trim() == CONTENT_TYPE_NDJSON);
+
+    let mut body = Vec::new();
+    for line in lines {
+        if ndjson {
+            serde_json::to_writer(&mut body, &line).expect("failed to serialize log line");
+        } else {
+            body.extend_from_slice(line.line.as_bytes());
+        }
+        body.push(b'\n');
+    }
+
+    let ty = if ndjson {
+        CONTENT_TYPE_NDJSON
+    } else {
+        "text/plain; charset=utf-8"
+    };
+    Ok(([(header::CONTENT_TYPE, ty)], body))
+}
+
+/// Sniffs content type of a file from its extension, or leading bytes if the extension is
+/// unrecognized.
+fn sniff_content_type(path: &std::path::Path, head: &[u8]) -> &'static str {
+    const CONTENT_TYPE_TEXT: &str = "text/plain; charset=utf-8";
+    const MAGICS: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF8", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"\x1f\x8b", CONTENT_TYPE_GZIP),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\0asm", "application/wasm"),
+        (b"\x7fELF", "application/x-executable"),
+    ];
+
+    let by_ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase)
+        .and_then(|ext| {
+            Some(match ext.as_str() {
+                "html" | "htm" => "text/html; charset=utf-8",
+                "css" => "text/css; charset=utf-8",
+                "js" | "mjs" => "text/javascript; charset=utf-8",
+                "json" => "application/json",
+                "txt" | "md" | "toml" | "yaml" | "yml" | "sh" | "env" => CONTENT_TYPE_TEXT,
+                "xml" => "application/xml",
+                "svg" => "image/svg+xml",
+                "png" => "image/png",
+                "jpg" | "jpeg" => "image/jpeg",
+                "gif" => "image/gif",
+                "webp" => "image/webp",
+                "ico" => "image/x-icon",
+                "pdf" => "application/pdf",
+                "wasm" => "application/wasm",
+                "tar" => CONTENT_TYPE_TAR,
+                "gz" | "tgz" => CONTENT_TYPE_GZIP,
+                "zip" => "application/zip",
+                "jar" => "application/java-archive",
+                _ => return None,
+            })
+        });
+    if let Some(ty) = by_ext {
+        return ty;
+    }
+
+    if let Some((_, ty)) = MAGICS.iter().find(|(magic, _)| head.starts_with(magic)) {
+        ty
+    } else if head.starts_with(b"RIFF") && head.get(8..12) == Some(b"WEBP") {
+        "image/webp"
+    } else if !head.contains(&0)
+        // the leading bytes may end in the middle of a character
+        && std::str::from_utf8(head).map_or_else(|err| err.error_len().is_none(), |_| true)
+    {
+        CONTENT_TYPE_TEXT
+    } else {
+        CONTENT_TYPE_OCTET_STREAM
+    }
+}
+
+const PERMISSION_FILE: u32 = PermissionFlags::READ.bits();
+pub(crate) const PATH_FILE: &str = "/api/file/{key}/{*path}";
+
+/// Retrieves a single file from contents of a function.
+///
+/// # Request
+///
+/// - Authentication is required with permission `READ` and _the group requirement by the function._
+/// - The path is relative to the contents directory, and is required to stay within it.
+///
+/// # Response
+///
+/// - Responsed with the file, with its content type sniffed from the extension or leading bytes.
+pub async fn file(
+    cx: State,
+    Auth(token): Auth<PERMISSION_FILE>,
+    Path((key, path)): Path<(func::OwnedKey, String)>,
+) -> Result<impl IntoResponse, Error> {
+    const LEN_SNIFF: u64 = 512;
+
+    authorize_group(&cx, &token, key.as_ref())?;
+
+    let rel = std::path::Path::new(&path);
+    if !rel
+        .components()
+        .all(|c| matches!(c, std::path::Component::Normal(_)))
+    {
+        return Err(Error::InvalidFilePath);
+    }
+    let key = cx
+        .funcs
+        .canonical_key(key.as_ref())
+        .ok_or(Error::NotFound)?;
+    let root = tokio::fs::canonicalize(cx.funcs.contents_path(key.as_ref())).await?;
+    let full = match tokio::fs::canonicalize(root.join(rel)).await {
+        Ok(full) => full,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Err(Error::NotFound),
+        Err(err) => return Err(err.into()),
+    };
+    // symlinks may point outside of the contents
+    if !full.starts_with(&root) {
+        return Err(Error::InvalidFilePath);
+    }
+
+    let mut file = tokio::fs::File::open(&full).await?;
+    let metadata = file.metadata().await?;
+    if !metadata.is_file() {
+        return Err(Error::NotFound);
+    }
+    let mut head = Vec::new();
+    (&mut file).take(LEN_SNIFF).read_to_end(&mut head).await?;
+    let ty = sniff_content_type(&full, &head);
+
+    let reader = std::io::Cursor::new(head).chain(file);
+    Ok((
+        [
+            (header::CONTENT_TYPE, ty.to_owned()),
+            (header::CONTENT_LENGTH, metadata.len().to_string()),
+            // contents are untrusted, never let them run as pages of the platform
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_owned()),
+            (header::CONTENT_SECURITY_POLICY, "sandbox".to_owned()),
+        ],
+        Body::from_stream(tokio_util::io::ReaderStream::new(reader)),
+    ))
+}
+
+/// Size of the in-memory pipe between the archive builder and the response body of [`download`].
+const DOWNLOAD_BUFFER_SIZE: usize = 64 * 1024;
+
+const PERMISSION_DOWNLOAD: u32 = PermissionFlags::READ.bits();
+pub(crate) const PATH_DOWNLOAD: &str = "/api/download/{key}";
+
+/// Downloads contents of a function as a tarball.
+///
+/// # Request
+///
+/// - Authentication is required with permission `READ` and _the group requirement by the function._
+/// - The tarball is compressed if `gzip` is accepted through `Accept-Encoding`.
+///
+/// # Response
+///
+/// - Responsed with a streamed tarball of the contents directory, in the same layout as uploaded.
+pub async fn download(
+    cx: State,
+    Auth(token): Auth<PERMISSION_DOWNLOAD>,
+    Path(key): Path<func::OwnedKey>,
+    headers: header::HeaderMap,
+) -> Result<impl IntoResponse, Error> {
+    authorize_group(&cx, &token, key.as_ref())?;
+    let key = cx
+        .funcs
+        .canonical_key(key.as_ref())
+        .ok_or(Error::NotFound)?;
+    let path = cx.funcs.contents_path(key.as_ref());
+    if !tokio::fs::try_exists(&path).await? {
+        return Err(Error::NotFound);
+    }
+
+    let gzip = accepts_gzip(&headers);
+    let disposition = format!("attachment; filename=\"{key}.tar\"");
+    let (writer, reader) = tokio::io::duplex(DOWNLOAD_BUFFER_SIZE);
+    tokio::spawn(async move {
+        let result = if gzip {
+            write_contents_tarball(
+                &path,
+                async_compression::tokio::write::GzipEncoder::new(writer),
+            )
+            .await
+        } else {
+            write_contents_tarball(&path, writer).await
+        };
+        // the response body ends up truncated on failures, which clients detect as corruption
+        if let Err(err) = result {
+            tracing::error!("failed to archive contents of function {key}: {err}");
+        }
+    });
+
+    let mut response = (
+        [
+            (header::CONTENT_TYPE, CONTENT_TYPE_TAR.to_owned()),
+            (header::CONTENT_DISPOSITION, disposition),
+            (header::VARY, header::ACCEPT_ENCODING.as_str().to_owned()),
+        ],
+        Body::from_stream(tokio_util::io::ReaderStream::new(reader)),
+    )
+        .into_response();
+    if gzip {
+        response.headers_mut().insert(
+            header::CONTENT_ENCODING,
+            header::HeaderValue::from_static("gzip"),
+        );
+    }
+    Ok(response)
+}
+
+/// Writes a tarball of given contents directory into the writer, shutting it down once finished.
+async fn write_contents_tarball<W>(path: &std::path::Path, writer: W) -> std::io::Result<()>
+where
+    W: AsyncWrite + Unpin + Send + Sync + 'static,
+{
+    let mut builder = tokio_tar::Builder::new(writer);
+    // symlinks are archived as is, so ones pointing outside of the contents never leak files
+    builder.follow_symlinks(false);
+    builder.append_dir_all(".", path).await?;
+    builder.into_inner().await?.shutdown().await
+}
+
+/// Checks whether `gzip` is accepted through `Accept-Encoding` headers, with a nonzero quality.
+fn accepts_gzip(headers: &header::HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|coding| {
+            let mut params = coding.split(';');
+            params
+                .next()
+                .is_some_and(|name| name.trim().eq_ignore_ascii_case("gzip"))
+                && params.all(|param| {
+                    param
+                        .trim()
+                        .strip_prefix("q=")
+                        .is_none_or(|q| q.trim().parse::<f32>().is_ok_and(|q| q > 0.0))
+                })
+        })
 }
@@ -1,2 +1,4 @@
+pub mod admin;
 pub mod func;
+pub mod probe;
 pub mod user;
@@ -0,0 +1,62 @@
+use std::sync::atomic::Ordering;
+
+use axum::{
+    extract::Request,
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse as _, Response},
+};
+
+use crate::{State, proxy};
+
+pub(crate) const PATH_HEALTHZ: &str = "/healthz";
+
+/// Liveness probe for orchestrators, succeeding as long as the process is up.
+///
+/// # Request
+///
+/// - No authentication is required.
+pub async fn healthz() -> StatusCode {
+    StatusCode::OK
+}
+
+pub(crate) const PATH_READYZ: &str = "/readyz";
+
+/// Readiness probe for orchestrators, succeeding once data is loaded from the storage and the
+/// listener is bound.
+///
+/// # Request
+///
+/// - No authentication is required.
+///
+/// # Response
+///
+/// - Responsed with `503 Service Unavailable` before getting ready, or once shutting down so
+///   load balancers drain the node.
+/// - Responsed with `503 Service Unavailable` as well once saving data into the storage keeps
+///   failing beyond `--readiness-max-save-failures` or `--readiness-max-failing-secs`.
+pub async fn readyz(cx: State) -> StatusCode {
+    if cx.ready.load(Ordering::Relaxed) && !cx.is_save_failing() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+/// Responds to probes addressed to the platform itself, ahead of the proxy and the management
+/// API.
+///
+/// Requests to functions are passed through untouched, so functions serving their own probes at
+/// the same paths stay reachable.
+pub async fn route_probes(cx: State, request: Request, next: Next) -> Response {
+    if matches!(*request.method(), Method::GET | Method::HEAD)
+        && !proxy::is_func_request(&cx, &request)
+    {
+        match request.uri().path() {
+            PATH_HEALTHZ => return healthz().await.into_response(),
+            PATH_READYZ => return readyz(cx).await.into_response(),
+            _ => {}
+        }
+    }
+    next.run(request).await
+}
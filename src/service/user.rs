@@ -1,11 +1,14 @@
 use std::borrow::Cow;
 
-use axum::{Json, extract::Path};
+use axum::{
+    Json,
+    extract::{Path, Query},
+};
 use serde::{Deserialize, Serialize};
-use time::Duration;
+use time::{Duration, UtcDateTime};
 use yfass::user::{self, User};
 
-use crate::{Auth, Error, PermissionFlags, State};
+use crate::{Auth, Error, PermissionFlags, State, Token};
 
 fn validate_username_param(name: &str) -> Result<(), Error> {
     if name.is_empty() {
@@ -14,7 +17,7 @@ fn validate_username_param(name: &str) -> Result<(), Error> {
     name.chars()
         .all(|c| c.is_ascii_alphabetic() || c.is_ascii_digit() || c == '-')
         .then_some(())
-        .ok_or(Error::InvalidKeyFormat)
+        .ok_or(Error::InvalidUsernameFormat)
 }
 
 #[derive(Serialize, Deserialize)]
@@ -22,12 +25,25 @@ pub struct ClientUser {
     pub name: String,
     #[serde(default)]
     pub groups: Box<[user::Group]>,
+    /// Disk quota of the user in bytes, unlimited if not specified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quota_bytes: Option<u64>,
+}
+
+/// The root user, which is never stored in the user manager.
+fn root_client() -> ClientUser {
+    ClientUser {
+        name: "root".to_owned(),
+        groups: Box::new([user::Group::Permission(user::Permission::Root)]),
+        quota_bytes: None,
+    }
 }
 
 fn client_from_ref(user: &User) -> ClientUser {
     ClientUser {
         name: user.name.clone(),
         groups: user.groups.iter().cloned().collect(),
+        quota_bytes: user.quota_bytes,
     }
 }
 
@@ -61,7 +77,8 @@ pub async fn add(
         .then_some(())
         .ok_or(Error::PermissionDenied)?;
 
-    let user = User::new(req.name.to_ascii_lowercase(), req.groups.into_iter());
+    let mut user = User::new(req.name.to_ascii_lowercase(), req.groups.into_iter());
+    user.quota_bytes = req.quota_bytes;
     cx.users.add(user)?;
     Ok(())
 }
@@ -99,10 +116,7 @@ pub async fn get(
     Auth(token): Auth<GET_PERMISSION>,
     name_optional: Option<Path<String>>,
 ) -> Result<Json<ClientUser>, Error> {
-    let root = ClientUser {
-        name: "root".to_owned(),
-        groups: Box::new([user::Group::Permission(user::Permission::Root)]),
-    };
+    let root = root_client();
 
     let val = cx.users.peek_from_token(&token, |this| {
         (
@@ -141,18 +155,78 @@ pub async fn get(
     .map(Json)
 }
 
+#[derive(Deserialize)]
+pub struct ListQuery {
+    /// Group in its full form like `custom:name`, for listing only members of it.
+    #[serde(default)]
+    pub group: Option<String>,
+}
+
+const LIST_PERMISSION: u32 = PermissionFlags::ADMIN.bits();
+pub(crate) const PATH_LIST: &str = "/api/user/list";
+
+/// Lists all users including the root user.
+///
+/// # Request
+///
+/// - Authentication is required with permission `ADMIN`.
+/// - Query parameters are in form of [`ListQuery`].
+///
+/// # Response
+///
+/// The response body is a JSON array of [`ClientUser`], sorted by names.
+pub async fn list(
+    cx: State,
+    Auth(_): Auth<LIST_PERMISSION>,
+    Query(query): Query<ListQuery>,
+) -> Result<Json<Vec<ClientUser>>, Error> {
+    let group: Option<user::Group> = query.group.as_deref().map(str::parse).transpose()?;
+    let mut users: Vec<_> = cx
+        .users
+        .list(|user| {
+            group
+                .as_ref()
+                .is_none_or(|group| user.is_in(group))
+                .then(|| client_from_ref(user))
+        })
+        .into_iter()
+        .flatten()
+        .collect();
+    let root = root_client();
+    if group.as_ref().is_none_or(|group| {
+        root.groups.contains(group) || *group == user::Group::Singular(root.name.clone())
+    }) {
+        users.push(root);
+    }
+    users.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+    Ok(Json(users))
+}
+
+/// Resolves token valid duration from given days, or the server-wide default.
 #[inline]
-const fn default_token_duration_days() -> u32 {
-    10
+fn token_duration(cx: &State, days: Option<u32>) -> Duration {
+    days.map_or(cx.default_token_duration, |days| {
+        Duration::days(days as i64)
+    })
 }
 
 #[derive(Deserialize)]
 pub struct RequestTokenRequest {
-    /// Token valid duration in **days.**
-    #[serde(default = "default_token_duration_days")]
-    pub duration: u32,
+    /// Token valid duration in **days,** at most [`user::MAX_TOKEN_DURATION`].
+    ///
+    /// Defaults to the server-wide default duration.
+    #[serde(default)]
+    pub duration: Option<u32>,
     /// Username of the account whose token is being allocated.
     pub user: String,
+    /// Label for telling the token apart from others, like `ci` or `laptop`.
+    #[serde(default)]
+    pub label: Option<String>,
+    /// Groups the token is restricted to, which must be a subset of groups of the user.
+    ///
+    /// Defaults to all groups of the user.
+    #[serde(default)]
+    pub scope: Option<Box<[user::Group]>>,
 }
 
 const REQUEST_TOKEN_PERMISSION: u32 = PermissionFlags::ADMIN.bits();
@@ -177,15 +251,119 @@ pub async fn request_token(
         .add_token(
             &req.user,
             &mut *cx.rng.lock(),
-            Duration::days(req.duration as i64),
+            token_duration(&cx, req.duration),
+            req.label,
+            req.scope.map(|scope| scope.into_iter().collect()),
         )
         .map_err(Into::into)
 }
 
+#[derive(Deserialize)]
+pub struct ChangeTokenDurationRequest {
+    /// The token to be extended.
+    pub token: String,
+    /// New valid duration in **days** from now.
+    ///
+    /// Defaults to the server-wide default duration.
+    #[serde(default)]
+    pub duration: Option<u32>,
+}
+
+const CHANGE_TOKEN_DURATION_PERMISSION: u32 = PermissionFlags::ADMIN.bits();
+pub(crate) const PATH_CHANGE_TOKEN_DURATION: &str = "/api/user/change-token-duration";
+
+/// Changes valid duration of an unexpired token.
+///
+/// # Request
+///
+/// - Authentication is required with permission `ADMIN` for checking **all users.**
+/// - Request body is JSON form of [`ChangeTokenDurationRequest`].
+pub async fn change_token_duration(
+    cx: State,
+    Auth(_): Auth<CHANGE_TOKEN_DURATION_PERMISSION>,
+    Json(req): Json<ChangeTokenDurationRequest>,
+) -> Result<(), Error> {
+    cx.users.extend_token(
+        &req.token,
+        UtcDateTime::now() + token_duration(&cx, req.duration),
+    )?;
+    Ok(())
+}
+
+const LIST_TOKENS_PERMISSION: u32 = PermissionFlags::empty().bits();
+pub(crate) const PATH_LIST_TOKENS: &str = "/api/user/tokens/{user}";
+
+/// Lists unexpired tokens of a user, exposing only prefixes of them.
+///
+/// # Request
+///
+/// - Authentication is required with permission `ADMIN` for checking **other users.**
+///
+/// # Response
+///
+/// The response body is a JSON array of [`user::TokenInfo`], sorted by expiry.
+pub async fn list_tokens(
+    cx: State,
+    Auth(token): Auth<LIST_TOKENS_PERMISSION>,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<user::TokenInfo>>, Error> {
+    let permitted = cx
+        .users
+        .peek_from_token(&token, |this| {
+            this.name == name || this.is_in(&user::Group::Permission(user::Permission::Admin))
+        })?
+        // root
+        .unwrap_or(true);
+    if !permitted {
+        return Err(Error::PermissionDenied);
+    }
+    Ok(Json(cx.users.list_tokens(&name)?))
+}
+
+#[derive(Deserialize)]
+pub struct RefreshTokenRequest {
+    /// Duration in **days** to extend the token by.
+    ///
+    /// Defaults to the server-wide default duration.
+    #[serde(default)]
+    pub duration: Option<u32>,
+}
+
+#[derive(Serialize)]
+pub struct RefreshTokenResponse {
+    /// New instant the token expires at.
+    #[serde(serialize_with = "yfass::rfc3339::serialize")]
+    pub expires_at: UtcDateTime,
+}
+
+pub(crate) const PATH_REFRESH_TOKEN: &str = "/api/user/token/refresh";
+
+/// Extends the unexpired token used for authentication.
+///
+/// # Request
+///
+/// - Authentication is required with the token to be refreshed, which is reported as not found
+///   if it's unknown or expired instead of being denied.
+/// - Request body is JSON form of [`RefreshTokenRequest`].
+///
+/// # Response
+///
+/// The response body is JSON form of [`RefreshTokenResponse`].
+pub async fn refresh_token(
+    cx: State,
+    Token(token): Token,
+    Json(req): Json<RefreshTokenRequest>,
+) -> Result<Json<RefreshTokenResponse>, Error> {
+    let expires_at = cx
+        .users
+        .refresh_token(&token, token_duration(&cx, req.duration))?;
+    Ok(Json(RefreshTokenResponse { expires_at }))
+}
+
 const MODIFY_PERMISSION: u32 = PermissionFlags::ADMIN.bits();
 pub(crate) const PATH_MODIFY: &str = "/api/user/modify";
 
-/// Modifies information (currently only group is supported) of a user.
+/// Modifies information (groups and the disk quota) of a user.
 ///
 /// # Request
 ///
@@ -212,6 +390,146 @@ pub async fn modify(
     cx.users
         .peek_mut(&user.name, |u| {
             u.groups = user.groups.into_iter().collect();
+            u.quota_bytes = user.quota_bytes;
         })?
         .ok_or(Error::ModifyRootUser)
 }
+
+const GROUPS_PERMISSION: u32 = PermissionFlags::ADMIN.bits();
+pub(crate) const PATH_GROUPS: &str = "/api/groups";
+
+/// Lists custom groups any user is in.
+///
+/// # Request
+///
+/// - Authentication is required with permission `ADMIN`.
+///
+/// # Response
+///
+/// The response body is a JSON array of names of the custom groups, sorted.
+pub async fn groups(cx: State, Auth(_): Auth<GROUPS_PERMISSION>) -> Json<Vec<String>> {
+    let mut groups: Vec<_> = cx.users.list_custom_groups().into_iter().collect();
+    groups.sort_unstable();
+    Json(groups)
+}
+
+const GROUP_MEMBERS_PERMISSION: u32 = PermissionFlags::ADMIN.bits();
+pub(crate) const PATH_GROUP_MEMBERS: &str = "/api/groups/{group}/members";
+
+/// Lists users in a group.
+///
+/// # Request
+///
+/// - Authentication is required with permission `ADMIN`.
+/// - The group is in its full form like `custom:name`.
+///
+/// # Response
+///
+/// The response body is a JSON array of names of the members, sorted. The root user is never
+/// listed.
+pub async fn group_members(
+    cx: State,
+    Auth(_): Auth<GROUP_MEMBERS_PERMISSION>,
+    Path(group): Path<String>,
+) -> Result<Json<Vec<String>>, Error> {
+    sorted_members(&cx, &group).map(Json)
+}
+
+fn sorted_members(cx: &State, group: &str) -> Result<Vec<String>, Error> {
+    let group: user::Group = group.parse()?;
+    let mut members = cx.users.members_of(&group);
+    members.sort_unstable();
+    Ok(members)
+}
+
+#[derive(Deserialize)]
+pub struct MembersQuery {
+    /// The group in its full form like `custom:name`.
+    pub group: String,
+}
+
+const MEMBERS_PERMISSION: u32 = PermissionFlags::ADMIN.bits();
+pub(crate) const PATH_MEMBERS: &str = "/api/group/members";
+
+/// Lists users in a group, with the group passed as a query parameter.
+///
+/// # Request
+///
+/// - Authentication is required with permission `ADMIN`.
+/// - Query parameters are in form of [`MembersQuery`].
+///
+/// # Response
+///
+/// The response body is a JSON array of names of the members, sorted. The root user is never
+/// listed.
+pub async fn members(
+    cx: State,
+    Auth(_): Auth<MEMBERS_PERMISSION>,
+    Query(query): Query<MembersQuery>,
+) -> Result<Json<Vec<String>>, Error> {
+    sorted_members(&cx, &query.group).map(Json)
+}
+
+const GROUP_ADD_PERMISSION: u32 = PermissionFlags::ADMIN.bits();
+pub(crate) const PATH_GROUP_ADD: &str = "/api/group/add/{group}";
+
+/// Registers a custom group.
+///
+/// # Request
+///
+/// - Authentication is required with permission `ADMIN`.
+/// - The group is the name of the custom group, without the `custom:` prefix.
+pub async fn add_group(
+    cx: State,
+    Auth(_): Auth<GROUP_ADD_PERMISSION>,
+    Path(group): Path<String>,
+) -> Result<(), Error> {
+    validate_username_param(&group)?;
+    cx.users.register_group(group).map_err(Into::into)
+}
+
+#[derive(Deserialize)]
+pub struct RemoveGroupQuery {
+    /// Whether to remove the group from users still in it, instead of refusing.
+    #[serde(default)]
+    pub cascade: bool,
+}
+
+const GROUP_REMOVE_PERMISSION: u32 = PermissionFlags::ADMIN.bits();
+pub(crate) const PATH_GROUP_REMOVE: &str = "/api/group/remove/{group}";
+
+/// Unregisters a custom group.
+///
+/// # Request
+///
+/// - Authentication is required with permission `ADMIN`.
+/// - The group is the name of the custom group, without the `custom:` prefix.
+/// - Query parameters are in form of [`RemoveGroupQuery`].
+pub async fn remove_group(
+    cx: State,
+    Auth(_): Auth<GROUP_REMOVE_PERMISSION>,
+    Path(group): Path<String>,
+    Query(query): Query<RemoveGroupQuery>,
+) -> Result<(), Error> {
+    cx.users
+        .unregister_group(&group, query.cascade)
+        .map_err(Into::into)
+}
+
+const GROUP_LIST_PERMISSION: u32 = PermissionFlags::ADMIN.bits();
+pub(crate) const PATH_GROUP_LIST: &str = "/api/group/list";
+
+/// Lists registered custom groups.
+///
+/// # Request
+///
+/// - Authentication is required with permission `ADMIN`.
+///
+/// # Response
+///
+/// The response body is a JSON array of names of the registered groups, sorted.
+pub async fn list_groups(cx: State, Auth(_): Auth<GROUP_LIST_PERMISSION>) -> Json<Vec<String>> {
+    let mut groups = cx.users.list_groups();
+    groups.sort_unstable();
+    Json(groups)
+}
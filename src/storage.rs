@@ -0,0 +1,145 @@
+//! Persistence backends of platform information.
+//!
+//! Information of functions and users are persisted as serialized blobs through [`Storage`],
+//! addressed by `/`-separated keys like `name@version/config.json`. Contents of functions are
+//! always unpacked to the local filesystem as they're mounted into sandboxes.
+
+use std::{
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+};
+
+/// Boxed future returned by [`Storage`] methods.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Abstraction of persistence backends storing blobs by keys.
+pub trait Storage: std::fmt::Debug + Send + Sync {
+    /// Reads the blob stored with given key, or `None` if it does not exist.
+    fn read<'a>(&'a self, key: &'a str) -> BoxFuture<'a, std::io::Result<Option<Vec<u8>>>>;
+
+    /// Writes a blob with given key, replacing the previous one atomically.
+    fn write<'a>(&'a self, key: &'a str, data: Vec<u8>) -> BoxFuture<'a, std::io::Result<()>>;
+
+    /// Removes the blob stored with given key, doing nothing if it does not exist.
+    fn remove<'a>(&'a self, key: &'a str) -> BoxFuture<'a, std::io::Result<()>>;
+
+    /// Lists names of directories directly under given directory, or the root if empty.
+    ///
+    /// Directories are prefixes of keys up to a `/`. Names starting with `.` are hidden.
+    fn list_dirs<'a>(&'a self, dir: &'a str) -> BoxFuture<'a, std::io::Result<Vec<String>>>;
+
+    /// Returns the last modification time of the blob stored with given key, or `None` if it does
+    /// not exist or the backend doesn't track modification times.
+    fn modified<'a>(
+        &'a self,
+        key: &'a str,
+    ) -> BoxFuture<'a, std::io::Result<Option<std::time::SystemTime>>> {
+        let _ = key;
+        Box::pin(async { Ok(None) })
+    }
+
+    /// Copies the blob stored with given key to another key, doing nothing if it does not exist.
+    fn copy<'a>(&'a self, from: &'a str, to: &'a str) -> BoxFuture<'a, std::io::Result<()>> {
+        Box::pin(async move {
+            match self.read(from).await? {
+                Some(data) => self.write(to, data).await,
+                None => Ok(()),
+            }
+        })
+    }
+}
+
+/// Storage of blobs as files under a root directory, with keys as relative paths.
+#[derive(Debug, Clone)]
+pub struct FsStorage {
+    root_dir: Arc<Path>,
+}
+
+/// Suffix of temporary files written before replacing the target files.
+const TMP_SUFFIX: &str = ".tmp";
+
+impl FsStorage {
+    /// Creates a storage under given root directory.
+    pub fn new<P>(root_dir: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self {
+            root_dir: root_dir.into().into_boxed_path().into(),
+        }
+    }
+
+    #[inline]
+    fn path(&self, key: &str) -> PathBuf {
+        self.root_dir.join(key)
+    }
+}
+
+impl Storage for FsStorage {
+    fn read<'a>(&'a self, key: &'a str) -> BoxFuture<'a, std::io::Result<Option<Vec<u8>>>> {
+        Box::pin(async move {
+            match tokio::fs::read(self.path(key)).await {
+                Ok(data) => Ok(Some(data)),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(err) => Err(err),
+            }
+        })
+    }
+
+    fn write<'a>(&'a self, key: &'a str, data: Vec<u8>) -> BoxFuture<'a, std::io::Result<()>> {
+        Box::pin(async move {
+            let path = self.path(key);
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            // written aside then renamed, in case of crashing during writes
+            let mut tmp_path = path.clone().into_os_string();
+            tmp_path.push(TMP_SUFFIX);
+            tokio::fs::write(&tmp_path, data).await?;
+            tokio::fs::rename(&tmp_path, &path).await
+        })
+    }
+
+    fn remove<'a>(&'a self, key: &'a str) -> BoxFuture<'a, std::io::Result<()>> {
+        Box::pin(async move {
+            match tokio::fs::remove_file(self.path(key)).await {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            }
+        })
+    }
+
+    fn modified<'a>(
+        &'a self,
+        key: &'a str,
+    ) -> BoxFuture<'a, std::io::Result<Option<std::time::SystemTime>>> {
+        Box::pin(async move {
+            match tokio::fs::metadata(self.path(key)).await {
+                Ok(metadata) => metadata.modified().map(Some),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(err) => Err(err),
+            }
+        })
+    }
+
+    fn list_dirs<'a>(&'a self, dir: &'a str) -> BoxFuture<'a, std::io::Result<Vec<String>>> {
+        Box::pin(async move {
+            let mut read_dir = match tokio::fs::read_dir(self.path(dir)).await {
+                Ok(read_dir) => read_dir,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+                Err(err) => return Err(err),
+            };
+            let mut dirs = Vec::new();
+            while let Some(entry) = read_dir.next_entry().await? {
+                let Ok(name) = entry.file_name().into_string() else {
+                    continue;
+                };
+                if !name.starts_with('.') && entry.file_type().await?.is_dir() {
+                    dirs.push(name);
+                }
+            }
+            Ok(dirs)
+        })
+    }
+}
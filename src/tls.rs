@@ -0,0 +1,112 @@
+//! TLS support for connecting to functions serving HTTPS.
+
+use std::{path::Path, sync::Arc};
+
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::WebPkiSupportedAlgorithms,
+    pki_types::{CertificateDer, ServerName, UnixTime, pem::PemObject as _},
+};
+
+/// How certificates of functions are verified.
+#[derive(Debug, Clone, Copy)]
+pub enum Verification<'a> {
+    /// Trusts native root certificates.
+    Native,
+    /// Trusts root certificates in given PEM bundle in addition to native ones.
+    CustomCa(&'a Path),
+    /// Skips verification of certificates entirely.
+    Insecure,
+}
+
+/// Builds the client configuration verifying certificates in given way.
+pub fn client_config(verification: Verification<'_>) -> std::io::Result<Arc<rustls::ClientConfig>> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = rustls::ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(std::io::Error::other)?;
+
+    let config = if let Verification::Insecure = verification {
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(NoVerification(
+                provider.signature_verification_algorithms,
+            )))
+            .with_no_client_auth()
+    } else {
+        let mut roots = native_roots();
+        if let Verification::CustomCa(path) = verification {
+            let certs = CertificateDer::pem_file_iter(path)
+                .and_then(|iter| iter.collect::<Result<Vec<_>, _>>())
+                .map_err(|err| {
+                    std::io::Error::other(format!(
+                        "failed to read CA bundle {}: {err}",
+                        path.display()
+                    ))
+                })?;
+            let (added, ignored) = roots.add_parsable_certificates(certs);
+            if added == 0 {
+                return Err(std::io::Error::other(format!(
+                    "no valid certificate found in CA bundle {}",
+                    path.display()
+                )));
+            }
+            tracing::info!(
+                "tls: loaded {added} root certificates from CA bundle, ignored {ignored}"
+            );
+        }
+        builder.with_root_certificates(roots).with_no_client_auth()
+    };
+    Ok(Arc::new(config))
+}
+
+fn native_roots() -> rustls::RootCertStore {
+    let native = rustls_native_certs::load_native_certs();
+    for err in &native.errors {
+        tracing::warn!("tls: failed to load native certificate: {err}");
+    }
+    let mut roots = rustls::RootCertStore::empty();
+    let (added, ignored) = roots.add_parsable_certificates(native.certs);
+    tracing::debug!("tls: loaded {added} native root certificates, ignored {ignored}");
+    roots
+}
+
+/// Verifier accepting any certificate, while still checking signatures of handshakes so
+/// connections are at least consistent with the presented certificates.
+#[derive(Debug)]
+struct NoVerification(WebPkiSupportedAlgorithms);
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.0)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.0)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.0.supported_schemes()
+    }
+}
@@ -0,0 +1,145 @@
+//! TLS termination of connections from clients.
+
+use std::{io, net::SocketAddr, path::Path, sync::Arc};
+
+use axum::{
+    extract::{ConnectInfo, Request, connect_info::Connected},
+    response::Response,
+};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject as _};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::{Semaphore, mpsc},
+};
+use tokio_rustls::{TlsAcceptor, server::TlsStream};
+
+use crate::Error;
+
+/// Maximum time for clients to complete handshakes, so stalled clients don't hold connections.
+const HANDSHAKE_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_secs(10);
+/// Count of established connections queued before being served.
+const QUEUE_SIZE: usize = 64;
+/// Maximum count of concurrent handshakes, beyond which accepting connections pauses.
+const MAX_HANDSHAKES: usize = 256;
+
+/// Builds the server configuration presenting the certificate chain and private key in given PEM
+/// files.
+pub fn server_config(cert: &Path, key: &Path) -> io::Result<Arc<rustls::ServerConfig>> {
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|iter| iter.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| {
+            io::Error::other(format!(
+                "failed to read certificate chain {}: {err}",
+                cert.display()
+            ))
+        })?;
+    let key = PrivateKeyDer::from_pem_file(key).map_err(|err| {
+        io::Error::other(format!(
+            "failed to read private key {}: {err}",
+            key.display()
+        ))
+    })?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut config = rustls::ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(io::Error::other)?;
+    // only HTTP/1 is served, where functions are still resolved from the `Host` header
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(Arc::new(config))
+}
+
+/// Listener handing out connections once their handshakes complete.
+///
+/// Handshakes are performed concurrently in the background so slow clients don't block others.
+#[derive(Debug)]
+pub struct TlsListener {
+    local_addr: SocketAddr,
+    rx: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+}
+
+impl TlsListener {
+    /// Wraps connections accepted from given listener with TLS.
+    pub fn new(mut listener: TcpListener, config: Arc<rustls::ServerConfig>) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let acceptor = TlsAcceptor::from(config);
+        let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+        let handshakes = Arc::new(Semaphore::new(MAX_HANDSHAKES));
+
+        tokio::spawn(async move {
+            loop {
+                let (permit, (stream, addr)) = tokio::select! {
+                    // stops accepting once the server stops
+                    () = tx.closed() => break,
+                    accepted = async {
+                        let permit = handshakes.clone().acquire_owned().await;
+                        (permit, axum::serve::Listener::accept(&mut listener).await)
+                    } => accepted,
+                };
+                let acceptor = acceptor.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let handshake =
+                        tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await;
+                    drop(permit);
+                    match handshake {
+                        Ok(Ok(stream)) => drop(tx.send((stream, addr)).await),
+                        Ok(Err(err)) => tracing::debug!("tls: handshake with {addr} failed: {err}"),
+                        Err(_) => tracing::debug!("tls: handshake with {addr} timed out"),
+                    }
+                });
+            }
+        });
+
+        Ok(Self { local_addr, rx })
+    }
+}
+
+impl axum::serve::Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.rx.recv().await {
+            Some(accepted) => accepted,
+            // the accepting task only stops after the listener is dropped
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+/// Server name indicated by the client of a connection, if any.
+#[derive(Debug, Clone)]
+pub struct ServerName(Option<Arc<str>>);
+
+impl Connected<axum::serve::IncomingStream<'_, TlsListener>> for ServerName {
+    fn connect_info(stream: axum::serve::IncomingStream<'_, TlsListener>) -> Self {
+        Self(stream.io().get_ref().1.server_name().map(Arc::from))
+    }
+}
+
+/// Rejects requests with `Host` not matching the server name indicated by the client, so a
+/// connection established for one host is never used for another.
+pub async fn check_host(
+    ConnectInfo(ServerName(name)): ConnectInfo<ServerName>,
+    request: Request,
+    next: axum::middleware::Next,
+) -> Result<Response, Error> {
+    if let Some(name) = name
+        && let Some(host) = request.headers().get(axum::http::header::HOST)
+    {
+        let host = host.to_str().map_err(|_| Error::MissingHost)?;
+        let hostname = host.rsplit_once(':').map_or(host, |(hostname, _)| hostname);
+        if !hostname.eq_ignore_ascii_case(&name) {
+            return Err(Error::MisdirectedRequest(host.to_owned()));
+        }
+    }
+    Ok(next.run(request).await)
+}
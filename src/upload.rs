@@ -0,0 +1,306 @@
+//! Resumable uploads of function contents.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWriteExt as _},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::func::OwnedKey;
+
+/// Information of an upload session, given on creation.
+#[derive(Debug, Clone)]
+pub struct SessionInfo {
+    /// Key of the function being uploaded.
+    pub key: OwnedKey,
+    /// Name of the user who created the session.
+    pub owner: String,
+    /// Content type of the archive being uploaded.
+    pub content_type: String,
+}
+
+#[derive(Debug)]
+struct Session {
+    info: SessionInfo,
+    path: PathBuf,
+    /// Count of bytes received.
+    len: u64,
+    /// Total length of the archive, if announced by the client.
+    total: Option<u64>,
+    last_active: Instant,
+    /// Whether the session is committed or removed.
+    closed: bool,
+}
+
+/// Manager of upload sessions, appending chunks of archives into temporary files.
+///
+/// Sessions inactive for longer than the TTL are removed by [`Self::prune`].
+#[derive(Debug)]
+pub struct UploadSessions {
+    sessions: scc::HashMap<String, Arc<Mutex<Session>>>,
+    /// Count of open sessions of each user.
+    open: scc::HashMap<String, usize>,
+    dir: PathBuf,
+    ttl: Duration,
+    max_per_user: usize,
+}
+
+impl UploadSessions {
+    /// Creates an empty manager storing temporary files under given directory, allowing at most
+    /// `max_per_user` open sessions for each user.
+    pub fn new<P>(dir: P, ttl: Duration, max_per_user: usize) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self {
+            sessions: scc::HashMap::new(),
+            open: scc::HashMap::new(),
+            dir: dir.into(),
+            ttl,
+            max_per_user,
+        }
+    }
+
+    /// Removes temporary files left by sessions of previous runs.
+    ///
+    /// This function is blocking and _should only be called at initialization._
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if failed to remove the directory.
+    pub fn clear_fs(&self) -> std::io::Result<()> {
+        match std::fs::remove_dir_all(&self.dir) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    /// Creates a new session with given id.
+    ///
+    /// # Errors
+    ///
+    /// - `TooManySessions` if the owner already holds the maximum count of open sessions.
+    /// - `Duplicated` if a session with given id already exists.
+    /// - Other errors if failed to create the temporary file.
+    pub async fn create(&self, id: String, info: SessionInfo) -> Result<(), UploadError> {
+        match self.open.entry_async(info.owner.clone()).await {
+            scc::hash_map::Entry::Occupied(mut entry) => {
+                if *entry.get() >= self.max_per_user {
+                    return Err(UploadError::TooManySessions {
+                        max: self.max_per_user,
+                    });
+                }
+                *entry.get_mut() += 1;
+            }
+            scc::hash_map::Entry::Vacant(entry) => drop(entry.insert_entry(1)),
+        }
+
+        let owner = info.owner.clone();
+        let result = self.priv_create(id, info).await;
+        if result.is_err() {
+            self.release(&owner).await;
+        }
+        result
+    }
+
+    async fn priv_create(&self, id: String, info: SessionInfo) -> Result<(), UploadError> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let path = self.dir.join(&id);
+        tokio::fs::File::create(&path).await?;
+        let session = Session {
+            info,
+            path,
+            len: 0,
+            total: None,
+            last_active: Instant::now(),
+            closed: false,
+        };
+        self.sessions
+            .insert_async(id, Arc::new(Mutex::new(session)))
+            .await
+            .map_err(|_| UploadError::Duplicated)
+    }
+
+    /// Releases an open session of given user.
+    async fn release(&self, owner: &str) {
+        if let scc::hash_map::Entry::Occupied(mut entry) =
+            self.open.entry_async(owner.to_owned()).await
+        {
+            if *entry.get() <= 1 {
+                let _ = entry.remove();
+            } else {
+                *entry.get_mut() -= 1;
+            }
+        }
+    }
+
+    /// Returns information of a session along with the count of bytes received.
+    pub async fn get(&self, id: &str) -> Option<(SessionInfo, u64)> {
+        let session = self.sessions.read_async(id, |_, s| s.clone()).await?;
+        let session = session.lock().await;
+        Some((session.info.clone(), session.len))
+    }
+
+    /// Appends a chunk starting at given offset to a session, returning the count of bytes
+    /// received so far.
+    ///
+    /// Bytes read before failures of the reader are kept, so clients could resume from the
+    /// count returned by [`Self::get`].
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if the session does not exist.
+    /// - `OffsetMismatch` if the offset is not the count of bytes received.
+    /// - Other errors if failed to read the chunk or to write the temporary file.
+    pub async fn append<R>(
+        &self,
+        id: &str,
+        offset: u64,
+        total: Option<u64>,
+        reader: &mut R,
+    ) -> Result<u64, UploadError>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let session = self
+            .sessions
+            .read_async(id, |_, s| s.clone())
+            .await
+            .ok_or(UploadError::NotFound)?;
+        let mut session = session.lock().await;
+        if session.closed {
+            return Err(UploadError::NotFound);
+        }
+        if offset != session.len {
+            return Err(UploadError::OffsetMismatch {
+                expected: session.len,
+            });
+        }
+        if total.is_some() {
+            session.total = total;
+        }
+        session.last_active = Instant::now();
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(&session.path)
+            .await?;
+        let result = tokio::io::copy(reader, &mut file).await;
+        file.flush().await?;
+        session.len = file.metadata().await?.len();
+        session.last_active = Instant::now();
+        result?;
+        Ok(session.len)
+    }
+
+    /// Removes a session and opens its temporary file for reading, which is removed once closed.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if the session does not exist.
+    /// - `Incomplete` if fewer bytes than the announced total length are received.
+    /// - Other errors if failed to open the temporary file.
+    pub async fn take(&self, id: &str) -> Result<(SessionInfo, tokio::fs::File), UploadError> {
+        let session = self
+            .sessions
+            .read_async(id, |_, s| s.clone())
+            .await
+            .ok_or(UploadError::NotFound)?;
+        // waits for appending chunks
+        let mut session = session.lock().await;
+        if session.closed {
+            return Err(UploadError::NotFound);
+        }
+        if let Some(total) = session.total
+            && total != session.len
+        {
+            return Err(UploadError::Incomplete {
+                received: session.len,
+                total,
+            });
+        }
+        // rejects chunks waiting for the lock
+        session.closed = true;
+        self.sessions.remove_async(id).await;
+        self.release(&session.info.owner).await;
+
+        let file = tokio::fs::File::open(&session.path).await;
+        // the file is readable until closed
+        remove_file(&session.path).await;
+        Ok((session.info.clone(), file?))
+    }
+
+    /// Removes a session without committing it.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if the session does not exist.
+    pub async fn remove(&self, id: &str) -> Result<(), UploadError> {
+        let (_, session) = self
+            .sessions
+            .remove_async(id)
+            .await
+            .ok_or(UploadError::NotFound)?;
+        let mut session = session.lock().await;
+        // the session could have been taken meanwhile, which released it already
+        if !session.closed {
+            session.closed = true;
+            self.release(&session.info.owner).await;
+        }
+        remove_file(&session.path).await;
+        Ok(())
+    }
+
+    /// Removes sessions inactive for longer than the TTL.
+    pub async fn prune(&self) {
+        let mut expired = Vec::new();
+        self.sessions.retain_sync(|_, session| {
+            // sessions being appended to are active
+            let Ok(mut s) = session.try_lock() else {
+                return true;
+            };
+            let alive = s.last_active.elapsed() < self.ttl;
+            if !alive {
+                s.closed = true;
+                expired.push((s.info.owner.clone(), s.path.clone()));
+            }
+            alive
+        });
+        for (owner, path) in expired {
+            self.release(&owner).await;
+            remove_file(&path).await;
+        }
+    }
+}
+
+async fn remove_file(path: &Path) {
+    drop(
+        tokio::fs::remove_file(path)
+            .await
+            .inspect_err(|e| tracing::warn!("failed to remove upload session file: {e}")),
+    )
+}
+
+/// Errors that may occur when working with [`UploadSessions`].
+#[derive(Debug, thiserror::Error)]
+#[allow(missing_docs)]
+#[non_exhaustive]
+pub enum UploadError {
+    #[error("the upload session does not exist")]
+    NotFound,
+    #[error("the upload session already exists")]
+    Duplicated,
+    #[error("too many open upload sessions, at most {max} are allowed for each user")]
+    TooManySessions { max: usize },
+    #[error("chunk does not continue the received bytes, expected offset {expected}")]
+    OffsetMismatch { expected: u64 },
+    #[error("upload is incomplete, received {received} of {total} bytes")]
+    Incomplete { received: u64, total: u64 },
+    #[error("I/O error occurred: {0}")]
+    Io(#[from] std::io::Error),
+}
@@ -4,7 +4,7 @@ use std::{
     borrow::Cow,
     collections::{HashMap, HashSet},
     fmt::Display,
-    path::{Path, PathBuf},
+    path::PathBuf,
     str::FromStr,
     sync::{
         Arc,
@@ -15,8 +15,11 @@ use std::{
 use base64::Engine as _;
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
 use time::{Duration, UtcDateTime};
 
+use crate::storage::{FsStorage, Storage};
+
 /// User of the platform.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
@@ -28,8 +31,67 @@ pub struct User {
     ///
     /// Do not check using the set directly; Instead, use [`Self::is_in`] to check whether a user is in a group.
     pub groups: HashSet<Group>,
+    /// Maximum total size in bytes of contents of functions owned by this user.
+    ///
+    /// Unlimited if not specified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quota_bytes: Option<u64>,
 
-    tokens: HashMap<String, UtcDateTime>, // token ->  expiration instant
+    tokens: HashMap<String, Token>, // token -> its metadata
+}
+
+/// Metadata of a token held by a user.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "SerializedToken")]
+struct Token {
+    expires_at: UtcDateTime,
+    /// Label given on request for telling tokens apart.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    created_at: UtcDateTime,
+    /// Groups the token is restricted to, or `None` for all groups of the user.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scope: Option<HashSet<Group>>,
+}
+
+/// Serialized form of [`Token`], accepting the legacy form of a bare expiration instant.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SerializedToken {
+    Full {
+        expires_at: UtcDateTime,
+        #[serde(default)]
+        label: Option<String>,
+        created_at: UtcDateTime,
+        #[serde(default)]
+        scope: Option<HashSet<Group>>,
+    },
+    Legacy(UtcDateTime),
+}
+
+impl From<SerializedToken> for Token {
+    fn from(value: SerializedToken) -> Self {
+        match value {
+            SerializedToken::Full {
+                expires_at,
+                label,
+                created_at,
+                scope,
+            } => Self {
+                expires_at,
+                label,
+                created_at,
+                scope,
+            },
+            // creation instants of legacy tokens are unknown, so they're counted from loading
+            SerializedToken::Legacy(expires_at) => Self {
+                expires_at,
+                label: None,
+                created_at: UtcDateTime::now().min(expires_at),
+                scope: None,
+            },
+        }
+    }
 }
 
 impl User {
@@ -41,6 +103,7 @@ impl User {
         Self {
             name,
             groups: groups.into_iter().collect(),
+            quota_bytes: None,
             tokens: HashMap::new(),
         }
     }
@@ -59,26 +122,98 @@ impl User {
     pub fn is_token_valid(&self, token: &str) -> bool {
         self.tokens
             .get(token)
-            .is_some_and(|time| UtcDateTime::now() < *time)
+            .is_some_and(|token| UtcDateTime::now() < token.expires_at)
     }
 
-    fn add_token<R>(&mut self, rng: R, duration: Duration) -> String
+    fn add_token<R>(
+        &mut self,
+        rng: R,
+        duration: Duration,
+        label: Option<String>,
+        scope: Option<HashSet<Group>>,
+    ) -> String
     where
         R: RngCore,
     {
-        // remove expired tokens. we got mutable access why not do this
-        self.tokens.retain(|_, time| UtcDateTime::now() < *time);
-
         let token = gen_token(rng);
-        self.tokens
-            .insert(token.clone(), UtcDateTime::now() + duration);
+        let now = UtcDateTime::now();
+        self.tokens.insert(
+            token.clone(),
+            Token {
+                expires_at: now + duration,
+                label,
+                created_at: now,
+                scope,
+            },
+        );
         token
     }
 
+    /// Removes expired tokens of this user, returning the removed ones.
+    fn prune_expired_tokens(&mut self) -> Vec<String> {
+        let now = UtcDateTime::now();
+        let expired: Vec<String> = self
+            .tokens
+            .iter()
+            .filter(|(_, token)| token.expires_at <= now)
+            .map(|(token, _)| token.clone())
+            .collect();
+        for token in &expired {
+            self.tokens.remove(token);
+        }
+        expired
+    }
+
     /// Clears all tokens of this user.
     pub fn clear_tokens(&mut self) {
         self.tokens.clear();
     }
+
+    /// Returns information of unexpired tokens of this user, sorted by expiry.
+    pub fn tokens(&self) -> Vec<TokenInfo> {
+        let now = UtcDateTime::now();
+        let mut tokens: Vec<_> = self
+            .tokens
+            .iter()
+            .filter(|(_, token)| token.expires_at > now)
+            .map(|(token, meta)| TokenInfo {
+                // tokens are base64-encoded so slicing never splits characters
+                prefix: token[..LEN_TOKEN_PREFIX.min(token.len())].to_owned(),
+                label: meta.label.clone(),
+                expires_at: meta.expires_at,
+                created_at: meta.created_at,
+                scope: meta
+                    .scope
+                    .as_ref()
+                    .map(|scope| scope.iter().cloned().collect()),
+            })
+            .collect();
+        tokens.sort_unstable_by_key(|info| info.expires_at);
+        tokens
+    }
+}
+
+/// Length of prefixes of tokens exposed by [`TokenInfo`].
+const LEN_TOKEN_PREFIX: usize = 6;
+
+/// Information of a token, without exposing the token itself.
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
+pub struct TokenInfo {
+    /// First few characters of the token, for telling tokens apart.
+    pub prefix: String,
+    /// Label given on request of the token.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Instant the token expires at.
+    #[serde(serialize_with = "crate::rfc3339::serialize")]
+    pub expires_at: UtcDateTime,
+    /// Instant the token was created at.
+    #[serde(serialize_with = "crate::rfc3339::serialize")]
+    pub created_at: UtcDateTime,
+    /// Groups the token is restricted to, or `None` for all groups of the user.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<Vec<Group>>,
 }
 
 /// Generates a random token from given [`RngCore`].
@@ -93,6 +228,11 @@ where
     base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(token_raw)
 }
 
+/// Hashes a token for comparing without keeping the plaintext.
+fn hash_token(token: &str) -> [u8; 32] {
+    Sha256::digest(token.as_bytes()).into()
+}
+
 /// Group of a user.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 #[non_exhaustive]
@@ -176,7 +316,7 @@ impl FromStr for Group {
             .map_err(|err| ParseGroupError::InvalidPermission(value.to_owned(), err)),
             UG_KEY_CUSTOM => Ok(Self::Custom(value.to_owned())),
             UG_KEY_SINGULAR => Ok(Self::Singular(value.to_owned())),
-            _ => Err(ParseGroupError::MissingKey),
+            _ => Err(ParseGroupError::InvalidKey(key.to_owned())),
         }
     }
 }
@@ -237,53 +377,107 @@ pub enum ParseGroupError {
 pub struct UserManager {
     users: scc::HashMap<String, User>,      // user name -> user
     tokens: scc::HashIndex<String, String>, // token -> user name
-    root_dir: Arc<Path>,
+    groups: scc::HashSet<String>,           // names of registered custom groups
 
-    root_token: String,
+    /// SHA-256 digest of the root token, so that the plaintext is never kept.
+    root_token_hash: [u8; 32],
 
     dirty: AtomicBool,
+    storage: Option<Arc<dyn Storage>>,
 }
 
 const ROOT_USERNAME: &str = "root";
 
+/// Maximum valid duration of tokens.
+///
+/// Requesting tokens valid for longer is rejected, while extending tokens clamps their expiry to
+/// this duration from now.
+pub const MAX_TOKEN_DURATION: Duration = Duration::days(365);
+
 #[derive(Serialize, Deserialize)]
 struct SerializedUsers {
     users: Box<[User]>,
+    /// Names of registered custom groups.
+    #[serde(default)]
+    groups: Box<[String]>,
 }
 
 const USERS_FILE: &str = "users.json";
+const USERS_BACKUP_FILE: &str = "users.json.bak";
+
+/// Reads serialized users stored with given key, or `None` if it does not exist.
+async fn read_serialized(
+    storage: &dyn Storage,
+    key: &str,
+) -> Result<Option<SerializedUsers>, ManagerError> {
+    match storage.read(key).await? {
+        Some(raw) => Ok(Some(serde_json::from_slice(&raw)?)),
+        None => Ok(None),
+    }
+}
 
 impl UserManager {
     fn mark_dirty(&self) {
-        self.dirty.store(true, atomic::Ordering::Relaxed);
+        if self.storage.is_some() {
+            self.dirty.store(true, atomic::Ordering::Relaxed);
+        }
     }
 
-    /// Checks whether the user manager is dirty and needs to be written to the filesystem.
+    /// Checks whether the user manager is dirty and needs to be written to the storage.
     #[inline]
     pub fn is_dirty(&self) -> bool {
         self.dirty.load(atomic::Ordering::Relaxed)
     }
 
-    /// Creates an empty, uninitialized user manager.
+    /// Creates an empty, uninitialized user manager persisting to the filesystem.
+    ///
+    /// The root account authenticates with given token, or a randomly-generated one for this
+    /// session if not specified.
     ///
-    /// For loading users from the filesystem, use [`Self::read_from_fs`].
-    pub fn new<P, R>(rng: R, root_dir: P) -> Self
+    /// For loading users from the storage, use [`Self::read_from_storage`].
+    pub fn new<P, R>(rng: R, root_dir: P, root_token: Option<&str>) -> Self
     where
         P: Into<PathBuf>,
         R: RngCore,
     {
-        let this = Self {
+        let root_token_hash = match root_token {
+            Some(token) => hash_token(token),
+            None => {
+                let token = gen_token(rng);
+                tracing::info!("token of root account generated for this session: {token}");
+                hash_token(&token)
+            }
+        };
+        Self {
             users: scc::HashMap::new(),
             tokens: scc::HashIndex::new(),
-            root_dir: root_dir.into().into_boxed_path().into(),
-            root_token: gen_token(rng),
+            groups: scc::HashSet::new(),
+            root_token_hash,
             dirty: AtomicBool::new(false),
-        };
-        tracing::info!(
-            "token of root account generated for this session: {}",
-            this.root_token
-        );
-        this
+            storage: Some(Arc::new(FsStorage::new(root_dir))),
+        }
+    }
+
+    /// Creates an empty user manager without persistence, whose reading from and writing to the
+    /// storage are no-ops.
+    ///
+    /// See [`Self::new`] for the root token.
+    pub fn new_in_memory<R>(rng: R, root_token: Option<&str>) -> Self
+    where
+        R: RngCore,
+    {
+        Self {
+            storage: None,
+            ..Self::new(rng, PathBuf::new(), root_token)
+        }
+    }
+
+    /// Sets the storage users are persisted to.
+    pub fn with_storage(self, storage: Arc<dyn Storage>) -> Self {
+        Self {
+            storage: Some(storage),
+            ..self
+        }
     }
 
     /// Whether the user manager is empty.
@@ -291,68 +485,143 @@ impl UserManager {
         self.users.is_empty()
     }
 
-    /// Loads all users from the filesystem.
+    /// Loads all users from the storage.
     ///
-    /// This function is blocking and _should only be called at initialization._
+    /// This function _should only be called at initialization._
     ///
     /// # Errors
     ///
     /// - `Initialized` if the function manager is not empty.
-    /// - Other errors if any error occurs while interacting with filesystem.
-    pub fn read_from_fs(&self) -> Result<(), ManagerError> {
-        let span = tracing::info_span!("loading users from the filesystem");
+    /// - Other errors if any error occurs while interacting with the storage.
+    pub async fn read_from_storage(&self) -> Result<(), ManagerError> {
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
+
+        let span = tracing::info_span!("loading users from the storage");
         let _e = span.enter();
 
         if !self.is_empty() {
             return Err(ManagerError::Initialized);
         }
 
-        let file_result = std::fs::File::open(self.root_dir.join(USERS_FILE));
-        if file_result
-            .as_ref()
-            .is_err_and(|err| err.kind() == std::io::ErrorKind::NotFound)
-        {
-            return Ok(());
+        let serialized = match read_serialized(&**storage, USERS_FILE).await {
+            Ok(Some(serialized)) => serialized,
+            Ok(None) => return Ok(()),
+            Err(ManagerError::ParseJson(err)) => {
+                tracing::warn!(
+                    "!!! {USERS_FILE} is corrupted ({err}), falling back to {USERS_BACKUP_FILE} !!!"
+                );
+                read_serialized(&**storage, USERS_BACKUP_FILE)
+                    .await?
+                    .ok_or(ManagerError::ParseJson(err))?
+            }
+            Err(err) => return Err(err),
+        };
+
+        self.priv_insert_groups(serialized.groups);
+        self.priv_insert_users(serialized.users, false)
+    }
+
+    /// Serializes all users, in the same format they're written to the storage.
+    #[allow(clippy::missing_errors_doc)] // serialization should not fail
+    pub fn export(&self) -> Result<Vec<u8>, ManagerError> {
+        let mut users = Vec::with_capacity(self.users.len());
+        self.users.iter_sync(|_, user| {
+            users.push(user.clone());
+            true
+        });
+        serde_json::to_vec(&SerializedUsers {
+            users: users.into_boxed_slice(),
+            groups: self.list_groups().into_boxed_slice(),
+        })
+        .map_err(Into::into)
+    }
+
+    /// Adds users serialized by [`Self::export`].
+    ///
+    /// # Errors
+    ///
+    /// - `Duplicated` if any user already exists, unless replacing existing users. Nothing is
+    ///   added in this case.
+    /// - Other errors if the users are malformed.
+    pub fn import(&self, raw: &[u8], replace: bool) -> Result<(), ManagerError> {
+        let serialized = self.priv_check_import(raw, replace)?;
+        self.priv_insert_users(serialized.users, replace)?;
+        self.priv_insert_groups(serialized.groups);
+        self.mark_dirty();
+        Ok(())
+    }
+
+    /// Checks whether users serialized by [`Self::export`] could be imported without adding them,
+    /// see [`Self::import`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the error importing them would fail with.
+    pub fn check_import(&self, raw: &[u8], replace: bool) -> Result<(), ManagerError> {
+        self.priv_check_import(raw, replace).map(drop)
+    }
+
+    fn priv_check_import(
+        &self,
+        raw: &[u8],
+        replace: bool,
+    ) -> Result<SerializedUsers, ManagerError> {
+        let serialized: SerializedUsers = serde_json::from_slice(raw)?;
+        if serialized.users.iter().any(|user| {
+            user.name == ROOT_USERNAME || (!replace && self.users.contains_sync(&user.name))
+        }) {
+            return Err(ManagerError::Duplicated);
+        }
+        Ok(serialized)
+    }
+
+    fn priv_insert_groups(&self, groups: Box<[String]>) {
+        for group in groups {
+            drop(self.groups.insert_sync(group));
         }
-        let serialized: SerializedUsers =
-            serde_json::from_reader(std::io::BufReader::new(file_result?))?;
+    }
 
-        self.users.reserve(serialized.users.len());
+    fn priv_insert_users(&self, users: Box<[User]>, replace: bool) -> Result<(), ManagerError> {
+        self.users.reserve(users.len());
         let now = UtcDateTime::now();
-        for user in serialized.users {
-            for (token, time) in &user.tokens {
-                if time > &now {
+        for user in users {
+            if user.name == ROOT_USERNAME {
+                return Err(ManagerError::Duplicated);
+            }
+            for (token, meta) in &user.tokens {
+                if meta.expires_at > now {
                     drop(self.tokens.insert_sync(token.clone(), user.name.clone()));
                 }
             }
-            self.users
-                .insert_sync(user.name.clone(), user)
-                .map_err(|_| ManagerError::Duplicated)?;
+            if replace {
+                drop(self.users.upsert_sync(user.name.clone(), user));
+            } else {
+                self.users
+                    .insert_sync(user.name.clone(), user)
+                    .map_err(|_| ManagerError::Duplicated)?;
+            }
         }
 
         Ok(())
     }
 
-    /// Writes all users to the filesystem.
+    /// Writes all users to the storage.
     #[allow(clippy::missing_errors_doc)] // general I/O errors from std::io
-    pub async fn write_all_to_fs(&self) -> Result<(), ManagerError> {
-        let span = tracing::info_span!("writing users to the filesystem");
+    pub async fn write_all_to_storage(&self) -> Result<(), ManagerError> {
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
+
+        let span = tracing::info_span!("writing users to the storage");
         let _e = span.enter();
 
-        let mut users = Vec::with_capacity(self.users.len());
-        self.users.iter_sync(|_, user| {
-            users.push(user.clone());
-            true
-        });
+        let serialized = self.export()?;
 
-        tokio::fs::create_dir_all(&self.root_dir).await?;
-        tokio::fs::write(
-            self.root_dir.join(USERS_FILE),
-            serde_json::to_vec(&SerializedUsers {
-                users: users.into_boxed_slice(),
-            })?,
-        )
-        .await?;
+        // keep the previous version as backup, in case of corrupted saves
+        storage.copy(USERS_FILE, USERS_BACKUP_FILE).await?;
+        storage.write(USERS_FILE, serialized).await?;
 
         self.dirty.store(false, atomic::Ordering::Relaxed);
         Ok(())
@@ -381,14 +650,24 @@ impl UserManager {
     where
         I: IntoIterator<Item = Cow<'g, Group>>,
     {
-        if self.root_token == token {
+        if self.is_root(token) {
             return true;
         }
 
         self.tokens
             .peek_with(token, |_, un| {
                 self.users.read_sync(un, |_, user| {
-                    groups.into_iter().all(|g| user.groups.contains(&g))
+                    let Some(meta) = user.tokens.get(token) else {
+                        return false;
+                    };
+                    UtcDateTime::now() < meta.expires_at
+                        && groups.into_iter().all(|g| {
+                            // users are always in their singular groups regardless of scopes,
+                            // the same as users peeked from scoped tokens
+                            user.is_in(&g)
+                                && (matches!(*g, Group::Singular(_))
+                                    || meta.scope.as_ref().is_none_or(|scope| scope.contains(&g)))
+                        })
                 })
             })
             .flatten()
@@ -397,6 +676,8 @@ impl UserManager {
 
     /// Peeks a user from given token, returning the value from given function or `None` if peeking a root account.
     ///
+    /// Users peeked from scoped tokens are only in groups within the scope.
+    ///
     /// # Errors
     ///
     /// - `NotFound` if the user does not exist.
@@ -404,7 +685,7 @@ impl UserManager {
     where
         F: FnOnce(&User) -> U,
     {
-        if token == self.root_token {
+        if self.is_root(token) {
             return Ok(None);
         }
 
@@ -413,45 +694,199 @@ impl UserManager {
                 if un == ROOT_USERNAME {
                     Some(None)
                 } else {
-                    self.users.read_sync(un, |_, user| f(user)).map(Some)
+                    self.users
+                        .read_sync(un, |_, user| {
+                            match user.tokens.get(token).and_then(|t| t.scope.as_ref()) {
+                                Some(scope) => f(&User {
+                                    name: user.name.clone(),
+                                    groups: user.groups.intersection(scope).cloned().collect(),
+                                    quota_bytes: user.quota_bytes,
+                                    tokens: HashMap::new(),
+                                }),
+                                None => f(user),
+                            }
+                        })
+                        .map(Some)
                 }
             })
             .flatten()
             .ok_or(ManagerError::NotFound)
     }
 
-    /// Adds a randomly-generated token to this user and returns the token.
+    /// Adds a randomly-generated token with an optional label to this user and returns the token.
+    ///
+    /// The token authenticates only for groups in `scope` if specified, which must be a subset
+    /// of groups of the user.
     ///
     /// # Errors
     ///
+    /// - `DurationExceeded` if the duration is longer than [`MAX_TOKEN_DURATION`].
     /// - `NotFound` if the user does not exist.
+    /// - `ScopeExceeded` if the scope contains groups the user is not in.
     pub fn add_token<R>(
         &self,
         name: &str,
         rng: R,
         duration: Duration,
+        label: Option<String>,
+        scope: Option<HashSet<Group>>,
     ) -> Result<String, ManagerError>
     where
         R: RngCore,
     {
-        let token = self
-            .users
-            .get_sync(name)
-            .ok_or(ManagerError::NotFound)?
-            .add_token(rng, duration);
+        // tokens are pruned periodically through `prune_expired_tokens`, while users holding
+        // too many of them are pruned lazily here
+        const THRESHOLD_LAZY_PRUNE: usize = 64;
+
+        if duration > MAX_TOKEN_DURATION {
+            return Err(ManagerError::DurationExceeded);
+        }
+        let mut user = self.users.get_sync(name).ok_or(ManagerError::NotFound)?;
+        if scope
+            .as_ref()
+            .is_some_and(|scope| !scope.iter().all(|g| user.is_in(g)))
+        {
+            return Err(ManagerError::ScopeExceeded);
+        }
+        if user.tokens.len() >= THRESHOLD_LAZY_PRUNE {
+            for expired in user.prune_expired_tokens() {
+                self.tokens.remove_sync(&expired);
+            }
+        }
+        let token = user.add_token(rng, duration, label, scope);
+        drop(user);
         drop(self.tokens.insert_sync(token.clone(), name.to_owned()));
         self.mark_dirty();
         Ok(token)
     }
 
+    /// Extends an unexpired token to expire at given instant, returning the effective expiry.
+    ///
+    /// The expiry is clamped to [`MAX_TOKEN_DURATION`] from now.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if the token does not exist.
+    /// - `Expired` if the token is already expired.
+    pub fn extend_token(
+        &self,
+        token: &str,
+        new_expiry: UtcDateTime,
+    ) -> Result<UtcDateTime, ManagerError> {
+        self.update_token_expiry(token, |_| new_expiry)
+    }
+
+    /// Extends an unexpired token by given duration, returning the effective expiry.
+    ///
+    /// The expiry is clamped to [`MAX_TOKEN_DURATION`] from now.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if the token does not exist or is already expired.
+    pub fn refresh_token(
+        &self,
+        token: &str,
+        additional: Duration,
+    ) -> Result<UtcDateTime, ManagerError> {
+        self.update_token_expiry(token, |expiry| expiry + additional)
+            .map_err(|err| match err {
+                ManagerError::Expired => ManagerError::NotFound,
+                err => err,
+            })
+    }
+
+    fn update_token_expiry<F>(&self, token: &str, f: F) -> Result<UtcDateTime, ManagerError>
+    where
+        F: FnOnce(UtcDateTime) -> UtcDateTime,
+    {
+        let name = self
+            .tokens
+            .peek_with(token, |_, name| name.clone())
+            .ok_or(ManagerError::NotFound)?;
+        let mut user = self.users.get_sync(&name).ok_or(ManagerError::NotFound)?;
+
+        let now = UtcDateTime::now();
+        let expiry = &mut user
+            .tokens
+            .get_mut(token)
+            .ok_or(ManagerError::NotFound)?
+            .expires_at;
+        if *expiry <= now {
+            return Err(ManagerError::Expired);
+        }
+        *expiry = f(*expiry).min(now + MAX_TOKEN_DURATION);
+        let expiry = *expiry;
+        drop(user);
+
+        self.mark_dirty();
+        Ok(expiry)
+    }
+
+    /// Lists unexpired tokens of given user, dropping the expired ones meanwhile.
+    ///
+    /// The root token is never listed.
+    ///
+    /// # Errors
+    ///
+    /// - `NotFound` if the user does not exist.
+    pub fn list_tokens(&self, name: &str) -> Result<Vec<TokenInfo>, ManagerError> {
+        if name == ROOT_USERNAME {
+            return Ok(Vec::new());
+        }
+        let mut user = self.users.get_sync(name).ok_or(ManagerError::NotFound)?;
+        let expired = user.prune_expired_tokens();
+        let tokens = user.tokens();
+        drop(user);
+
+        if !expired.is_empty() {
+            for token in &expired {
+                self.tokens.remove_sync(token);
+            }
+            self.mark_dirty();
+        }
+        Ok(tokens)
+    }
+
+    /// Removes expired tokens of all users from this manager.
+    ///
+    /// This scans every user thus is intended to be called periodically rather than per request.
+    pub fn prune_expired_tokens(&self) {
+        let mut expired = Vec::new();
+        self.users.retain_sync(|_, user| {
+            expired.append(&mut user.prune_expired_tokens());
+            true
+        });
+
+        if !expired.is_empty() {
+            for token in &expired {
+                self.tokens.remove_sync(token);
+            }
+            tracing::debug!("pruned {} expired tokens", expired.len());
+            self.mark_dirty();
+        }
+    }
+
+    /// Whether the given token is the root token.
+    #[inline]
+    pub fn is_root(&self, token: &str) -> bool {
+        hash_token(token) == self.root_token_hash
+    }
+
     /// Returns the name of the user holding the given token.
     pub fn user_name(&self, token: &str) -> Option<String> {
-        if token == self.root_token {
+        if self.is_root(token) {
             return Some("root".to_owned());
         }
         self.tokens.peek_with(token, |_, name| name.clone())
     }
 
+    /// Returns the disk quota of given user, or `None` if unlimited or the user is not found.
+    pub fn quota_bytes(&self, name: &str) -> Option<u64> {
+        self.users
+            .read_sync(name, |_, user| user.quota_bytes)
+            .flatten()
+    }
+
     /// Removes a user from this manager.
     ///
     /// # Errors
@@ -466,6 +901,95 @@ impl UserManager {
         Ok(())
     }
 
+    /// Returns names of custom groups any user is in.
+    pub fn list_custom_groups(&self) -> HashSet<String> {
+        let mut groups = HashSet::new();
+        self.users.iter_sync(|_, user| {
+            groups.extend(user.groups.iter().filter_map(|g| match g {
+                Group::Custom(name) => Some(name.clone()),
+                _ => None,
+            }));
+            true
+        });
+        groups
+    }
+
+    /// Maps every user through given function, excluding the root user.
+    pub fn list<F, U>(&self, mut f: F) -> Vec<U>
+    where
+        F: FnMut(&User) -> U,
+    {
+        let mut users = Vec::with_capacity(self.users.len());
+        self.users.iter_sync(|_, user| {
+            users.push(f(user));
+            true
+        });
+        users
+    }
+
+    /// Registers a custom group by its name.
+    ///
+    /// # Errors
+    ///
+    /// - `GroupDuplicated` if the group is already registered.
+    pub fn register_group(&self, name: String) -> Result<(), ManagerError> {
+        self.groups
+            .insert_sync(name)
+            .map_err(|_| ManagerError::GroupDuplicated)?;
+        self.mark_dirty();
+        Ok(())
+    }
+
+    /// Unregisters a custom group by its name.
+    ///
+    /// Users still in the group are removed from it if cascading, otherwise the group is kept.
+    ///
+    /// # Errors
+    ///
+    /// - `GroupNotFound` if the group is not registered.
+    /// - `GroupInUse` if any user is still in the group and not cascading.
+    pub fn unregister_group(&self, name: &str, cascade: bool) -> Result<(), ManagerError> {
+        if !self.groups.contains_sync(name) {
+            return Err(ManagerError::GroupNotFound);
+        }
+        let group = Group::Custom(name.to_owned());
+        if cascade {
+            self.users.retain_sync(|_, user| {
+                user.groups.remove(&group);
+                true
+            });
+        } else if !self.users.iter_sync(|_, user| !user.is_in(&group)) {
+            return Err(ManagerError::GroupInUse);
+        }
+        self.groups
+            .remove_sync(name)
+            .ok_or(ManagerError::GroupNotFound)?;
+        self.mark_dirty();
+        Ok(())
+    }
+
+    /// Returns names of registered custom groups.
+    pub fn list_groups(&self) -> Vec<String> {
+        let mut groups = Vec::with_capacity(self.groups.len());
+        self.groups.iter_sync(|name| {
+            groups.push(name.clone());
+            true
+        });
+        groups
+    }
+
+    /// Returns names of users in given group, excluding the root user.
+    pub fn members_of(&self, group: &Group) -> Vec<String> {
+        let mut members = Vec::new();
+        self.users.iter_sync(|name, user| {
+            if user.is_in(group) {
+                members.push(name.clone());
+            }
+            true
+        });
+        members
+    }
+
     /// Peeks an user or `None` if peeking a root account.
     ///
     /// # Errors
@@ -519,4 +1043,19 @@ pub enum ManagerError {
     Duplicated,
     #[error("the user holding the given name does not exist")]
     NotFound,
+    #[error("the given token is already expired")]
+    Expired,
+    #[error(
+        "the valid duration of the token exceeds the maximum of {} days",
+        MAX_TOKEN_DURATION.whole_days()
+    )]
+    DurationExceeded,
+    #[error("the scope of the token exceeds groups of the user")]
+    ScopeExceeded,
+    #[error("the group holding the given name is already registered")]
+    GroupDuplicated,
+    #[error("the group holding the given name is not registered")]
+    GroupNotFound,
+    #[error("the group is still assigned to users")]
+    GroupInUse,
 }
@@ -0,0 +1,91 @@
+//! Test client for deploying through an alias being removed concurrently.
+//!
+//! Repeatedly sets an alias of a function, then deploys through the alias while removing it from
+//! another thread. Every deploy must either succeed or fail with `404` telling the function or
+//! alias is missing, and never leave an instance running behind a failed deploy.
+//!
+//! Requires the platform address in `YFASS_API` (like `localhost:8080`), a token with permission
+//! `WRITE`, `EXECUTE` and `READ` in `YFASS_TOKEN`, and the key of an uploaded but not running
+//! function in `YFASS_KEY` (like `name@version`).
+
+use std::time::Duration;
+
+use test_util::{env, request};
+
+/// Alias set and removed on the function.
+const ALIAS: &str = "alias-race-test";
+/// Count of rounds of racing.
+const ROUNDS: u64 = 50;
+
+fn main() {
+    let api = env("YFASS_API");
+    let token = env("YFASS_TOKEN");
+    let key = env("YFASS_KEY");
+    let (name, _) = key.split_once('@').expect("invalid function key");
+
+    let (mut deployed, mut missing) = (0, 0);
+    for round in 0..ROUNDS {
+        let (status, body) = request(
+            &api,
+            &token,
+            "PATCH",
+            &format!("/api/alias/{key}"),
+            Some(&format!(r#"{{"alias":"{ALIAS}"}}"#)),
+        );
+        assert_eq!(status, 200, "failed to set alias: {body}");
+
+        let remover = {
+            let (api, token, key) = (api.clone(), token.clone(), key.clone());
+            std::thread::spawn(move || {
+                // spread removals across stages of deploying
+                std::thread::sleep(Duration::from_millis(round % 10));
+                request(
+                    &api,
+                    &token,
+                    "PATCH",
+                    &format!("/api/alias/{key}"),
+                    Some(r#"{"alias":null}"#),
+                )
+            })
+        };
+        let (status, body) = request(
+            &api,
+            &token,
+            "POST",
+            &format!("/api/deploy/{name}@{ALIAS}"),
+            None,
+        );
+        let (removed, removed_body) = remover.join().unwrap();
+        assert_eq!(removed, 200, "failed to remove alias: {removed_body}");
+
+        let succeeded = status == 200;
+        match status {
+            200 => deployed += 1,
+            404 => {
+                assert!(
+                    body.contains(&format!("{name}@{ALIAS} not found")),
+                    "404 not telling the alias is missing: {body}"
+                );
+                missing += 1;
+            }
+            _ => panic!("unexpected response to deploying through removed alias: {status} {body}"),
+        }
+
+        // failed deploys must not leak instances
+        let (status, body) = request(&api, &token, "POST", &format!("/api/kill/{key}"), None);
+        match status {
+            200 => assert!(succeeded, "instance left running after failed deploy"),
+            404 => assert!(
+                !succeeded && body.contains("no running instance"),
+                "bad response to killing the function: {body}"
+            ),
+            _ => panic!("failed to kill the function: {status} {body}"),
+        }
+        let (_, body) = request(&api, &token, "GET", &format!("/api/status/{key}"), None);
+        assert!(
+            body.contains(r#""running":false"#),
+            "instance left running: {body}"
+        );
+    }
+    println!("{deployed} deploys succeeded and {missing} found the alias missing");
+}
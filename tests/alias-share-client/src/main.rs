@@ -0,0 +1,78 @@
+//! Test client for `test-alias-share-fn`.
+//!
+//! Deploys a function through an alias, then deploys it through its version, which is rejected
+//! as the instance is already running and promoted onto the host of the version instead. Both
+//! hosts must then be served by the same instance, and killing it through either key stops it.
+//!
+//! Requires the platform address in `YFASS_API` (like `localhost:8080`, which is also the host
+//! functions are subdomains of) with subdomain routing, a token with permission `WRITE`,
+//! `EXECUTE` and `READ` in `YFASS_TOKEN`, and the key of an uploaded but not running function in
+//! `YFASS_KEY` (like `name@version`).
+
+use test_util::{Request, env, request};
+
+/// Alias set on the function.
+const ALIAS: &str = "alias-share-test";
+/// Count of requests sent to each host.
+const ROUNDS: usize = 10;
+
+fn main() {
+    let api = env("YFASS_API");
+    let token = env("YFASS_TOKEN");
+    let key = env("YFASS_KEY");
+    let (name, version) = key.split_once('@').expect("invalid function key");
+    let alias_key = format!("{name}@{ALIAS}");
+
+    let api_request =
+        |method: &str, path: &str, body: Option<&str>| request(&api, &token, method, path, body);
+
+    let (status, body) = api_request(
+        "PATCH",
+        &format!("/api/alias/{key}"),
+        Some(&format!(r#"{{"alias":"{ALIAS}"}}"#)),
+    );
+    assert_eq!(status, 200, "failed to set alias: {body}");
+
+    let (status, body) = api_request("POST", &format!("/api/deploy/{alias_key}"), None);
+    assert_eq!(status, 200, "failed to deploy through alias: {body}");
+    let (status, body) = api_request("POST", &format!("/api/deploy/{key}"), None);
+    assert_eq!(
+        status, 409,
+        "deploying the version of a running alias not rejected: {body}"
+    );
+    let (status, body) = api_request("POST", &format!("/api/promote-instance/{key}"), None);
+    assert_eq!(status, 200, "failed to promote the version: {body}");
+
+    let mut instance = None;
+    for _ in 0..ROUNDS {
+        for host in [
+            format!("{version}.{name}.{api}"),
+            format!("{ALIAS}.{name}.{api}"),
+        ] {
+            let response = Request::new("GET", "/", &host).send(&api);
+            assert_eq!(
+                response.status, 200,
+                "failed to reach the function at {host}: {}",
+                response.body
+            );
+            let instance = instance.get_or_insert_with(|| response.body.clone());
+            assert_eq!(
+                *instance, response.body,
+                "{host} served by another instance"
+            );
+        }
+    }
+
+    // a single instance is shared, so it's gone after killing it through either key
+    let (status, body) = api_request("POST", &format!("/api/kill/{alias_key}"), None);
+    assert_eq!(status, 200, "failed to kill through alias: {body}");
+    let (status, body) = api_request("POST", &format!("/api/kill/{key}"), None);
+    assert_eq!(status, 404, "another instance left running: {body}");
+
+    let (status, body) = api_request(
+        "PATCH",
+        &format!("/api/alias/{key}"),
+        Some(r#"{"alias":null}"#),
+    );
+    assert_eq!(status, 200, "failed to remove alias: {body}");
+}
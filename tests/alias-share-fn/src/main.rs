@@ -0,0 +1,35 @@
+//! Test function for telling instances apart by responding with an identifier of the process.
+
+use std::{
+    net::Ipv4Addr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{Router, routing::get};
+
+fn main() {
+    println!("starting alias share test server");
+    let port = std::env::var("YFASS_PORT")
+        .expect("missing YFASS_PORT env var")
+        .parse::<u16>()
+        .unwrap();
+
+    // process identifiers alone may be reused across instances
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let instance = format!("{}-{started}", std::process::id());
+    let router: Router<()> = Router::new().route("/", get(async move || instance));
+
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(async move {
+            let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+                .await
+                .unwrap();
+            axum::serve(listener, router).await.unwrap();
+        })
+}
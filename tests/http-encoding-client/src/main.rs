@@ -0,0 +1,52 @@
+//! Test client for `test-http-encoding-fn`.
+//!
+//! Checks that the proxy passes content encodings of the function through as is.
+
+use std::io::Read as _;
+
+use flate2::bufread::GzDecoder;
+use reqwest::header;
+
+/// Text of every response, before encoding.
+const TEXT: &str = "the quick brown fox jumps over the lazy dog";
+
+fn main() {
+    let host = std::env::var("YFASS_HOST").expect("missing YFASS_HOST env var");
+    let client = reqwest::blocking::Client::new();
+
+    // already encoded by the function, so must not be encoded again
+    let resp = client
+        .get(format!("http://{host}/gzip"))
+        .header(header::ACCEPT_ENCODING, "gzip")
+        .send()
+        .expect("request failed");
+    assert_eq!(resp.status(), reqwest::StatusCode::OK, "bad status code");
+    assert_eq!(
+        resp.headers().get(header::CONTENT_ENCODING),
+        Some(&header::HeaderValue::from_static("gzip")),
+        "content encoding not preserved"
+    );
+    let compressed = resp.bytes().expect("cannot read response body");
+    let mut decompressed = String::new();
+    GzDecoder::new(&compressed[..])
+        .read_to_string(&mut decompressed)
+        .expect("decompression failed, possibly encoded twice");
+    assert_eq!(decompressed, TEXT, "non-identical data");
+
+    // the client accepts no encoding, so must not be encoded
+    let resp = client
+        .get(format!("http://{host}/identity"))
+        .header(header::ACCEPT_ENCODING, "identity")
+        .send()
+        .expect("request failed");
+    assert_eq!(resp.status(), reqwest::StatusCode::OK, "bad status code");
+    assert!(
+        resp.headers().get(header::CONTENT_ENCODING).is_none(),
+        "unexpected content encoding"
+    );
+    assert_eq!(
+        resp.text().expect("cannot read response body"),
+        TEXT,
+        "non-identical data"
+    );
+}
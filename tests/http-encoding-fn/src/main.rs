@@ -0,0 +1,55 @@
+//! Test function for HTTP by responding with bodies of given content encodings.
+
+use std::{io::Read as _, net::Ipv4Addr};
+
+use axum::{
+    Router,
+    http::header,
+    response::{ErrorResponse, IntoResponse},
+    routing::get,
+};
+use flate2::read::GzEncoder;
+
+/// Text of every response, before encoding.
+const TEXT: &str = "the quick brown fox jumps over the lazy dog";
+
+fn main() {
+    println!("starting http encoding test server");
+    let port = std::env::var("YFASS_PORT")
+        .expect("missing YFASS_PORT env var")
+        .parse::<u16>()
+        .unwrap();
+
+    let router: Router<()> = Router::new()
+        .route("/gzip", get(gzip))
+        .route("/identity", get(identity));
+
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(async move {
+            let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+                .await
+                .unwrap();
+            axum::serve(listener, router).await.unwrap();
+        })
+}
+
+async fn gzip() -> Result<impl IntoResponse, ErrorResponse> {
+    let mut compressed = Vec::new();
+    let mut gz = GzEncoder::new(TEXT.as_bytes(), Default::default());
+    gz.read_to_end(&mut compressed).map_err(to_err)?;
+    println!("sending {} bytes with gzip encoding", compressed.len());
+    Ok(([(header::CONTENT_ENCODING, "gzip")], compressed))
+}
+
+async fn identity() -> &'static str {
+    println!("sending {} bytes without encoding", TEXT.len());
+    TEXT
+}
+
+#[inline]
+fn to_err<E: std::error::Error>(e: E) -> ErrorResponse {
+    ErrorResponse::from(e.to_string())
+}
@@ -0,0 +1,37 @@
+//! Test client for `test-http-head-fn`.
+//!
+//! Checks that the proxy drops bodies of responses to `HEAD` requests while preserving their
+//! headers.
+
+use test_util::{Request, env};
+
+/// Body of every response of the function, including ones to `HEAD` requests.
+const TEXT: &str = "the quick brown fox jumps over the lazy dog";
+
+fn main() {
+    let host = env("YFASS_HOST");
+    let addr = if host.contains(':') {
+        host.clone()
+    } else {
+        format!("{host}:80")
+    };
+
+    let response = Request::new("HEAD", "/", &host).send(&addr);
+    let (head, body) = (response.head, response.body);
+    assert!(head.starts_with("HTTP/1.1 200"), "bad status line: {head}");
+    assert!(
+        head.to_ascii_lowercase()
+            .contains(&format!("content-length: {}", TEXT.len())),
+        "content length not preserved: {head}"
+    );
+    assert!(
+        body.is_empty(),
+        "unexpected body of head response: {body:?}"
+    );
+
+    // the function is still usable afterwards
+    let response = Request::new("GET", "/", &host).send(&addr);
+    let (head, body) = (response.head, response.body);
+    assert!(head.starts_with("HTTP/1.1 200"), "bad status line: {head}");
+    assert_eq!(body, TEXT, "non-identical data");
+}
@@ -0,0 +1,67 @@
+//! Test function for HTTP by misbehaving on `HEAD` requests, responding with bodies anyway.
+//!
+//! Responses are written by hand, as HTTP libraries never send bodies of `HEAD` responses.
+
+use std::net::Ipv4Addr;
+
+use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+/// Body of every response, including ones to `HEAD` requests.
+const TEXT: &str = "the quick brown fox jumps over the lazy dog";
+
+fn main() {
+    println!("starting http head test server");
+    let port = std::env::var("YFASS_PORT")
+        .expect("missing YFASS_PORT env var")
+        .parse::<u16>()
+        .unwrap();
+
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(async move {
+            let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+                .await
+                .unwrap();
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    if let Err(err) = serve(stream).await {
+                        println!("connection error: {err}");
+                    }
+                });
+            }
+        })
+}
+
+/// Serves a single request, then closes the connection.
+async fn serve(mut stream: tokio::net::TcpStream) -> std::io::Result<()> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.ends_with(b"\r\n\r\n") {
+        let len = stream.read(&mut buf).await?;
+        if len == 0 {
+            return Ok(());
+        }
+        head.extend_from_slice(&buf[..len]);
+    }
+    let method = head.split(|b| *b == b' ').next().unwrap_or_default();
+    println!(
+        "sending {} bytes to {} request",
+        TEXT.len(),
+        String::from_utf8_lossy(method)
+    );
+
+    let response = format!(
+        "HTTP/1.1 200 OK\r\n\
+        content-type: text/plain\r\n\
+        content-length: {}\r\n\
+        connection: close\r\n\
+        \r\n\
+        {TEXT}",
+        TEXT.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
@@ -0,0 +1,82 @@
+//! Test client for length limits of names, versions and aliases of functions.
+//!
+//! Each of them is accepted at exactly the limit, and rejected with `400` at one over the limit
+//! or when empty.
+//!
+//! Requires the platform address in `YFASS_API` (like `localhost:8080`), a token with permission
+//! `WRITE` in `YFASS_TOKEN`, and the key of an uploaded but not running function in `YFASS_KEY`
+//! (like `name@version`). The limit is read from `YFASS_MAX_LENGTH` if the platform runs with
+//! `--max-function-name-length`, or defaults to 63.
+
+use test_util::{env, request};
+
+/// Default value of `--max-function-name-length`.
+const DEFAULT_MAX_LENGTH: usize = 63;
+
+fn main() {
+    let api = env("YFASS_API");
+    let token = env("YFASS_TOKEN");
+    let key = env("YFASS_KEY");
+    let (name, version) = key.split_once('@').expect("invalid function key");
+    let max = std::env::var("YFASS_MAX_LENGTH").map_or(DEFAULT_MAX_LENGTH, |max| {
+        max.parse().expect("invalid YFASS_MAX_LENGTH")
+    });
+
+    let at_limit = "a".repeat(max);
+    let over_limit = "a".repeat(max + 1);
+    let api_request =
+        |method: &str, path: &str, body: &str| request(&api, &token, method, path, Some(body));
+    let assert_rejected = |(status, body): (u16, String), what: &str| {
+        assert_eq!(status, 400, "{what} not rejected: {body}");
+        assert!(
+            body.contains("invalid key format"),
+            "{what} rejected for another reason: {body}"
+        );
+    };
+
+    // aliases
+    let set_alias = |alias: &str| {
+        api_request(
+            "PATCH",
+            &format!("/api/alias/{key}"),
+            &format!(r#"{{"alias":"{alias}"}}"#),
+        )
+    };
+    let (status, body) = set_alias(&at_limit);
+    assert_eq!(status, 200, "alias at the limit rejected: {body}");
+    assert_rejected(set_alias(&over_limit), "alias over the limit");
+    assert_rejected(set_alias(""), "empty alias");
+    let (status, body) = api_request("PATCH", &format!("/api/alias/{key}"), r#"{"alias":null}"#);
+    assert_eq!(status, 200, "failed to remove alias: {body}");
+
+    // names and versions, renamed back after accepted
+    let rename = |from: &str, to_name: &str, to_version: &str| {
+        api_request(
+            "PATCH",
+            &format!("/api/rename/{from}"),
+            &format!(r#"{{"to_name":"{to_name}","to_version":"{to_version}"}}"#),
+        )
+    };
+    for (to_name, to_version, what) in
+        [(&*at_limit, version, "name"), (name, &*at_limit, "version")]
+    {
+        let (status, body) = rename(&key, to_name, to_version);
+        assert_eq!(status, 200, "{what} at the limit rejected: {body}");
+        let (status, body) = rename(&format!("{to_name}@{to_version}"), name, version);
+        assert_eq!(status, 200, "failed to rename back: {body}");
+    }
+    assert_rejected(rename(&key, &over_limit, version), "name over the limit");
+    assert_rejected(rename(&key, "", version), "empty name");
+    assert_rejected(rename(&key, name, &over_limit), "version over the limit");
+    assert_rejected(rename(&key, name, ""), "empty version");
+
+    // rejected before receiving contents
+    assert_rejected(
+        api_request("POST", &format!("/api/upload/{over_limit}@{version}"), ""),
+        "uploading with name over the limit",
+    );
+    assert_rejected(
+        api_request("POST", &format!("/api/upload/{name}@{over_limit}"), ""),
+        "uploading with version over the limit",
+    );
+}
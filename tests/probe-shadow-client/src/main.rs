@@ -0,0 +1,37 @@
+//! Test client for `test-probe-shadow-fn`.
+//!
+//! Checks that probes of the platform only answer requests to the platform host, while the
+//! function keeps serving its own probes at the same paths.
+//!
+//! Requires the platform address in `YFASS_API` (like `localhost:8080`) and the host of the
+//! running function in `YFASS_HOST` (like `a0.test.localhost:8080`) with subdomain routing.
+//! Requests to the function are sent to the platform address with the function host, so no DNS
+//! is involved.
+
+use test_util::{Request, env};
+
+fn main() {
+    let api = env("YFASS_API");
+    let host = env("YFASS_HOST");
+
+    for (path, text) in [
+        ("/healthz", "function healthz"),
+        ("/readyz", "function readyz"),
+    ] {
+        let response = Request::new("GET", path, &host).send(&api);
+        let (head, body) = (response.head, response.body);
+        assert!(head.starts_with("HTTP/1.1 200"), "bad status line: {head}");
+        assert_eq!(
+            body, text,
+            "probe of function {path} answered by the platform"
+        );
+
+        let response = Request::new("GET", path, &api).send(&api);
+        let (head, body) = (response.head, response.body);
+        assert!(head.starts_with("HTTP/1.1 200"), "bad status line: {head}");
+        assert!(
+            body.is_empty(),
+            "probe of platform {path} forwarded: {body:?}"
+        );
+    }
+}
@@ -0,0 +1,28 @@
+//! Test function for HTTP by serving its own probes at the same paths as the platform.
+
+use std::net::Ipv4Addr;
+
+use axum::{Router, routing::get};
+
+fn main() {
+    println!("starting probe shadow test server");
+    let port = std::env::var("YFASS_PORT")
+        .expect("missing YFASS_PORT env var")
+        .parse::<u16>()
+        .unwrap();
+
+    let router: Router<()> = Router::new()
+        .route("/healthz", get(async || "function healthz"))
+        .route("/readyz", get(async || "function readyz"));
+
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(async move {
+            let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+                .await
+                .unwrap();
+            axum::serve(listener, router).await.unwrap();
+        })
+}
@@ -0,0 +1,51 @@
+//! Test client for `test-sse-fn`.
+//!
+//! Checks that the proxy delivers Server-Sent Events as they're emitted, instead of buffering the
+//! stream until it ends.
+
+use std::{
+    io::{BufRead as _, BufReader},
+    time::{Duration, Instant},
+};
+
+use reqwest::header;
+
+/// Count of events emitted by the function.
+const EVENTS: u32 = 5;
+/// Interval between events emitted by the function.
+const INTERVAL: Duration = Duration::from_secs(1);
+
+fn main() {
+    let host = std::env::var("YFASS_HOST").expect("missing YFASS_HOST env var");
+    let started = Instant::now();
+    let resp = reqwest::blocking::Client::new()
+        .get(format!("http://{host}/events"))
+        .header(header::ACCEPT, "text/event-stream")
+        .send()
+        .expect("request failed");
+    assert_eq!(resp.status(), reqwest::StatusCode::OK, "bad status code");
+    assert!(
+        resp.headers()
+            .get(header::CONTENT_TYPE)
+            .is_some_and(|ty| ty.as_bytes().starts_with(b"text/event-stream")),
+        "bad content type"
+    );
+
+    let mut received = 0;
+    for line in BufReader::new(resp).lines() {
+        let line = line.expect("failed to read event");
+        let Some(data) = line.strip_prefix("data: ") else {
+            continue;
+        };
+        let elapsed = started.elapsed();
+        println!("received event {data} after {elapsed:?}");
+        assert_eq!(data, received.to_string(), "events out of order");
+        // every event arrives right after emitted, with a generous margin
+        assert!(
+            elapsed < INTERVAL * received + INTERVAL / 2,
+            "event {data} delayed, the stream is likely buffered"
+        );
+        received += 1;
+    }
+    assert_eq!(received, EVENTS, "missing events");
+}
@@ -0,0 +1,50 @@
+//! Test function for Server-Sent Events by emitting numbered events periodically.
+
+use std::{convert::Infallible, net::Ipv4Addr, time::Duration};
+
+use axum::{
+    Router,
+    response::sse::{Event, Sse},
+    routing::get,
+};
+use futures_util::Stream;
+
+/// Count of events emitted per stream.
+const EVENTS: u32 = 5;
+/// Interval between events.
+const INTERVAL: Duration = Duration::from_secs(1);
+
+fn main() {
+    println!("starting sse test server");
+    let port = std::env::var("YFASS_PORT")
+        .expect("missing YFASS_PORT env var")
+        .parse::<u16>()
+        .unwrap();
+
+    let router: Router<()> = Router::new().route("/events", get(events));
+
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(async move {
+            let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+                .await
+                .unwrap();
+            axum::serve(listener, router).await.unwrap();
+        })
+}
+
+async fn events() -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    println!("streaming {EVENTS} events");
+    Sse::new(futures_util::stream::unfold(0, |i| async move {
+        if i == EVENTS {
+            return None;
+        }
+        // the first event is sent immediately
+        if i > 0 {
+            tokio::time::sleep(INTERVAL).await;
+        }
+        Some((Ok(Event::default().data(i.to_string())), i + 1))
+    }))
+}
@@ -0,0 +1,140 @@
+//! Helpers shared by test clients.
+//!
+//! Requests are written by hand over plain TCP, so responses of the platform are observed exactly
+//! as sent without any client library interpreting them.
+
+use std::{
+    io::{Read as _, Write as _},
+    net::TcpStream,
+};
+
+/// Reads a required environment variable.
+///
+/// # Panics
+///
+/// Panics if the variable is missing.
+pub fn env(name: &str) -> String {
+    std::env::var(name).unwrap_or_else(|_| panic!("missing {name} env var"))
+}
+
+/// Response read until the connection is closed.
+#[derive(Debug)]
+pub struct Response {
+    /// Status line and headers.
+    pub head: String,
+    /// Status code.
+    pub status: u16,
+    /// Everything after the head.
+    pub body: String,
+}
+
+/// Request written by hand.
+#[derive(Debug, Clone, Copy)]
+pub struct Request<'a> {
+    method: &'a str,
+    path: &'a str,
+    host: &'a str,
+    token: Option<&'a str>,
+    content_type: Option<&'a str>,
+    body: &'a [u8],
+}
+
+impl<'a> Request<'a> {
+    /// Creates a request without body to given host.
+    pub fn new(method: &'a str, path: &'a str, host: &'a str) -> Self {
+        Self {
+            method,
+            path,
+            host,
+            token: None,
+            content_type: None,
+            body: &[],
+        }
+    }
+
+    /// Authenticates the request with given bearer token.
+    pub fn token(self, token: &'a str) -> Self {
+        Self {
+            token: Some(token),
+            ..self
+        }
+    }
+
+    /// Sets a JSON body of the request.
+    pub fn json(self, body: &'a str) -> Self {
+        self.body("application/json", body.as_bytes())
+    }
+
+    /// Sets a body of the request with given content type.
+    pub fn body(self, content_type: &'a str, body: &'a [u8]) -> Self {
+        Self {
+            content_type: Some(content_type),
+            body,
+            ..self
+        }
+    }
+
+    /// Sends the request to given address, reading the response until the connection is closed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if failed to send the request or the response is malformed.
+    pub fn send(&self, addr: &str) -> Response {
+        let mut stream = TcpStream::connect(addr).expect("failed to connect");
+        let mut head = format!(
+            "{} {} HTTP/1.1\r\nhost: {}\r\nconnection: close\r\n",
+            self.method, self.path, self.host
+        );
+        if let Some(token) = self.token {
+            head += &format!("authorization: Bearer {token}\r\n");
+        }
+        if let Some(content_type) = self.content_type {
+            head += &format!(
+                "content-type: {content_type}\r\ncontent-length: {}\r\n",
+                self.body.len()
+            );
+        }
+        head += "\r\n";
+        stream
+            .write_all(head.as_bytes())
+            .and_then(|()| stream.write_all(self.body))
+            .expect("failed to send request");
+
+        let mut response = Vec::new();
+        stream
+            .read_to_end(&mut response)
+            .expect("failed to read response");
+        let response = String::from_utf8_lossy(&response);
+        let (head, body) = response
+            .split_once("\r\n\r\n")
+            .expect("incomplete response head");
+        let status = head
+            .strip_prefix("HTTP/1.1 ")
+            .and_then(|rest| rest.get(..3))
+            .and_then(|code| code.parse().ok())
+            .expect("malformed status line");
+        Response {
+            head: head.to_owned(),
+            status,
+            body: body.to_owned(),
+        }
+    }
+}
+
+/// Sends a management request with an optional JSON body to the platform at `api`, returning the
+/// status code and the body of the response.
+///
+/// # Panics
+///
+/// Panics if failed to send the request or the response is malformed.
+pub fn request(
+    api: &str,
+    token: &str,
+    method: &str,
+    path: &str,
+    body: Option<&str>,
+) -> (u16, String) {
+    let request = Request::new(method, path, api).token(token);
+    let response = body.map_or(request, |body| request.json(body)).send(api);
+    (response.status, response.body)
+}
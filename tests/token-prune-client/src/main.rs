@@ -0,0 +1,60 @@
+//! Test client for pruning expired tokens.
+//!
+//! Requests a token expiring immediately, then checks that it's rejected right away, and removed
+//! from the stored users once `--token-prune-interval-secs` elapsed while still being rejected.
+//!
+//! Requires the platform address in `YFASS_API` (like `localhost:8080`), a token with permission
+//! `ROOT` in `YFASS_TOKEN`, the name of an existing user in `YFASS_USER`, the path of
+//! `users.json` in the data directory of the platform in `YFASS_USERS_FILE`, and the configured
+//! prune interval in seconds in `YFASS_TOKEN_PRUNE_INTERVAL`.
+
+use std::time::Duration;
+
+use test_util::{env, request};
+
+fn main() {
+    let api = env("YFASS_API");
+    let token = env("YFASS_TOKEN");
+    let user = env("YFASS_USER");
+    let users_file = env("YFASS_USERS_FILE");
+    let interval: u64 = env("YFASS_TOKEN_PRUNE_INTERVAL")
+        .parse()
+        .expect("invalid prune interval");
+
+    let (status, expired) = request(
+        &api,
+        &token,
+        "POST",
+        "/api/user/request-token",
+        Some(&format!(
+            r#"{{"user":"{user}","duration":0,"label":"token-prune"}}"#
+        )),
+    );
+    assert_eq!(status, 200, "failed to request token: {expired}");
+    let stored = || {
+        let (status, body) = request(&api, &token, "POST", "/api/admin/flush", None);
+        assert_eq!(status, 200, "failed to flush data: {body}");
+        std::fs::read_to_string(&users_file)
+            .expect("failed to read users file")
+            .contains(&expired)
+    };
+    let rejected = || {
+        let (status, body) = request(
+            &api,
+            &expired,
+            "GET",
+            &format!("/api/user/get/{user}"),
+            None,
+        );
+        assert_eq!(status, 403, "expired token not rejected: {body}");
+    };
+
+    assert!(stored(), "token not stored before being pruned");
+    rejected();
+
+    // waits for the longest jittered interval plus some slack
+    std::thread::sleep(Duration::from_secs(interval + interval / 6 + 2));
+    assert!(!stored(), "expired token not pruned");
+    rejected();
+    println!("expired token rejected and pruned");
+}
@@ -0,0 +1,88 @@
+//! Test client for detecting archive formats of uploads from magic bytes.
+//!
+//! Uploads bodies as `application/octet-stream` and checks that plain tarballs are accepted,
+//! gzipped tarballs are detected, and zstd-compressed or garbage bodies are rejected with `400`
+//! without leaving the function behind.
+//!
+//! Requires the platform address in `YFASS_API` (like `localhost:8080`) and a token with
+//! permission `WRITE`, `READ` and `REMOVE` in `YFASS_TOKEN`.
+
+use test_util::{Request, env, request};
+
+/// Key of the function being uploaded.
+const KEY: &str = "upload-magic-test@0";
+/// Size of a block of a tarball.
+const BLOCK: usize = 512;
+
+fn main() {
+    let api = env("YFASS_API");
+    let token = env("YFASS_TOKEN");
+    let upload = |body: &[u8]| {
+        let response = Request::new("POST", &format!("/api/upload/{KEY}"), &api)
+            .token(&token)
+            .body("application/octet-stream", body)
+            .send(&api);
+        (response.status, response.body)
+    };
+
+    let (status, body) = upload(&tarball("main.sh", b"#!/bin/sh\necho ok\n"));
+    assert_eq!(status, 200, "plain tarball rejected: {body}");
+    let (status, body) = request(&api, &token, "DELETE", &format!("/api/remove/{KEY}"), None);
+    assert_eq!(status, 200, "failed to remove the function: {body}");
+
+    // gzip header of an empty member, decoding of which is gated behind an unstable feature
+    let gzip = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x03, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+    let (status, body) = upload(&gzip);
+    assert!(
+        status == 200 || (status == 403 && body.contains("upload-tar-gz")),
+        "gzipped tarball not detected: {status} {body}"
+    );
+    if status == 200 {
+        request(&api, &token, "DELETE", &format!("/api/remove/{KEY}"), None);
+    }
+
+    // zstd frame magic followed by a frame header and garbage
+    let mut zstd = vec![0x28, 0xb5, 0x2f, 0xfd, 0x04, 0x58];
+    zstd.resize(BLOCK * 2, 0xaa);
+    let garbage: Vec<u8> = (0..BLOCK * 2).map(|i| (i * 31 % 251) as u8).collect();
+    for (format, body) in [("zstd", zstd), ("garbage", garbage)] {
+        let (status, response) = upload(&body);
+        assert_eq!(status, 400, "{format} body not rejected: {response}");
+        assert!(
+            response.contains("unable to detect archive type"),
+            "bad response to uploading {format} body: {response}"
+        );
+        let (status, response) = request(&api, &token, "GET", &format!("/api/get/{KEY}"), None);
+        assert_eq!(
+            status, 404,
+            "function left behind after rejecting {format} body: {response}"
+        );
+    }
+    println!("archive formats detected from magic bytes");
+}
+
+/// Builds a ustar tarball containing a single file.
+fn tarball(name: &str, contents: &[u8]) -> Vec<u8> {
+    let mut header = [0u8; BLOCK];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    header[100..108].copy_from_slice(b"0000755\0");
+    header[108..116].copy_from_slice(b"0000000\0");
+    header[116..124].copy_from_slice(b"0000000\0");
+    header[124..136].copy_from_slice(format!("{:011o}\0", contents.len()).as_bytes());
+    header[136..148].copy_from_slice(b"00000000000\0");
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    // checksum is computed with its own field filled with spaces
+    header[148..156].fill(b' ');
+    let checksum: u32 = header.iter().map(|&b| u32::from(b)).sum();
+    header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+
+    let mut tarball = header.to_vec();
+    tarball.extend_from_slice(contents);
+    tarball.resize(tarball.len().next_multiple_of(BLOCK) + BLOCK * 2, 0);
+    tarball
+}
@@ -0,0 +1,88 @@
+//! Test client for stalled uploads.
+//!
+//! Uploads a function declaring a longer body than actually sent, then checks that the platform
+//! aborts the upload with `408` after `--upload-timeout` and rolls the function back.
+//!
+//! Requires the platform address in `YFASS_API` (like `localhost:8080`), a token with permission
+//! `WRITE` and `READ` in `YFASS_TOKEN`, and the configured upload timeout in seconds in
+//! `YFASS_UPLOAD_TIMEOUT`.
+
+use std::{
+    io::{Read as _, Write as _},
+    net::TcpStream,
+    time::{Duration, Instant},
+};
+
+/// Key of the function being uploaded.
+const KEY: &str = "upload-stall-test@0";
+/// Declared length of the body, far beyond the bytes actually sent.
+const DECLARED_LEN: usize = 64 * 1024;
+
+fn main() {
+    let api = std::env::var("YFASS_API").expect("missing YFASS_API env var");
+    let token = std::env::var("YFASS_TOKEN").expect("missing YFASS_TOKEN env var");
+    let timeout = Duration::from_secs(
+        std::env::var("YFASS_UPLOAD_TIMEOUT")
+            .expect("missing YFASS_UPLOAD_TIMEOUT env var")
+            .parse()
+            .expect("invalid upload timeout"),
+    );
+
+    let mut stream = TcpStream::connect(&api).expect("failed to connect");
+    // generous enough for the platform to respond, but never hanging forever
+    stream
+        .set_read_timeout(Some(timeout * 2 + Duration::from_secs(5)))
+        .unwrap();
+    write!(
+        stream,
+        "POST /api/upload/{KEY} HTTP/1.1\r\n\
+        host: {api}\r\n\
+        authorization: Bearer {token}\r\n\
+        content-type: application/x-tar\r\n\
+        content-length: {DECLARED_LEN}\r\n\
+        connection: close\r\n\
+        \r\n"
+    )
+    .expect("failed to send request head");
+    // less than a single tar header, then stall
+    stream
+        .write_all(&[0; 100])
+        .expect("failed to send partial body");
+
+    let started = Instant::now();
+    let mut response = Vec::new();
+    stream
+        .read_to_end(&mut response)
+        .expect("failed to read response, the upload may be never aborted");
+    let response = String::from_utf8_lossy(&response);
+    println!(
+        "responded after {:?}: {}",
+        started.elapsed(),
+        response.lines().next().unwrap_or_default()
+    );
+    assert!(
+        response.starts_with("HTTP/1.1 408"),
+        "bad response: {response}"
+    );
+
+    // the partially uploaded function is rolled back
+    let mut stream = TcpStream::connect(&api).expect("failed to connect");
+    write!(
+        stream,
+        "GET /api/get/{KEY} HTTP/1.1\r\n\
+        host: {api}\r\n\
+        authorization: Bearer {token}\r\n\
+        connection: close\r\n\
+        \r\n"
+    )
+    .expect("failed to send request");
+    let mut response = Vec::new();
+    stream
+        .read_to_end(&mut response)
+        .expect("failed to read response");
+    let response = String::from_utf8_lossy(&response);
+    assert!(
+        response.starts_with("HTTP/1.1 404"),
+        "function not rolled back: {response}"
+    );
+}
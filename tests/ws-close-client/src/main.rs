@@ -0,0 +1,58 @@
+//! Test client for `test-ws-close-fn`.
+
+use tungstenite::{Message, protocol::CloseFrame};
+
+/// Close codes the function closes with, and the codes the client should receive for them, with
+/// `None` for closing without a status code.
+///
+/// Codes reserved for local use (1005, 1006 and 1015) and codes out of the valid ranges (999 and
+/// 5000) must never appear on the wire, so the platform rejects them as a protocol violation of
+/// the function and relays 1002 instead.
+const CODES: &[(Option<u16>, Option<u16>)] = &[
+    (Some(1000), Some(1000)),
+    (Some(1001), Some(1001)),
+    (Some(1008), Some(1008)),
+    (Some(1011), Some(1011)),
+    (Some(3000), Some(3000)),
+    (Some(4999), Some(4999)),
+    (None, None),
+    (Some(1005), Some(1002)),
+    (Some(1006), Some(1002)),
+    (Some(1015), Some(1002)),
+    (Some(999), Some(1002)),
+    (Some(5000), Some(1002)),
+];
+
+fn main() {
+    let host = std::env::var("YFASS_HOST").expect("missing YFASS_HOST env var");
+
+    for &(code, expected) in CODES {
+        let (mut ws, _) = tungstenite::connect(format!("ws://{}/", host)).expect("connect failed");
+        let request = code.map_or_else(|| "none".to_owned(), |code| code.to_string());
+        ws.send(Message::Text(request.into()))
+            .expect("write close code failed");
+
+        let frame = loop {
+            match ws.read().expect("cannot read") {
+                Message::Close(frame) => break frame,
+                _ => continue,
+            }
+        };
+        match (expected, frame) {
+            (Some(expected), Some(CloseFrame { code: received, .. })) => {
+                assert!(received.is_allowed(), "reserved close code {received}");
+                assert_eq!(
+                    u16::from(received),
+                    expected,
+                    "mismatched close code for {code:?}"
+                );
+            }
+            (None, None) => {}
+            (expected, frame) => {
+                panic!("expected close code {expected:?} for {code:?} but received {frame:?}")
+            }
+        }
+        // completes the closing handshake
+        drop(ws.flush());
+    }
+}
@@ -0,0 +1,51 @@
+//! Test function for websocket by closing connections with the code received from client.
+
+use std::net::Ipv4Addr;
+
+use axum::{
+    Router,
+    extract::{
+        WebSocketUpgrade,
+        ws::{CloseFrame, Message},
+    },
+    response::Response,
+    routing::any,
+};
+
+fn main() {
+    println!("starting websocket close test server");
+    let port = std::env::var("YFASS_PORT")
+        .expect("missing YFASS_PORT env var")
+        .parse::<u16>()
+        .unwrap();
+
+    let router: Router<()> = Router::new().route("/", any(accept_ws_request));
+
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(async move {
+            let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+                .await
+                .unwrap();
+            axum::serve(listener, router).await.unwrap();
+        })
+}
+
+async fn accept_ws_request(upgrade: WebSocketUpgrade) -> Response {
+    upgrade.on_upgrade(|mut ws| async move {
+        // the requested close code, or `none` for closing without a status code
+        let Some(Ok(Message::Text(code))) = ws.recv().await else {
+            return;
+        };
+        let frame = code.as_str().parse::<u16>().ok().map(|code| CloseFrame {
+            code,
+            reason: "bye".into(),
+        });
+        println!("closing with frame {frame:?}");
+        ws.send(Message::Close(frame)).await.unwrap();
+        // wait for the closing handshake
+        while ws.recv().await.is_some() {}
+    })
+}
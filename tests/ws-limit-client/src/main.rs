@@ -0,0 +1,68 @@
+//! Test client for `test-ws-limit-fn`.
+//!
+//! The server should be started with `--max-ws-message-bytes 1048576`.
+
+use tungstenite::{
+    Message,
+    protocol::{CloseFrame, frame::coding::CloseCode},
+    stream::MaybeTlsStream,
+};
+
+/// Maximum size of websocket messages relayed by the server.
+const LIMIT: usize = 1 << 20;
+
+type WebSocket = tungstenite::WebSocket<MaybeTlsStream<std::net::TcpStream>>;
+
+fn main() {
+    let host = std::env::var("YFASS_HOST").expect("missing YFASS_HOST env var");
+    let connect = || {
+        tungstenite::connect(format!("ws://{}/", host))
+            .expect("connect failed")
+            .0
+    };
+
+    // messages within the limit are relayed in both directions
+    let mut ws = connect();
+    ws.send(Message::Binary(vec![0; LIMIT].into()))
+        .expect("write message failed");
+    match ws.read().expect("cannot read") {
+        Message::Text(len) => assert_eq!(len.as_str(), LIMIT.to_string(), "mismatched size"),
+        msg => panic!("expected size of the message but received {msg:?}"),
+    }
+    ws.send(Message::Text(LIMIT.to_string().into()))
+        .expect("write size failed");
+    match ws.read().expect("cannot read") {
+        Message::Binary(data) => assert_eq!(data.len(), LIMIT, "mismatched size"),
+        msg => panic!("expected message of size {LIMIT} but received {msg:?}"),
+    }
+    drop(ws.close(None));
+
+    // oversized message from the client
+    let mut ws = connect();
+    ws.send(Message::Binary(vec![0; LIMIT + 1].into()))
+        .expect("write message failed");
+    assert_closed_too_big(&mut ws);
+
+    // oversized message from the function
+    let mut ws = connect();
+    ws.send(Message::Text((LIMIT + 1).to_string().into()))
+        .expect("write size failed");
+    assert_closed_too_big(&mut ws);
+}
+
+fn assert_closed_too_big(ws: &mut WebSocket) {
+    let frame = loop {
+        match ws.read().expect("cannot read") {
+            Message::Close(frame) => break frame,
+            _ => continue,
+        }
+    };
+    match frame {
+        Some(CloseFrame { code, .. }) => {
+            assert_eq!(code, CloseCode::Size, "mismatched close code")
+        }
+        None => panic!("expected close code 1009 but received none"),
+    }
+    // completes the closing handshake
+    drop(ws.flush());
+}
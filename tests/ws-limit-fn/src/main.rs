@@ -0,0 +1,61 @@
+//! Test function for websocket by sending messages of the sizes received from client.
+
+use std::net::Ipv4Addr;
+
+use axum::{
+    Router,
+    extract::{WebSocketUpgrade, ws::Message},
+    response::Response,
+    routing::any,
+};
+
+fn main() {
+    println!("starting websocket limit test server");
+    let port = std::env::var("YFASS_PORT")
+        .expect("missing YFASS_PORT env var")
+        .parse::<u16>()
+        .unwrap();
+
+    let router: Router<()> = Router::new().route("/", any(accept_ws_request));
+
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(async move {
+            let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+                .await
+                .unwrap();
+            axum::serve(listener, router).await.unwrap();
+        })
+}
+
+async fn accept_ws_request(upgrade: WebSocketUpgrade) -> Response {
+    upgrade.on_upgrade(|mut ws| async move {
+        while let Some(Ok(msg)) = ws.recv().await {
+            let reply = match msg {
+                // the requested size of the message to be sent
+                Message::Text(len) => {
+                    let Ok(len) = len.as_str().parse::<usize>() else {
+                        continue;
+                    };
+                    println!("sending {len} bytes");
+                    Message::Binary(vec![0; len].into())
+                }
+                // reports the size of the received message
+                Message::Binary(data) => {
+                    println!("received {} bytes", data.len());
+                    Message::Text(data.len().to_string().into())
+                }
+                Message::Close(frame) => {
+                    println!("closed with frame {frame:?}");
+                    break;
+                }
+                _ => continue,
+            };
+            if ws.send(reply).await.is_err() {
+                break;
+            }
+        }
+    })
+}
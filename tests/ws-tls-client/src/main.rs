@@ -0,0 +1,33 @@
+//! Test client for `test-ws-tls-fn`.
+//!
+//! Connects to the platform through plain websocket, which is relayed to the function over TLS
+//! and echoed back. Requires the platform built with feature `upstream-tls` and trusting the
+//! certificate of the function.
+
+use tungstenite::Message;
+
+fn main() {
+    let host = std::env::var("YFASS_HOST").expect("missing YFASS_HOST env var");
+    let (mut ws, _) = tungstenite::connect(format!("ws://{}/", host)).expect("connect failed");
+
+    let messages = [
+        Message::Text("hello over tls".into()),
+        Message::Binary(vec![0u8, 1, 2, 3, 255].into()),
+        Message::Binary(vec![42u8; 256 * 1024].into()),
+    ];
+    for message in messages {
+        ws.send(message.clone()).expect("write failed");
+        let echoed = loop {
+            match ws.read().expect("cannot read") {
+                msg @ (Message::Text(_) | Message::Binary(_)) => break msg,
+                Message::Close(frame) => panic!("closed before echoing: {frame:?}"),
+                _ => continue,
+            }
+        };
+        assert_eq!(echoed, message, "mismatched echo");
+    }
+
+    ws.close(None).expect("close failed");
+    // completes the closing handshake
+    while ws.read().is_ok() {}
+}
@@ -0,0 +1,66 @@
+//! Test function for websocket over TLS by echoing messages received from client.
+//!
+//! Serves `wss` only with the certificate chain and private key in `cert.pem` and `key.pem` of
+//! the working directory, for functions with `upstream_scheme` set to `https`.
+
+use std::{net::Ipv4Addr, sync::Arc};
+
+use futures_util::{SinkExt as _, StreamExt as _};
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{
+        ServerConfig,
+        pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject as _},
+    },
+};
+use tokio_tungstenite::tungstenite::Message;
+
+fn main() {
+    println!("starting websocket tls test server");
+    let port = std::env::var("YFASS_PORT")
+        .expect("missing YFASS_PORT env var")
+        .parse::<u16>()
+        .unwrap();
+
+    let certs = CertificateDer::pem_file_iter("cert.pem")
+        .expect("failed to open cert.pem")
+        .collect::<Result<Vec<_>, _>>()
+        .expect("failed to read cert.pem");
+    let key = PrivateKeyDer::from_pem_file("key.pem").expect("failed to read key.pem");
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .expect("invalid certificate or key");
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(async move {
+            let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+                .await
+                .unwrap();
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let stream = match acceptor.accept(stream).await {
+                        Ok(stream) => stream,
+                        // plain connections never get through
+                        Err(err) => return println!("tls handshake failed: {err}"),
+                    };
+                    let Ok(mut ws) = tokio_tungstenite::accept_async(stream).await else {
+                        return;
+                    };
+                    while let Some(Ok(msg)) = ws.next().await {
+                        if let Message::Text(_) | Message::Binary(_) = msg
+                            && ws.send(msg).await.is_err()
+                        {
+                            return;
+                        }
+                    }
+                });
+            }
+        })
+}